@@ -0,0 +1,229 @@
+// Copyright 2019 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+
+// the cell holds no value
+const EMPTY: u8 = 0;
+// the cell is being accessed by a thread
+const BUSY: u8 = 1;
+// the cell holds a value
+const FULL: u8 = 2;
+
+/// An `AtomicPrimitive` which may be unset. Unlike `AtomicOption`, the value
+/// and whether it is present are always read and modified together, so a
+/// thread never observes a value which has been taken or one which is only
+/// partially set. Each operation briefly holds the cell exclusively, spinning
+/// while another thread holds it.
+pub struct AtomicCell<T>
+where
+    T: AtomicPrimitive + Default,
+{
+    inner: T,
+    state: AtomicU8,
+}
+
+// returns the cell to the given state when dropped, so that a panic while the
+// cell is held does not leave it busy forever
+struct Guard<'a> {
+    state: &'a AtomicU8,
+    release: u8,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.state.store(self.release, Ordering::Release);
+    }
+}
+
+impl<T> AtomicCell<T>
+where
+    T: AtomicPrimitive + Default,
+{
+    /// Create a new `AtomicCell` which holds no value
+    pub fn new() -> Self {
+        Self {
+            inner: T::default(),
+            state: AtomicU8::new(EMPTY),
+        }
+    }
+
+    /// Create a new `AtomicCell` which holds the value
+    pub fn with_value(value: <T as AtomicPrimitive>::Primitive) -> Self {
+        Self {
+            inner: T::new(value),
+            state: AtomicU8::new(FULL),
+        }
+    }
+
+    // takes exclusive access to the cell, returning a guard which will
+    // release it as full, and whether the cell was full
+    fn acquire(&self) -> (Guard<'_>, bool) {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state != BUSY
+                && self
+                    .state
+                    .compare_exchange_weak(state, BUSY, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                let guard = Guard {
+                    state: &self.state,
+                    release: state,
+                };
+                return (guard, state == FULL);
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Returns true if the cell holds a value
+    pub fn is_set(&self) -> bool {
+        self.state.load(Ordering::Acquire) == FULL
+    }
+
+    /// Returns the value, if there is one
+    pub fn get(&self) -> Option<<T as AtomicPrimitive>::Primitive> {
+        let (_guard, full) = self.acquire();
+        if full {
+            Some(self.inner.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+
+    /// Stores the value, returning the previous value if there was one
+    pub fn set(
+        &self,
+        value: <T as AtomicPrimitive>::Primitive,
+    ) -> Option<<T as AtomicPrimitive>::Primitive> {
+        let (mut guard, full) = self.acquire();
+        let previous = self.inner.swap(value, Ordering::Relaxed);
+        guard.release = FULL;
+        if full {
+            Some(previous)
+        } else {
+            None
+        }
+    }
+
+    /// Removes and returns the value, leaving the cell empty
+    pub fn take(&self) -> Option<<T as AtomicPrimitive>::Primitive> {
+        let (mut guard, full) = self.acquire();
+        guard.release = EMPTY;
+        if full {
+            Some(self.inner.load(Ordering::Relaxed))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value, first setting it to the result of `f` if the cell is
+    /// empty. When called concurrently, `f` is called by only one thread and
+    /// all threads return the same value. If `f` panics, the cell is left
+    /// empty.
+    pub fn get_or_init<F>(&self, f: F) -> <T as AtomicPrimitive>::Primitive
+    where
+        F: FnOnce() -> <T as AtomicPrimitive>::Primitive,
+    {
+        let (mut guard, full) = self.acquire();
+        if !full {
+            self.inner.store(f(), Ordering::Relaxed);
+            guard.release = FULL;
+        }
+        self.inner.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Default for AtomicCell<T>
+where
+    T: AtomicPrimitive + Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> std::fmt::Debug for AtomicCell<T>
+where
+    T: AtomicPrimitive + Default,
+    <T as AtomicPrimitive>::Primitive: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AtomicCell({:?})", self.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn set_take() {
+        let cell = AtomicCell::<AtomicU64>::new();
+        assert!(!cell.is_set());
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.take(), None);
+        // zero is a value, distinct from being unset
+        assert_eq!(cell.set(0), None);
+        assert!(cell.is_set());
+        assert_eq!(cell.get(), Some(0));
+        assert_eq!(cell.set(42), Some(0));
+        assert_eq!(cell.take(), Some(42));
+        assert!(!cell.is_set());
+        assert_eq!(cell.take(), None);
+
+        let cell = AtomicCell::<AtomicU64>::with_value(7);
+        assert_eq!(cell.get(), Some(7));
+    }
+
+    #[test]
+    fn get_or_init() {
+        let cell = AtomicCell::<AtomicU64>::new();
+        assert_eq!(cell.get_or_init(|| 5), 5);
+        assert_eq!(cell.get_or_init(|| 10), 5);
+        cell.take();
+        assert_eq!(cell.get_or_init(|| 10), 10);
+    }
+
+    #[test]
+    fn get_or_init_panic() {
+        let cell = Arc::new(AtomicCell::<AtomicU64>::new());
+        let clone = cell.clone();
+        let result = thread::spawn(move || clone.get_or_init(|| panic!("init failed"))).join();
+        assert!(result.is_err());
+        assert!(!cell.is_set());
+        assert_eq!(cell.get_or_init(|| 1), 1);
+    }
+
+    #[test]
+    fn get_or_init_concurrent() {
+        let threads = 8;
+        let cell = Arc::new(AtomicCell::<AtomicU64>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(threads));
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let cell = cell.clone();
+                let calls = calls.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    cell.get_or_init(|| {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        id as u64 + 100
+                    })
+                })
+            })
+            .collect();
+        let values: Vec<u64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let value = cell.get().unwrap();
+        assert!(value >= 100 && value < 100 + threads as u64);
+        assert!(values.iter().all(|v| *v == value));
+    }
+}
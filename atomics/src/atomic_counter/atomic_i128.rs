@@ -0,0 +1,52 @@
+// Copyright 2019 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::{AtomicCounter, AtomicI128, Ordering};
+
+impl AtomicCounter for AtomicI128 {
+    fn fetch_and(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous & value;
+        previous
+    }
+
+    fn fetch_nand(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = !(previous & value);
+        previous
+    }
+
+    fn fetch_or(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous | value;
+        previous
+    }
+
+    fn fetch_xor(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous ^ value;
+        previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::AtomicPrimitive;
+
+    #[test]
+    fn bitwise() {
+        let atomic = AtomicI128::new(0b1100);
+        assert_eq!(atomic.fetch_and(0b1010, Ordering::SeqCst), 0b1100);
+        assert_eq!(atomic.fetch_or(0b0001, Ordering::SeqCst), 0b1000);
+        assert_eq!(atomic.fetch_xor(0b1111, Ordering::SeqCst), 0b1001);
+        assert_eq!(atomic.fetch_nand(0b0110, Ordering::SeqCst), 0b0110);
+        assert_eq!(atomic.load(Ordering::SeqCst), !0b0110);
+    }
+}
@@ -5,14 +5,6 @@
 use crate::{AtomicCounter, AtomicI32, Ordering};
 
 impl AtomicCounter for AtomicI32 {
-    fn fetch_add(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive {
-        self.inner.fetch_add(value, order)
-    }
-
-    fn fetch_sub(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive {
-        self.inner.fetch_sub(value, order)
-    }
-
     fn fetch_and(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive {
         self.inner.fetch_and(value, order)
     }
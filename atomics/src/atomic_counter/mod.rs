@@ -4,11 +4,15 @@
 
 use crate::{AtomicPrimitive, Ordering};
 
+#[cfg(feature = "wide")]
+mod atomic_i128;
 mod atomic_i16;
 mod atomic_i32;
 mod atomic_i64;
 mod atomic_i8;
 mod atomic_isize;
+#[cfg(feature = "wide")]
+mod atomic_u128;
 mod atomic_u16;
 mod atomic_u32;
 mod atomic_u64;
@@ -33,26 +37,6 @@ pub trait AtomicCounter: AtomicPrimitive
 where
     Self::Primitive: Default + PartialEq + Copy,
 {
-    /// Adds to the current value, returning the previous value.
-    ///
-    /// This wraps around on overflow.
-    ///
-    /// `fetch_add` take an `Ordering` argument which describes the memory
-    /// ordering of the operation. All ordering modes are possible. Note that
-    /// using `Acquire` makes the store part of this operation `Relaxed`, and
-    /// using `Release` makes the load part of this operation `Relaxed`.
-    fn fetch_add(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive;
-
-    /// Subtracts from the current value, returning the previous value.
-    ///
-    /// This wraps around on overflow.
-    ///
-    /// `fetch_sub` take an `Ordering` argument which describes the memory
-    /// ordering of the operation. All ordering modes are possible. Note that
-    /// using `Acquire` makes the store part of this operation `Relaxed`, and
-    /// using `Release` makes the load part of this operation `Relaxed`.
-    fn fetch_sub(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive;
-
     /// Bitwise "and" with the current value, returning the previous value.
     ///
     /// `fetch_and` take an `Ordering` argument which describes the memory
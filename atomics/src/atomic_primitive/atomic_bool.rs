@@ -2,12 +2,15 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use super::check_failure_ordering;
 use crate::{AtomicPrimitive, Ordering};
 
 #[cfg(feature = "serde")]
 use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 
-/// A boolean type which can be safely shared between threads.
+/// A boolean type which can be safely shared between threads. As `bool` does
+/// not implement `Wrapping`, the `fetch_add` and `fetch_sub` methods of
+/// `AtomicPrimitive` are not available for this type.
 pub struct AtomicBool {
     pub(crate) inner: core::sync::atomic::AtomicBool,
 }
@@ -57,6 +60,7 @@ impl AtomicPrimitive for AtomicBool {
         success: Ordering,
         failure: Ordering,
     ) -> Result<Self::Primitive, Self::Primitive> {
+        check_failure_ordering(failure);
         self.inner.compare_exchange(current, new, success, failure)
     }
 
@@ -67,6 +71,7 @@ impl AtomicPrimitive for AtomicBool {
         success: Ordering,
         failure: Ordering,
     ) -> Result<Self::Primitive, Self::Primitive> {
+        check_failure_ordering(failure);
         self.inner
             .compare_exchange_weak(current, new, success, failure)
     }
@@ -131,3 +136,70 @@ impl Serialize for AtomicBool {
         serializer.serialize_some(&self.load(Ordering::SeqCst))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load() {
+        let atomic = AtomicBool::new(false);
+        assert!(!atomic.load(Ordering::SeqCst));
+        assert!(AtomicBool::new(true).load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn store() {
+        let atomic = AtomicBool::new(false);
+        atomic.store(true, Ordering::SeqCst);
+        assert!(atomic.into_inner());
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut atomic = AtomicBool::default();
+        *atomic.get_mut() = true;
+        assert!(atomic.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn swap() {
+        let atomic = AtomicBool::new(false);
+        assert!(!atomic.swap(true, Ordering::SeqCst));
+        assert!(atomic.swap(false, Ordering::SeqCst));
+        assert!(!atomic.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn compare_exchange() {
+        let atomic = AtomicBool::new(false);
+        assert_eq!(
+            atomic.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst),
+            Ok(false)
+        );
+        assert_eq!(
+            atomic.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst),
+            Err(true)
+        );
+    }
+
+    #[test]
+    fn compare_exchange_weak() {
+        let atomic = AtomicBool::new(false);
+        loop {
+            if atomic
+                .compare_exchange_weak(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                break;
+            }
+        }
+        assert!(atomic.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn eq() {
+        assert_eq!(AtomicBool::new(true), AtomicBool::new(true));
+        assert_ne!(AtomicBool::new(true), AtomicBool::default());
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2019 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::{check_failure_ordering, failure_ordering};
+use crate::{AtomicPrimitive, Ordering};
+
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An integer type which can be safely shared between threads.
+///
+/// Native 128bit atomics are not available on all platforms, so operations
+/// are performed under a lock and always behave as if `SeqCst` ordering was
+/// used.
+pub struct AtomicI128 {
+    pub(crate) inner: Mutex<i128>,
+}
+
+impl AtomicI128 {
+    // no operation can panic while holding the lock, so poisoning is ignored
+    pub(crate) fn lock(&self) -> MutexGuard<'_, i128> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl AtomicPrimitive for AtomicI128 {
+    type Primitive = i128;
+
+    fn new(value: Self::Primitive) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Primitive {
+        self.inner.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn into_inner(self) -> Self::Primitive {
+        self.inner.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn load(&self, _order: Ordering) -> Self::Primitive {
+        *self.lock()
+    }
+
+    fn store(&self, value: Self::Primitive, _order: Ordering) {
+        *self.lock() = value;
+    }
+
+    fn swap(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        std::mem::replace(&mut *self.lock(), value)
+    }
+
+    fn compare_and_swap(
+        &self,
+        current: Self::Primitive,
+        new: Self::Primitive,
+        order: Ordering,
+    ) -> Self::Primitive {
+        match self.compare_exchange(current, new, order, failure_ordering(order)) {
+            Ok(previous) => previous,
+            Err(previous) => previous,
+        }
+    }
+
+    fn compare_exchange(
+        &self,
+        current: Self::Primitive,
+        new: Self::Primitive,
+        _success: Ordering,
+        failure: Ordering,
+    ) -> Result<Self::Primitive, Self::Primitive> {
+        check_failure_ordering(failure);
+        let mut inner = self.lock();
+        let previous = *inner;
+        if previous == current {
+            *inner = new;
+            Ok(previous)
+        } else {
+            Err(previous)
+        }
+    }
+
+    fn compare_exchange_weak(
+        &self,
+        current: Self::Primitive,
+        new: Self::Primitive,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Self::Primitive, Self::Primitive> {
+        self.compare_exchange(current, new, success, failure)
+    }
+    fn fetch_add(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous.wrapping_add(value);
+        previous
+    }
+
+    fn fetch_sub(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous.wrapping_sub(value);
+        previous
+    }
+
+    fn fetch_max(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous.max(value);
+        previous
+    }
+
+    fn fetch_min(&self, value: Self::Primitive, _order: Ordering) -> Self::Primitive {
+        let mut inner = self.lock();
+        let previous = *inner;
+        *inner = previous.min(value);
+        previous
+    }
+}
+
+impl Default for AtomicI128 {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl PartialEq for AtomicI128 {
+    fn eq(&self, other: &Self) -> bool {
+        self.load(Ordering::SeqCst) == other.load(Ordering::SeqCst)
+    }
+}
+
+impl Eq for AtomicI128 {}
+
+impl std::fmt::Debug for AtomicI128 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for AtomicI128 {
+    fn deserialize<D>(deserializer: D) -> Result<AtomicI128, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        i128::deserialize(deserializer).map(AtomicI128::new)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for AtomicI128 {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_some(&self.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDE: i128 = i128::MAX - 1;
+
+    #[test]
+    fn load() {
+        let atomic = AtomicI128::new(0);
+        assert_eq!(atomic.load(Ordering::SeqCst), 0);
+        let atomic = AtomicI128::new(WIDE);
+        assert_eq!(atomic.load(Ordering::SeqCst), WIDE);
+    }
+
+    #[test]
+    fn store() {
+        let atomic = AtomicI128::new(0);
+        atomic.store(WIDE, Ordering::SeqCst);
+        assert_eq!(atomic.into_inner(), WIDE);
+    }
+
+    #[test]
+    fn swap() {
+        let atomic = AtomicI128::new(0);
+        assert_eq!(atomic.swap(WIDE, Ordering::SeqCst), 0);
+        assert_eq!(atomic.load(Ordering::SeqCst), WIDE);
+    }
+
+    #[test]
+    fn compare_and_swap() {
+        let atomic = AtomicI128::new(0);
+        let orders = [
+            Ordering::Relaxed,
+            Ordering::Release,
+            Ordering::Acquire,
+            Ordering::AcqRel,
+            Ordering::SeqCst,
+        ];
+        for order in &orders {
+            atomic.store(0, Ordering::SeqCst);
+            assert_eq!(atomic.compare_and_swap(0, WIDE, *order), 0);
+            assert_eq!(atomic.compare_and_swap(0, 2, *order), WIDE);
+        }
+    }
+
+    #[test]
+    fn compare_exchange() {
+        let atomic = AtomicI128::new(0);
+        assert_eq!(
+            atomic.compare_exchange(0, WIDE, Ordering::SeqCst, Ordering::SeqCst),
+            Ok(0)
+        );
+        assert_eq!(
+            atomic.compare_exchange(0, 2, Ordering::SeqCst, Ordering::SeqCst),
+            Err(WIDE)
+        );
+    }
+
+    #[test]
+    fn compare_exchange_weak() {
+        let atomic = AtomicI128::new(0);
+        loop {
+            if atomic
+                .compare_exchange_weak(0, WIDE, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                break;
+            }
+        }
+        assert_eq!(atomic.into_inner(), WIDE);
+    }
+
+    #[test]
+    fn fetch_add() {
+        let atomic = AtomicI128::new(i128::MAX);
+        assert_eq!(atomic.fetch_add(1, Ordering::SeqCst), i128::MAX);
+        assert_eq!(atomic.load(Ordering::SeqCst), i128::MIN);
+    }
+
+    #[test]
+    fn fetch_sub() {
+        let atomic = AtomicI128::new(i128::MIN);
+        assert_eq!(atomic.fetch_sub(1, Ordering::SeqCst), i128::MIN);
+        assert_eq!(atomic.load(Ordering::SeqCst), i128::MAX);
+    }
+
+    #[test]
+    fn fetch_max_min() {
+        let atomic = AtomicI128::new(i128::MIN);
+        assert_eq!(atomic.fetch_max(i128::MAX, Ordering::SeqCst), i128::MIN);
+        assert_eq!(atomic.fetch_max(1, Ordering::SeqCst), i128::MAX);
+        assert_eq!(atomic.fetch_min(1, Ordering::SeqCst), i128::MAX);
+        assert_eq!(atomic.fetch_min(i128::MAX, Ordering::SeqCst), 1);
+        assert_eq!(atomic.load(Ordering::SeqCst), 1);
+    }
+}
@@ -2,6 +2,7 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use super::check_failure_ordering;
 use crate::{AtomicPrimitive, Ordering};
 
 /// A raw pointer type which can be safely shared between threads.
@@ -54,6 +55,7 @@ impl<T> AtomicPrimitive for AtomicPtr<T> {
         success: Ordering,
         failure: Ordering,
     ) -> Result<Self::Primitive, Self::Primitive> {
+        check_failure_ordering(failure);
         self.inner.compare_exchange(current, new, success, failure)
     }
 
@@ -64,6 +66,7 @@ impl<T> AtomicPrimitive for AtomicPtr<T> {
         success: Ordering,
         failure: Ordering,
     ) -> Result<Self::Primitive, Self::Primitive> {
+        check_failure_ordering(failure);
         self.inner
             .compare_exchange_weak(current, new, success, failure)
     }
@@ -0,0 +1,213 @@
+// Copyright 2019 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+/// Defines an atomic integer type wrapping the given std atomic, along with
+/// its `AtomicPrimitive`, `Default`, `PartialEq`, `Eq`, and `Debug` impls and,
+/// with the `serde` feature, its `Serialize` and `Deserialize` impls. The
+/// `expecting` string describes the type in deserialization errors and the
+/// `deserialize` method is the hint passed to the `Deserializer`.
+macro_rules! impl_atomic_primitive {
+    ($name:ident, $inner:ty, $primitive:ident, $expecting:expr, $deserialize:ident) => {
+        /// An integer type which can be safely shared between threads.
+        pub struct $name {
+            pub(crate) inner: $inner,
+        }
+
+        impl $crate::AtomicPrimitive for $name {
+            type Primitive = $primitive;
+
+            fn new(value: Self::Primitive) -> Self {
+                Self {
+                    inner: <$inner>::new(value),
+                }
+            }
+
+            fn get_mut(&mut self) -> &mut Self::Primitive {
+                self.inner.get_mut()
+            }
+
+            fn into_inner(self) -> Self::Primitive {
+                self.inner.into_inner()
+            }
+
+            fn load(&self, order: $crate::Ordering) -> Self::Primitive {
+                self.inner.load(order)
+            }
+
+            fn store(&self, value: Self::Primitive, order: $crate::Ordering) {
+                self.inner.store(value, order);
+            }
+
+            fn swap(&self, value: Self::Primitive, order: $crate::Ordering) -> Self::Primitive {
+                self.inner.swap(value, order)
+            }
+
+            fn compare_and_swap(
+                &self,
+                current: Self::Primitive,
+                new: Self::Primitive,
+                order: $crate::Ordering,
+            ) -> Self::Primitive {
+                #[allow(deprecated)]
+                self.inner.compare_and_swap(current, new, order)
+            }
+
+            fn compare_exchange(
+                &self,
+                current: Self::Primitive,
+                new: Self::Primitive,
+                success: $crate::Ordering,
+                failure: $crate::Ordering,
+            ) -> Result<Self::Primitive, Self::Primitive> {
+                $crate::atomic_primitive::check_failure_ordering(failure);
+                self.inner.compare_exchange(current, new, success, failure)
+            }
+
+            fn compare_exchange_weak(
+                &self,
+                current: Self::Primitive,
+                new: Self::Primitive,
+                success: $crate::Ordering,
+                failure: $crate::Ordering,
+            ) -> Result<Self::Primitive, Self::Primitive> {
+                $crate::atomic_primitive::check_failure_ordering(failure);
+                self.inner
+                    .compare_exchange_weak(current, new, success, failure)
+            }
+
+            fn fetch_add(
+                &self,
+                value: Self::Primitive,
+                order: $crate::Ordering,
+            ) -> Self::Primitive {
+                self.inner.fetch_add(value, order)
+            }
+
+            fn fetch_sub(
+                &self,
+                value: Self::Primitive,
+                order: $crate::Ordering,
+            ) -> Self::Primitive {
+                self.inner.fetch_sub(value, order)
+            }
+
+            fn fetch_max(
+                &self,
+                value: Self::Primitive,
+                order: $crate::Ordering,
+            ) -> Self::Primitive {
+                self.inner.fetch_max(value, order)
+            }
+
+            fn fetch_min(
+                &self,
+                value: Self::Primitive,
+                order: $crate::Ordering,
+            ) -> Self::Primitive {
+                self.inner.fetch_min(value, order)
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                <Self as $crate::AtomicPrimitive>::new(Default::default())
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                use $crate::AtomicPrimitive;
+                self.load($crate::Ordering::SeqCst) == other.load($crate::Ordering::SeqCst)
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{:?}", self.inner)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        struct PrimitiveVisitor;
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::de::Visitor<'de> for PrimitiveVisitor {
+            type Value = $name;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str($expecting)
+            }
+
+            impl_atomic_primitive!(@visit $primitive, visit_i8, i8);
+            impl_atomic_primitive!(@visit $primitive, visit_i16, i16);
+            impl_atomic_primitive!(@visit $primitive, visit_i32, i32);
+            impl_atomic_primitive!(@visit $primitive, visit_i64, i64);
+            impl_atomic_primitive!(@visit $primitive, visit_u8, u8);
+            impl_atomic_primitive!(@visit $primitive, visit_u16, u16);
+            impl_atomic_primitive!(@visit $primitive, visit_u32, u32);
+            impl_atomic_primitive!(@visit $primitive, visit_u64, u64);
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<$name, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                deserializer.$deserialize(PrimitiveVisitor)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            #[inline]
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                use $crate::AtomicPrimitive;
+                serializer.serialize_some(&self.load($crate::Ordering::SeqCst))
+            }
+        }
+    };
+    // a visitor method which accepts any value representable by the primitive
+    (@visit $primitive:ident, $visit:ident, $from:ident) => {
+        #[allow(clippy::unnecessary_fallible_conversions)]
+        fn $visit<E>(self, value: $from) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            use std::convert::TryFrom;
+            use $crate::AtomicPrimitive;
+            $primitive::try_from(value)
+                .map(Self::Value::new)
+                .map_err(|_| {
+                    E::custom(format!(
+                        "{} is out of range: {}",
+                        stringify!($primitive),
+                        value
+                    ))
+                })
+        }
+    };
+}
+
+/// Implements `Wrapping` for each of the given integer types with their
+/// inherent wrapping arithmetic.
+macro_rules! impl_wrapping {
+    ($($primitive:ident),*) => {
+        $(
+            impl Wrapping for $primitive {
+                fn wrapping_add(&self, other: Self) -> Self {
+                    $primitive::wrapping_add(*self, other)
+                }
+                fn wrapping_sub(&self, other: Self) -> Self {
+                    $primitive::wrapping_sub(*self, other)
+                }
+            }
+        )*
+    };
+}
@@ -6,13 +6,20 @@ use crate::Ordering;
 
 use core::fmt::Debug;
 
+#[macro_use]
+mod macros;
+
 mod atomic_bool;
+#[cfg(feature = "wide")]
+mod atomic_i128;
 mod atomic_i16;
 mod atomic_i32;
 mod atomic_i64;
 mod atomic_i8;
 mod atomic_isize;
 mod atomic_ptr;
+#[cfg(feature = "wide")]
+mod atomic_u128;
 mod atomic_u16;
 mod atomic_u32;
 mod atomic_u64;
@@ -20,12 +27,16 @@ mod atomic_u8;
 mod atomic_usize;
 
 pub use self::atomic_bool::*;
+#[cfg(feature = "wide")]
+pub use self::atomic_i128::*;
 pub use self::atomic_i16::*;
 pub use self::atomic_i32::*;
 pub use self::atomic_i64::*;
 pub use self::atomic_i8::*;
 pub use self::atomic_isize::*;
 pub use self::atomic_ptr::*;
+#[cfg(feature = "wide")]
+pub use self::atomic_u128::*;
 pub use self::atomic_u16::*;
 pub use self::atomic_u32::*;
 pub use self::atomic_u64::*;
@@ -107,7 +118,8 @@ pub trait AtomicPrimitive: Send + Sync + Debug + PartialEq {
     /// ordering makes the store part of this operation `Relaxed`, and using
     /// `Release` makes the successful load `Relaxed`. The failure ordering
     /// can only be `SeqCst`, `Acquire`, or `Relaxed` and must be equivalent
-    /// to or weaker than the success ordering.
+    /// to or weaker than the success ordering. Passing `Release` or `AcqRel`
+    /// as the failure ordering panics.
     fn compare_exchange(
         &self,
         current: Self::Primitive,
@@ -131,7 +143,8 @@ pub trait AtomicPrimitive: Send + Sync + Debug + PartialEq {
     /// ordering makes the store part of this operation `Relaxed`, and using
     /// `Release` makes the successful load `Relaxed`. The failure ordering
     /// can only be `SeqCst`, `Acquire`, or `Relaxed` and must be equivalent
-    /// to or weaker than the success ordering.
+    /// to or weaker than the success ordering. Passing `Release` or `AcqRel`
+    /// as the failure ordering panics.
     fn compare_exchange_weak(
         &self,
         current: Self::Primitive,
@@ -139,4 +152,366 @@ pub trait AtomicPrimitive: Send + Sync + Debug + PartialEq {
         success: Ordering,
         failure: Ordering,
     ) -> Result<Self::Primitive, Self::Primitive>;
+
+    /// Adds to the current value, returning the previous value.
+    ///
+    /// This wraps around on overflow.
+    ///
+    /// `fetch_add` takes an `Ordering` argument which describes the memory
+    /// ordering of the operation. All ordering modes are possible. Note that
+    /// using `Acquire` makes the store part of this operation `Relaxed`, and
+    /// using `Release` makes the load part of this operation `Relaxed`.
+    ///
+    /// The default implementation is a compare-exchange loop, which types
+    /// with native support for atomic addition override.
+    fn fetch_add(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive
+    where
+        Self::Primitive: Copy + Wrapping,
+    {
+        let mut current = self.load(Ordering::Relaxed);
+        loop {
+            let new = current.wrapping_add(value);
+            match self.compare_exchange_weak(current, new, order, failure_ordering(order)) {
+                Ok(previous) => return previous,
+                Err(previous) => current = previous,
+            }
+        }
+    }
+
+    /// Subtracts from the current value, returning the previous value.
+    ///
+    /// This wraps around on overflow.
+    ///
+    /// `fetch_sub` takes an `Ordering` argument which describes the memory
+    /// ordering of the operation. All ordering modes are possible. Note that
+    /// using `Acquire` makes the store part of this operation `Relaxed`, and
+    /// using `Release` makes the load part of this operation `Relaxed`.
+    ///
+    /// The default implementation is a compare-exchange loop, which types
+    /// with native support for atomic subtraction override.
+    fn fetch_sub(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive
+    where
+        Self::Primitive: Copy + Wrapping,
+    {
+        let mut current = self.load(Ordering::Relaxed);
+        loop {
+            let new = current.wrapping_sub(value);
+            match self.compare_exchange_weak(current, new, order, failure_ordering(order)) {
+                Ok(previous) => return previous,
+                Err(previous) => current = previous,
+            }
+        }
+    }
+
+    /// Stores the maximum of the current value and `value`, returning the
+    /// previous value.
+    ///
+    /// `fetch_max` takes an `Ordering` argument which describes the memory
+    /// ordering of the operation. All ordering modes are possible. Note that
+    /// using `Acquire` makes the store part of this operation `Relaxed`, and
+    /// using `Release` makes the load part of this operation `Relaxed`.
+    ///
+    /// The default implementation is a compare-exchange loop, which types
+    /// with native support for atomic maximum override. The loop stops
+    /// without storing once the current value is at least `value`.
+    fn fetch_max(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive
+    where
+        Self::Primitive: Copy + PartialOrd,
+    {
+        let mut current = self.load(failure_ordering(order));
+        while value > current {
+            match self.compare_exchange_weak(current, value, order, failure_ordering(order)) {
+                Ok(previous) => return previous,
+                Err(previous) => current = previous,
+            }
+        }
+        current
+    }
+
+    /// Stores the minimum of the current value and `value`, returning the
+    /// previous value.
+    ///
+    /// `fetch_min` takes an `Ordering` argument which describes the memory
+    /// ordering of the operation. All ordering modes are possible. Note that
+    /// using `Acquire` makes the store part of this operation `Relaxed`, and
+    /// using `Release` makes the load part of this operation `Relaxed`.
+    ///
+    /// The default implementation is a compare-exchange loop, which types
+    /// with native support for atomic minimum override. The loop stops
+    /// without storing once the current value is at most `value`.
+    fn fetch_min(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive
+    where
+        Self::Primitive: Copy + PartialOrd,
+    {
+        let mut current = self.load(failure_ordering(order));
+        while value < current {
+            match self.compare_exchange_weak(current, value, order, failure_ordering(order)) {
+                Ok(previous) => return previous,
+                Err(previous) => current = previous,
+            }
+        }
+        current
+    }
+}
+
+/// A trait for primitive types which implement wrapping addition and
+/// subtraction, allowing an `AtomicPrimitive` to provide `fetch_add` and
+/// `fetch_sub`
+pub trait Wrapping {
+    fn wrapping_add(&self, other: Self) -> Self;
+    fn wrapping_sub(&self, other: Self) -> Self;
+}
+
+impl_wrapping!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+// the strongest ordering which may be used for the failure case of a
+// compare-exchange with the given success ordering
+fn failure_ordering(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        order => order,
+    }
+}
+
+// std panics if the failure ordering of a compare-exchange is `Release` or
+// `AcqRel`. check it up front so that the misuse is reported clearly
+pub(crate) fn check_failure_ordering(failure: Ordering) {
+    debug_assert!(
+        failure != Ordering::Release && failure != Ordering::AcqRel,
+        "invalid failure ordering for compare-exchange: {:?}, must be SeqCst, Acquire, or Relaxed",
+        failure
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // wraps an atomic without overriding `fetch_add` or `fetch_sub` so that
+    // the default implementations are used
+    #[derive(Debug)]
+    struct Fallback {
+        inner: core::sync::atomic::AtomicU8,
+    }
+
+    impl PartialEq for Fallback {
+        fn eq(&self, other: &Self) -> bool {
+            self.load(Ordering::SeqCst) == other.load(Ordering::SeqCst)
+        }
+    }
+
+    impl AtomicPrimitive for Fallback {
+        type Primitive = u8;
+
+        fn new(value: Self::Primitive) -> Self {
+            Self {
+                inner: core::sync::atomic::AtomicU8::new(value),
+            }
+        }
+
+        fn get_mut(&mut self) -> &mut Self::Primitive {
+            self.inner.get_mut()
+        }
+
+        fn into_inner(self) -> Self::Primitive {
+            self.inner.into_inner()
+        }
+
+        fn load(&self, order: Ordering) -> Self::Primitive {
+            self.inner.load(order)
+        }
+
+        fn store(&self, value: Self::Primitive, order: Ordering) {
+            self.inner.store(value, order);
+        }
+
+        fn swap(&self, value: Self::Primitive, order: Ordering) -> Self::Primitive {
+            self.inner.swap(value, order)
+        }
+
+        fn compare_and_swap(
+            &self,
+            current: Self::Primitive,
+            new: Self::Primitive,
+            order: Ordering,
+        ) -> Self::Primitive {
+            match self.compare_exchange(current, new, order, failure_ordering(order)) {
+                Ok(previous) => previous,
+                Err(previous) => previous,
+            }
+        }
+
+        fn compare_exchange(
+            &self,
+            current: Self::Primitive,
+            new: Self::Primitive,
+            success: Ordering,
+            failure: Ordering,
+        ) -> Result<Self::Primitive, Self::Primitive> {
+            self.inner.compare_exchange(current, new, success, failure)
+        }
+
+        fn compare_exchange_weak(
+            &self,
+            current: Self::Primitive,
+            new: Self::Primitive,
+            success: Ordering,
+            failure: Ordering,
+        ) -> Result<Self::Primitive, Self::Primitive> {
+            self.inner
+                .compare_exchange_weak(current, new, success, failure)
+        }
+    }
+
+    #[test]
+    fn fetch_add() {
+        let orders = [
+            Ordering::Relaxed,
+            Ordering::Release,
+            Ordering::Acquire,
+            Ordering::AcqRel,
+            Ordering::SeqCst,
+        ];
+        for order in &orders {
+            let atomic = Fallback::new(254);
+            let std = core::sync::atomic::AtomicU8::new(254);
+            for _ in 0..3 {
+                assert_eq!(atomic.fetch_add(1, *order), std.fetch_add(1, *order));
+            }
+            assert_eq!(atomic.into_inner(), 1);
+        }
+    }
+
+    #[test]
+    fn fetch_sub() {
+        let orders = [
+            Ordering::Relaxed,
+            Ordering::Release,
+            Ordering::Acquire,
+            Ordering::AcqRel,
+            Ordering::SeqCst,
+        ];
+        for order in &orders {
+            let atomic = Fallback::new(1);
+            let std = core::sync::atomic::AtomicU8::new(1);
+            for _ in 0..3 {
+                assert_eq!(atomic.fetch_sub(1, *order), std.fetch_sub(1, *order));
+            }
+            assert_eq!(atomic.into_inner(), 254);
+        }
+    }
+
+    #[test]
+    fn fetch_max_min() {
+        let orders = [
+            Ordering::Relaxed,
+            Ordering::Release,
+            Ordering::Acquire,
+            Ordering::AcqRel,
+            Ordering::SeqCst,
+        ];
+        for order in &orders {
+            let atomic = Fallback::new(10);
+            let std = core::sync::atomic::AtomicU8::new(10);
+            for value in &[5, 20, 20, 15, 255] {
+                assert_eq!(
+                    atomic.fetch_max(*value, *order),
+                    std.fetch_max(*value, *order)
+                );
+            }
+            for value in &[100, 0, 50] {
+                assert_eq!(
+                    atomic.fetch_min(*value, *order),
+                    std.fetch_min(*value, *order)
+                );
+            }
+            assert_eq!(atomic.into_inner(), 0);
+        }
+    }
+
+    // hammers the atomic from several threads, each with its own values
+    fn concurrent_max<T>(atomic: T) -> T::Primitive
+    where
+        T: AtomicPrimitive<Primitive = u8> + 'static,
+    {
+        let atomic = std::sync::Arc::new(atomic);
+        let threads: Vec<_> = (0..8_u8)
+            .map(|thread| {
+                let atomic = atomic.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10_000_u32 {
+                        let value = ((i * 7 + u32::from(thread) * 13) % 200) as u8 + thread;
+                        atomic.fetch_max(value, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        atomic.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn fetch_max_concurrent() {
+        // the largest value any thread stores is 199 + 7
+        assert_eq!(concurrent_max(Fallback::new(0)), 206);
+        assert_eq!(concurrent_max(crate::AtomicU8::new(0)), 206);
+
+        let atomic = std::sync::Arc::new(crate::AtomicU64::new(u64::MAX));
+        let threads: Vec<_> = (0..8_u64)
+            .map(|thread| {
+                let atomic = atomic.clone();
+                std::thread::spawn(move || {
+                    for value in (thread * 100_000)..((thread + 1) * 100_000) {
+                        atomic.fetch_min(value + 1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(atomic.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invalid failure ordering for compare-exchange: Release")]
+    fn compare_exchange_release_failure() {
+        let atomic = crate::AtomicU64::new(0);
+        let _ = atomic.compare_exchange(0, 1, Ordering::SeqCst, Ordering::Release);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invalid failure ordering for compare-exchange: AcqRel")]
+    fn compare_exchange_weak_acqrel_failure() {
+        let atomic = crate::AtomicBool::new(false);
+        let _ = atomic.compare_exchange_weak(false, true, Ordering::SeqCst, Ordering::AcqRel);
+    }
+
+    #[test]
+    fn check_failure_ordering_valid() {
+        for order in &[Ordering::Relaxed, Ordering::Acquire, Ordering::SeqCst] {
+            check_failure_ordering(*order);
+        }
+    }
+
+    fn implements<T: AtomicPrimitive + Default + Eq>() {}
+
+    #[test]
+    fn macro_types() {
+        implements::<crate::AtomicI8>();
+        implements::<crate::AtomicI16>();
+        implements::<crate::AtomicI32>();
+        implements::<crate::AtomicI64>();
+        implements::<crate::AtomicIsize>();
+        implements::<crate::AtomicU8>();
+        implements::<crate::AtomicU16>();
+        implements::<crate::AtomicU32>();
+        implements::<crate::AtomicU64>();
+        implements::<crate::AtomicUsize>();
+    }
 }
@@ -0,0 +1,228 @@
+// Copyright 2019 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+
+/// A value which may be set, raised, or lowered from any thread, eg: the
+/// number of open connections. Optionally, the `Gauge` also tracks the
+/// largest and smallest values it has held, see `with_extremes()`. Each
+/// operation is atomic on its own, but the extremes are updated after the
+/// value, so a reader may briefly observe a value outside of them. Operations
+/// are `Relaxed`, so a `Gauge` orders no other memory accesses.
+pub struct Gauge<T>
+where
+    T: AtomicPrimitive,
+{
+    value: T,
+    max: Option<T>,
+    min: Option<T>,
+}
+
+impl<T> Gauge<T>
+where
+    T: AtomicPrimitive,
+    <T as AtomicPrimitive>::Primitive: Copy + PartialOrd + Wrapping,
+{
+    /// Create a new `Gauge` holding the value
+    pub fn new(value: <T as AtomicPrimitive>::Primitive) -> Self {
+        Self {
+            value: T::new(value),
+            max: None,
+            min: None,
+        }
+    }
+
+    /// Create a new `Gauge` holding the value, which tracks the largest and
+    /// smallest values it has held
+    pub fn with_extremes(value: <T as AtomicPrimitive>::Primitive) -> Self {
+        Self {
+            value: T::new(value),
+            max: Some(T::new(value)),
+            min: Some(T::new(value)),
+        }
+    }
+
+    /// Returns the current value
+    pub fn get(&self) -> <T as AtomicPrimitive>::Primitive {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Sets the value, returning the previous value
+    pub fn set(
+        &self,
+        value: <T as AtomicPrimitive>::Primitive,
+    ) -> <T as AtomicPrimitive>::Primitive {
+        let previous = self.value.swap(value, Ordering::Relaxed);
+        self.track(value);
+        previous
+    }
+
+    /// Adds to the value, wrapping around on overflow, and returns the new
+    /// value
+    pub fn add(
+        &self,
+        value: <T as AtomicPrimitive>::Primitive,
+    ) -> <T as AtomicPrimitive>::Primitive {
+        let new = self
+            .value
+            .fetch_add(value, Ordering::Relaxed)
+            .wrapping_add(value);
+        self.track(new);
+        new
+    }
+
+    /// Subtracts from the value, wrapping around on overflow, and returns the
+    /// new value
+    pub fn sub(
+        &self,
+        value: <T as AtomicPrimitive>::Primitive,
+    ) -> <T as AtomicPrimitive>::Primitive {
+        let new = self
+            .value
+            .fetch_sub(value, Ordering::Relaxed)
+            .wrapping_sub(value);
+        self.track(new);
+        new
+    }
+
+    /// Raises the value to `value` if it is lower, eg: to keep the peak of a
+    /// reading taken by several threads. Returns the previous value.
+    pub fn set_max(
+        &self,
+        value: <T as AtomicPrimitive>::Primitive,
+    ) -> <T as AtomicPrimitive>::Primitive {
+        let previous = self.value.fetch_max(value, Ordering::Relaxed);
+        self.track(if value > previous { value } else { previous });
+        previous
+    }
+
+    /// Lowers the value to `value` if it is higher. Returns the previous
+    /// value.
+    pub fn set_min(
+        &self,
+        value: <T as AtomicPrimitive>::Primitive,
+    ) -> <T as AtomicPrimitive>::Primitive {
+        let previous = self.value.fetch_min(value, Ordering::Relaxed);
+        self.track(if value < previous { value } else { previous });
+        previous
+    }
+
+    /// Returns the largest value held, or `None` if the extremes are not
+    /// tracked
+    pub fn max(&self) -> Option<<T as AtomicPrimitive>::Primitive> {
+        self.max.as_ref().map(|max| max.load(Ordering::Relaxed))
+    }
+
+    /// Returns the smallest value held, or `None` if the extremes are not
+    /// tracked
+    pub fn min(&self) -> Option<<T as AtomicPrimitive>::Primitive> {
+        self.min.as_ref().map(|min| min.load(Ordering::Relaxed))
+    }
+
+    /// Resets the largest and smallest values to the current value, eg: at
+    /// the start of each interval
+    pub fn reset_extremes(&self) {
+        let value = self.get();
+        if let Some(ref max) = self.max {
+            max.store(value, Ordering::Relaxed);
+        }
+        if let Some(ref min) = self.min {
+            min.store(value, Ordering::Relaxed);
+        }
+    }
+
+    // updates the extremes with a value the gauge has held
+    fn track(&self, value: <T as AtomicPrimitive>::Primitive) {
+        if let Some(ref max) = self.max {
+            max.fetch_max(value, Ordering::Relaxed);
+        }
+        if let Some(ref min) = self.min {
+            min.fetch_min(value, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn operations() {
+        let gauge = Gauge::<AtomicI64>::new(10);
+        assert_eq!(gauge.get(), 10);
+        assert_eq!(gauge.set(3), 10);
+        assert_eq!(gauge.add(4), 7);
+        assert_eq!(gauge.sub(9), -2);
+        assert_eq!(gauge.set_max(5), -2);
+        assert_eq!(gauge.set_max(1), 5);
+        assert_eq!(gauge.get(), 5);
+        assert_eq!(gauge.set_min(-8), 5);
+        assert_eq!(gauge.set_min(0), -8);
+        assert_eq!(gauge.get(), -8);
+        assert_eq!(gauge.max(), None);
+        assert_eq!(gauge.min(), None);
+
+        let gauge = Gauge::<AtomicU8>::with_extremes(1);
+        assert_eq!(gauge.sub(2), 255);
+        assert_eq!(gauge.add(3), 2);
+        assert_eq!(gauge.max(), Some(255));
+        assert_eq!(gauge.min(), Some(1));
+        gauge.set(0);
+        assert_eq!(gauge.min(), Some(0));
+        gauge.reset_extremes();
+        assert_eq!(gauge.max(), Some(0));
+        assert_eq!(gauge.min(), Some(0));
+
+        // a value which leaves the gauge unchanged is not an extreme
+        let gauge = Gauge::<AtomicU64>::with_extremes(10);
+        assert_eq!(gauge.set_max(5), 10);
+        assert_eq!(gauge.set_min(20), 10);
+        assert_eq!(gauge.get(), 10);
+        assert_eq!(gauge.max(), Some(10));
+        assert_eq!(gauge.min(), Some(10));
+        gauge.set_max(15);
+        gauge.set_min(3);
+        assert_eq!(gauge.max(), Some(15));
+        assert_eq!(gauge.min(), Some(3));
+    }
+
+    #[test]
+    fn concurrent_set_max() {
+        let gauge = Arc::new(Gauge::<AtomicU64>::with_extremes(0));
+        let threads: Vec<_> = (0..8_u64)
+            .map(|thread| {
+                let gauge = gauge.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        gauge.set_max(i * 8 + thread);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(gauge.get(), 79_999);
+        assert_eq!(gauge.max(), Some(79_999));
+        assert_eq!(gauge.min(), Some(0));
+
+        let gauge = Arc::new(Gauge::<AtomicI32>::new(0));
+        let threads: Vec<_> = (1..=8)
+            .map(|thread| {
+                let gauge = gauge.clone();
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        gauge.set_min(-(i * 8 + thread));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(gauge.get(), -8_000);
+    }
+}
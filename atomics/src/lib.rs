@@ -2,13 +2,17 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+mod atomic_cell;
 mod atomic_counter;
 mod atomic_option;
 mod atomic_primitive;
+mod gauge;
 
+pub use crate::atomic_cell::*;
 pub use crate::atomic_counter::*;
 pub use crate::atomic_option::*;
 pub use crate::atomic_primitive::*;
+pub use crate::gauge::*;
 pub use core::sync::atomic::Ordering;
 
 #[cfg(test)]
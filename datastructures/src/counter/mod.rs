@@ -124,12 +124,18 @@ where
     }
 
     /// Convenience function to do a relaxed wrapping add
-    fn add(&self, value: Self::Primitive) -> Self::Primitive {
+    fn add(&self, value: Self::Primitive) -> Self::Primitive
+    where
+        Self::Primitive: Wrapping,
+    {
         self.fetch_add(value, Ordering::Relaxed)
     }
 
     /// Convenience function to do a relaxed wrapping sub
-    fn sub(&self, value: Self::Primitive) -> Self::Primitive {
+    fn sub(&self, value: Self::Primitive) -> Self::Primitive
+    where
+        Self::Primitive: Wrapping,
+    {
         self.fetch_sub(value, Ordering::Relaxed)
     }
 
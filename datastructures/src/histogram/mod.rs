@@ -6,9 +6,75 @@ use crate::counter::*;
 use atomics::*;
 use parking_lot::Mutex;
 use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+// the number of samples, counting from the lowest, which must be reached to
+// find the percentile of the total, see `Histogram::percentile`
+fn nearest_rank(percentile: f64, total: u64) -> u64 {
+    (snapped_ceil(percentile * total as f64) as u64).max(1)
+}
+
+// rounds the rank up to a whole number, unless it is within the rounding error
+// of the product it was computed from, in which case that number is taken. the
+// tolerance scales with the rank, so that fractional ranks of large totals are
+// still rounded up
+fn snapped_ceil(rank: f64) -> f64 {
+    let nearest = rank.round();
+    if (rank - nearest).abs() <= rank.abs() * 4.0 * f64::EPSILON {
+        nearest
+    } else {
+        rank.ceil()
+    }
+}
+
+/// The format version of `Histogram::encode()`
+pub const ENCODING_VERSION: u8 = 1;
+
+// the highest precision which decode() accepts. each digit of precision
+// multiplies the number of buckets by roughly 10
+const MAX_ENCODED_PRECISION: u32 = 6;
+
+// the most buckets which decode() allocates, about 32MB of u64 counts, so an
+// untrusted encoding can't exhaust memory with a huge max and precision
+const MAX_DECODED_BUCKETS: usize = 1 << 22;
+
+// the index of the bucket holding the value, for a histogram of the
+// precision whose max is at least the value
+fn bucket_index(value: u64, precision: u32) -> usize {
+    let exact = 10_u64.pow(precision);
+    if value <= exact {
+        value as usize
+    } else {
+        let exact = exact as usize;
+        let power = (value as f64).log10().floor() as u32;
+        let divisor = 10_u64.pow((power - precision) as u32 + 1);
+        let power_offset = (0.9 * f64::from(exact as u32 * (power - precision))) as usize;
+        let remainder = value / divisor as u64;
+        let shift = exact / 10;
+        exact + power_offset + remainder as usize - shift
+    }
+}
+
+/// Error returned when an encoded `Histogram` cannot be decoded
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    // the encoding ended within a varint or before the layout
+    Truncated,
+    // the encoding is of a format version which is not supported
+    UnknownVersion(u8),
+    // the precision is out of range, the layout has too many buckets, or a
+    // bucket is beyond the max
+    InvalidLayout,
+    // a varint exceeds u64, or a count does not fit in the counter type
+    Overflow,
+}
+
+// the precision of a `Histogram::logarithmic`, which bounds the width of each
+// bucket to a tenth of its values
+const LOGARITHMIC_PRECISION: u32 = 2;
+
 /// `Histogram` is inspired by HDRHistogram and stores a counter for `Bucket`s
 /// across a range of input values. `Histogram`s store between 0 and the `max`
 /// value passed to the constructor. Optionally, a `Histogram` may retain
@@ -29,13 +95,69 @@ where
     buckets: Vec<T>,
     index: Vec<AtomicU64>,
     too_high: AtomicU64,
+    // set once any bucket has reached the max of its counter type
+    saturated: AtomicBool,
+    // advanced after each change to the contents, while tracked is set
+    generation: AtomicU64,
+    tracked: AtomicBool,
     precision: AtomicU32,
     samples: Option<Arc<Mutex<VecDeque<Sample<<T as AtomicPrimitive>::Primitive>>>>>,
     window: Option<Arc<Mutex<Duration>>>,
     capacity: Option<AtomicUsize>,
 }
 
+/// A copy of the non-empty `Bucket`s of a `Histogram` at a point in time, see
+/// `Histogram::snapshot()` and `Histogram::drain()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramSnapshot {
+    // nominal value and count of each non-empty bucket, lowest value first
+    buckets: Vec<(u64, u64)>,
+    too_high: u64,
+    max: u64,
+}
+
+impl HistogramSnapshot {
+    /// Returns the nominal value and count of each non-empty `Bucket`, from
+    /// lowest to highest value
+    pub fn buckets(&self) -> &[(u64, u64)] {
+        &self.buckets
+    }
+
+    /// Returns the number of samples which were too high to store in a `Bucket`
+    pub fn too_high(&self) -> u64 {
+        self.too_high
+    }
+
+    /// Returns the total count of all samples, including those too high
+    pub fn total_count(&self) -> u64 {
+        self.buckets
+            .iter()
+            .fold(self.too_high, |total, (_, count)| {
+                total.saturating_add(*count)
+            })
+    }
+
+    /// Returns the nominal value at the percentile specified from 0.0-1.0, as
+    /// `Histogram::percentile()` would have at the time of the snapshot
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        let total = self.total_count();
+        if total == 0 {
+            return None;
+        }
+        let need = nearest_rank(percentile, total);
+        let mut have: u64 = 0;
+        for (value, count) in &self.buckets {
+            have = have.saturating_add(*count);
+            if have >= need {
+                return Some(*value);
+            }
+        }
+        Some(self.max)
+    }
+}
+
 /// Indicates whether the sample was an `Increment` or a `Decrement` operation
+#[derive(Clone)]
 enum Direction {
     Decrement,
     Increment,
@@ -43,6 +165,7 @@ enum Direction {
 
 /// Used to hold samples in the internal buffer, later used to remove expired
 /// or excess samples
+#[derive(Clone)]
 struct Sample<T> {
     value: u64,
     count: T,
@@ -99,6 +222,14 @@ where
     /// beyond which, aged sampled will be removed from the `Histogram`.
     /// Optionally, bound the number of samples stored in the `Histogram` by
     /// specifying the `capacity`.
+    ///
+    /// The number of `Bucket`s, and therefore the memory used, grows with
+    /// each additional digit of `precision` by roughly a factor of 10 and with
+    /// each power of 10 of `max` by a constant `9 * 10^(precision - 1)`. A
+    /// wide range is therefore cheap, while every digit of precision is
+    /// costly. Values above `max` are counted as too high and are reported at
+    /// `max` by the percentile and aggregate queries, so they are clamped to
+    /// the top of the range rather than lost.
     pub fn new(
         max: u64,
         precision: u32,
@@ -111,6 +242,9 @@ where
             buckets: Vec::new(),
             index: Vec::new(),
             too_high: AtomicU64::new(0),
+            saturated: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            tracked: AtomicBool::new(false),
             precision: AtomicU32::new(precision),
             samples: None,
             window: None,
@@ -139,6 +273,41 @@ where
         histogram
     }
 
+    /// Create a new `Histogram` suited to values spanning many orders of
+    /// magnitude, eg: memory or object sizes from bytes to gigabytes. Above
+    /// the first 100 values, each power of 10 is split into 90 `Bucket`s whose
+    /// width is a fixed fraction of their values, so every value is stored
+    /// within 10% whatever its magnitude and the memory used grows only with
+    /// the number of powers of 10 up to `max`. This is the layout of `new()`
+    /// with a `precision` of 2, see there for the `window` and `capacity`.
+    pub fn logarithmic(max: u64, window: Option<Duration>, capacity: Option<usize>) -> Self {
+        Self::new(max, LOGARITHMIC_PRECISION, window, capacity)
+    }
+
+    /// Create a new `Histogram` as with `new()`, without a window or capacity,
+    /// holding one sample of each of the `values`, eg: to replay latencies
+    /// captured from a trace.
+    pub fn from_values(values: &[u64], max: u64, precision: u32) -> Self
+    where
+        <T as AtomicPrimitive>::Primitive: From<u8>,
+    {
+        let histogram: Self = Self::new(max, precision, None, None);
+        for value in values {
+            histogram.increment(*value, <T as AtomicPrimitive>::Primitive::from(1_u8));
+        }
+        histogram
+    }
+
+    /// Returns the maximum value storable in a `Bucket` of the `Histogram`
+    pub fn max(&self) -> u64 {
+        self.max.get()
+    }
+
+    /// Returns the number of digits represented exactly by the `Histogram`
+    pub fn precision(&self) -> u32 {
+        self.precision.get()
+    }
+
     /// Returns the total size of the `Histogram` in bytes
     pub fn size(&self) -> usize {
         let mut total_size = 0;
@@ -160,18 +329,8 @@ where
     fn get_index(&self, value: u64) -> Result<usize, ()> {
         if value > self.max.get() {
             Err(())
-        } else if value <= self.exact.get() {
-            Ok(value as usize)
         } else {
-            let exact = self.exact.get() as usize;
-            let power = (value as f64).log10().floor() as u32;
-            let divisor = 10_u64.pow((power - self.precision.get()) as u32 + 1);
-            let power_offset =
-                (0.9 * f64::from(exact as u32 * (power - self.precision.get()))) as usize;
-            let remainder = value / divisor as u64;
-            let shift = exact / 10;
-            let index = exact + power_offset + remainder as usize - shift;
-            Ok(index)
+            Ok(bucket_index(value, self.precision.get()))
         }
     }
 
@@ -200,7 +359,7 @@ where
     // Internal function to get the non-inclusive maximum for a bucket at index
     fn get_max_value(&self, index: usize) -> Result<u64, ()> {
         if index == self.buckets.len() - 1 {
-            Ok(self.max.get() + 1)
+            Ok(self.max.get().saturating_add(1))
         } else {
             Ok(self.get_min_value(index + 1).unwrap())
         }
@@ -222,22 +381,38 @@ where
 
     // Internal function to get the nominal value of the bucket at index
     fn get_value(&self, index: usize) -> Result<u64, ()> {
-        self.get_max_value(index).map(|v| v - 1)
+        if index == self.buckets.len() - 1 {
+            Ok(self.max.get())
+        } else {
+            self.get_max_value(index).map(|v| v - 1)
+        }
     }
 
-    /// Increment the `Bucket` holding `value` by `count`
+    /// Increment the `Bucket` holding `value` by `count`. The count of a
+    /// `Bucket` saturates at the max of the counter type rather than wrapping,
+    /// see `saturated()`.
     pub fn increment(&self, value: u64, count: <T as AtomicPrimitive>::Primitive) {
         match self.get_index(value) {
             Ok(index) => {
-                self.buckets[index].saturating_add(count);
+                // the index is updated first so that it always includes the
+                // count of each bucket when drain() subtracts it
                 self.index[index / 100].saturating_add(u64::from(count));
+                let previous = self.buckets[index].saturating_add(count);
+                let added = previous.saturating_add(count).saturating_sub(previous);
+                if u64::from(added) < u64::from(count) {
+                    // the excess which the bucket could not hold is removed
+                    // from the index so that the two stay consistent
+                    self.saturated.store(true, Ordering::Relaxed);
+                    self.index[index / 100].saturating_sub(u64::from(count) - u64::from(added));
+                }
                 if let Some(samples) = &self.samples {
                     let time = Instant::now();
                     self.trim(time);
                     let mut samples = samples.lock();
+                    // only the count which was stored expires from the bucket
                     samples.push_back(Sample {
                         value,
-                        count,
+                        count: added,
                         time,
                         direction: Direction::Increment,
                     });
@@ -247,6 +422,21 @@ where
                 self.too_high.saturating_add(u64::from(count));
             }
         }
+        self.advance();
+    }
+
+    /// Adds the counts from another `Histogram` into this one. Each `Bucket` of
+    /// the other `Histogram` is recorded at its nominal value, so the result is
+    /// bounded by the precision of both. Samples which were too high for the
+    /// other `Histogram` are counted as too high for this one.
+    pub fn merge(&self, other: &Histogram<T>) {
+        for bucket in other {
+            if u64::from(bucket.count()) > 0 {
+                self.increment(bucket.value(), bucket.count());
+            }
+        }
+        self.too_high.saturating_add(other.too_high());
+        self.advance();
     }
 
     /// Decrement the `Bucket` holding `value` by `count`
@@ -271,6 +461,7 @@ where
                 self.too_high.saturating_sub(u64::from(count));
             }
         }
+        self.advance();
     }
 
     /// Clears all `Bucket`s within the `Histogram`
@@ -286,6 +477,76 @@ where
             self.index[i].set(0);
         }
         self.too_high.set(0);
+        self.advance();
+    }
+
+    /// Clears the `Bucket`s holding only values below the `threshold`, eg: to
+    /// discard samples recorded during warmup. A `Bucket` which also holds
+    /// values at or above the `threshold` is kept, so with reduced precision
+    /// some samples just below it may remain. The total count is reduced by
+    /// the samples removed, and samples which were too high are kept.
+    pub fn clear_below(&self, threshold: u64) {
+        let mut end = 0;
+        while end < self.buckets.len() && self.get_max_value(end).unwrap() <= threshold {
+            end += 1;
+        }
+        if let Some(samples) = &self.samples {
+            // the retained samples of cleared buckets must not be removed
+            // from the buckets again when they expire
+            samples
+                .lock()
+                .retain(|sample| match self.get_index(sample.value) {
+                    Ok(index) => index >= end,
+                    Err(_) => true,
+                });
+        }
+        for i in 0..end {
+            let count = u64::from(self.buckets[i].swap(
+                <T as AtomicPrimitive>::Primitive::default(),
+                Ordering::SeqCst,
+            ));
+            if count > 0 {
+                self.index[i / 100].saturating_sub(count);
+            }
+        }
+        self.advance();
+    }
+
+    /// Returns a copy of the non-empty `Bucket`s of the `Histogram`
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: self.buckets().collect(),
+            too_high: self.too_high(),
+            max: self.max.get(),
+        }
+    }
+
+    /// Clears the `Histogram`, returning a snapshot of exactly the samples
+    /// which were removed. Each `Bucket` is swapped with zero, so a sample
+    /// recorded concurrently is either included in the snapshot or remains in
+    /// the `Histogram` and is never lost or counted twice.
+    pub fn drain(&self) -> HistogramSnapshot {
+        if let Some(samples) = &self.samples {
+            samples.lock().clear();
+        }
+        let mut buckets = Vec::new();
+        for i in 0..self.buckets.len() {
+            let count = u64::from(self.buckets[i].swap(
+                <T as AtomicPrimitive>::Primitive::default(),
+                Ordering::SeqCst,
+            ));
+            if count > 0 {
+                self.index[i / 100].saturating_sub(count);
+                buckets.push((self.get_value(i).unwrap(), count));
+            }
+        }
+        let snapshot = HistogramSnapshot {
+            buckets,
+            too_high: self.too_high.swap(0, Ordering::SeqCst),
+            max: self.max.get(),
+        };
+        self.advance();
+        snapshot
     }
 
     // Internal function to remove expired and/or excess samples
@@ -297,11 +558,12 @@ where
                 while let Some(sample) = samples.pop_front() {
                     let age = time - sample.time;
                     if age > window {
+                        self.advance();
                         match self.get_index(sample.value) {
                             Ok(index) => match sample.direction {
                                 Direction::Decrement => {
-                                    self.buckets[index].saturating_add(sample.count);
                                     self.index[index / 100].saturating_add(u64::from(sample.count));
+                                    self.buckets[index].saturating_add(sample.count);
                                 }
                                 Direction::Increment => {
                                     self.buckets[index].saturating_sub(sample.count);
@@ -332,8 +594,8 @@ where
                         match self.get_index(sample.value) {
                             Ok(index) => match sample.direction {
                                 Direction::Decrement => {
-                                    self.buckets[index].saturating_add(sample.count);
                                     self.index[index / 100].saturating_add(u64::from(sample.count));
+                                    self.buckets[index].saturating_add(sample.count);
                                 }
                                 Direction::Increment => {
                                     self.buckets[index].saturating_sub(sample.count);
@@ -356,46 +618,267 @@ where
         }
     }
 
+    /// Returns a number which changes whenever the contents of the `Histogram`
+    /// change, eg: to cache values derived from it, or `None` unless enabled
+    /// with `track_generation()`. Samples which have aged out of the `window`
+    /// are removed first, so the generation also reflects their expiry.
+    pub fn generation(&self) -> Option<u64> {
+        if !self.tracked.load(Ordering::Acquire) {
+            return None;
+        }
+        if self.samples.is_some() {
+            self.trim(Instant::now());
+        }
+        Some(self.generation.load(Ordering::Acquire))
+    }
+
+    /// Sets whether the generation is tracked, see `generation()`. Tracking
+    /// adds an update of a shared word to each change of the contents, so it
+    /// is disabled by default. Enabling it changes the generation, so values
+    /// derived before it was enabled are not mistaken for current ones.
+    pub fn track_generation(&self, enabled: bool) {
+        self.tracked.store(enabled, Ordering::Release);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    // marks a change to the contents, if the generation is tracked. this
+    // follows the change, so a reader which sees the previous generation may
+    // not have seen the change, but one which sees the new generation has
+    fn advance(&self) {
+        if self.tracked.load(Ordering::Relaxed) {
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Returns true if the count of any `Bucket` has reached the max of the
+    /// counter type, in which case further samples in that `Bucket` were not
+    /// counted and the percentiles are skewed towards other values. This is a
+    /// sign that a wider counter type is needed, so once set it is kept when
+    /// the `Histogram` is cleared.
+    pub fn saturated(&self) -> bool {
+        self.saturated.load(Ordering::Relaxed)
+    }
+
     /// Returns the total count for all values in the `Histogram`
     pub fn total_count(&self) -> u64 {
         if self.samples.is_some() {
             let time = Instant::now();
             self.trim(time);
         }
-        let mut total = 0;
+        let mut total: u64 = 0;
         for i in 0..self.index.len() {
-            total += self.index[i].get();
+            total = total.saturating_add(self.index[i].get());
         }
-        total += self.too_high.get();
-        total
+        total.saturating_add(self.too_high.get())
     }
 
-    /// Returns the nominal value at the percentile specified from 0.0-1.0
+    /// Returns true if the `Histogram` holds no samples, including those which
+    /// were too high
+    pub fn is_empty(&self) -> bool {
+        self.total_count() == 0
+    }
+
+    /// Returns the nominal value at the percentile specified from 0.0-1.0. The
+    /// result never exceeds the `max` of the `Histogram`, which is returned for
+    /// samples that were too high to store in a `Bucket`.
+    ///
+    /// The percentile is taken by nearest rank: the result is the value of the
+    /// first `Bucket` whose cumulative count, from the lowest, meets or exceeds
+    /// `ceil(percentile * total_count)`, and at least 1. So the p50 of an even
+    /// number of samples is the lower of the two middle samples, and the p0 is
+    /// the lowest sample. A rank within rounding error of a whole number is
+    /// taken as that number, eg: the p30 of 10 samples is the 3rd although
+    /// `0.3 * 10.0` is slightly above 3, so percentiles which are equal but
+    /// for how they were computed return the same value. Every percentile
+    /// query of the `Histogram` and its snapshots follows the same rule.
     pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        self.percentile_with_count(percentile)
+            .map(|(value, _)| value)
+    }
+
+    /// Returns the fraction of samples, from 0.0-1.0, which are in the `Bucket`
+    /// holding `value` or a lower one, eg: the fraction of requests which were
+    /// at least as fast as `value`. This is the inverse of `percentile()`
+    /// within the width of a `Bucket`, so `percentile(rank(value))` returns
+    /// the nominal value of the `Bucket` holding `value`. Values at or above
+    /// the `max` rank at 1.0, including the samples which were too high.
+    /// Returns `None` if the `Histogram` is empty.
+    pub fn rank(&self, value: u64) -> Option<f64> {
+        let total = self.total_count();
+        if total == 0 {
+            return None;
+        }
+        if value >= self.max() {
+            return Some(1.0);
+        }
+        let mut have: u64 = 0;
+        for bucket in self {
+            if bucket.min() > value {
+                break;
+            }
+            have = have.saturating_add(u64::from(bucket.count()));
+        }
+        Some(have as f64 / total as f64)
+    }
+
+    /// Returns the relative error of the value reported for the percentile
+    /// specified from 0.0-1.0 by `percentile()`, as the half-width of the
+    /// `Bucket` holding that percentile divided by the midpoint of its range.
+    /// Values within the exact range of the `precision` have no error, above
+    /// it the error is at most about `5 / 10^precision`, eg: 0.5% with a
+    /// precision of 3. Returns `None` if the `Histogram` is empty or the
+    /// percentile falls among the samples which were too high, whose error is
+    /// unbounded.
+    pub fn percentile_error(&self, percentile: f64) -> Option<f64> {
+        let total = self.total_count();
+        if total == 0 {
+            return None;
+        }
+        let need = nearest_rank(percentile, total);
+        let mut have: u64 = 0;
+        for bucket in self {
+            have = have.saturating_add(u64::from(bucket.count()));
+            if have >= need {
+                // the values the bucket holds, inclusive of both ends
+                let low = bucket.min() as f64;
+                let high = (bucket.max() - 1) as f64;
+                let midpoint = (low + high) / 2.0;
+                if midpoint == 0.0 {
+                    return Some(0.0);
+                }
+                return Some((high - low) / 2.0 / midpoint);
+            }
+        }
+        None
+    }
+
+    /// Returns the lowest and highest values, inclusive, of the `Bucket`
+    /// holding the percentile specified from 0.0-1.0, eg: to report that the
+    /// p99 is between 9ms and 11ms. The high edge is the value reported by
+    /// `percentile()`, and the `Bucket` at the top of the range and the
+    /// samples which were too high both span only the `max`. Returns `None` if
+    /// the `Histogram` is empty.
+    pub fn percentile_bucket(&self, percentile: f64) -> Option<(u64, u64)> {
+        let total = self.total_count();
+        if total == 0 {
+            return None;
+        }
+        let need = nearest_rank(percentile, total);
+        let mut have: u64 = 0;
+        for bucket in self {
+            have = have.saturating_add(u64::from(bucket.count()));
+            if have >= need {
+                let high = (bucket.max() - 1).min(self.max());
+                return Some((bucket.min().min(high), high));
+            }
+        }
+        Some((self.max(), self.max()))
+    }
+
+    /// Returns the nominal value at the percentile specified from 0.0-1.0, as
+    /// from `percentile()`, along with the count of samples in the `Bucket`
+    /// holding that value and all lower `Bucket`s. When the percentile falls
+    /// among the samples which were too high, the count is the total count.
+    pub fn percentile_with_count(&self, percentile: f64) -> Option<(u64, u64)> {
         let total = self.total_count();
         if total == 0 {
             None
         } else {
-            let mut need = (percentile * total as f64).ceil() as u64;
-            if need == 0 {
-                need = 1;
-            }
-            let mut have = 0;
-            for i in 0..self.index.len() {
-                let count = self.index[i].get();
-                if have + count >= need {
-                    let index = i * 100;
-                    for j in index..(index + 100) {
-                        have += u64::from(self.buckets[j].get());
-                        if have >= need {
-                            return Some(self.get_value(j).unwrap());
-                        }
+            Some(self.value_at_count(nearest_rank(percentile, total), total))
+        }
+    }
+
+    /// Returns the nominal value at the percentile specified in basis points
+    /// out of 10_000, eg: 9_990 for the p99.9, as from `percentile()`. The
+    /// rank of the percentile is computed with integer arithmetic, so it is
+    /// exact for any count of samples and free of the rounding of `f64`
+    /// percentiles, eg: where `0.07 * 100.0` is slightly above 7. Basis points
+    /// above 10_000 are taken as 10_000.
+    pub fn percentile_bp(&self, bp: u32) -> Option<u64> {
+        let total = self.total_count();
+        if total == 0 {
+            None
+        } else {
+            let bp = u128::from(bp.min(10_000));
+            let need = (bp * u128::from(total)).div_ceil(10_000) as u64;
+            Some(self.value_at_count(need, total).0)
+        }
+    }
+
+    // returns the nominal value of the sample at the rank of need, counting
+    // from 1, and the count of samples up to and including its bucket
+    fn value_at_count(&self, need: u64, total: u64) -> (u64, u64) {
+        let need = need.max(1);
+        let mut have: u64 = 0;
+        for i in 0..self.index.len() {
+            let count = self.index[i].get();
+            if have.saturating_add(count) >= need {
+                let index = i * 100;
+                for j in index..(index + 100).min(self.buckets.len()) {
+                    have = have.saturating_add(u64::from(self.buckets[j].get()));
+                    if have >= need {
+                        return (self.get_value(j).unwrap(), have);
                     }
                 }
-                have += count;
             }
-            Some(self.max.get())
+            have = have.saturating_add(count);
         }
+        (self.max.get(), total)
+    }
+
+    /// Returns the value at the percentile specified from 0.0-1.0, linearly
+    /// interpolated within the `Bucket` holding it by how far into the count
+    /// of that `Bucket` the percentile falls. Unlike `percentile()`, which
+    /// steps between nominal values, the result changes smoothly with the
+    /// distribution. It never falls outside the range of the `Bucket`, nor
+    /// above its nominal value, so the lowest and highest values are bounded
+    /// as with `percentile()`. Samples which were too high to store in a
+    /// `Bucket` are reported at the `max`.
+    pub fn percentile_interpolated(&self, percentile: f64) -> Option<f64> {
+        let total = self.total_count();
+        if total == 0 {
+            return None;
+        }
+        let rank = percentile * total as f64;
+        let mut have: u64 = 0;
+        for bucket in self.into_iter() {
+            let count = u64::from(bucket.count());
+            if count == 0 {
+                continue;
+            }
+            if have.saturating_add(count) as f64 >= rank {
+                let fraction = ((rank - have as f64) / count as f64).max(0.0);
+                let value = bucket.min() as f64 + fraction * bucket.width() as f64;
+                return Some(value.min(bucket.value() as f64));
+            }
+            have = have.saturating_add(count);
+        }
+        Some(self.max.get() as f64)
+    }
+
+    /// Returns the nominal value at the percentile specified from 0.0-1.0 where
+    /// each `Bucket` is weighted by its total magnitude (value * count) rather
+    /// than by its count. Samples which were too high to store in a `Bucket`
+    /// are weighted as if they were recorded at the `max` of the `Histogram`.
+    pub fn weighted_percentile(&self, percentile: f64) -> Option<u64> {
+        let mut total = u128::from(self.too_high()) * u128::from(self.max.get());
+        for bucket in self.into_iter() {
+            total = total
+                .saturating_add(u128::from(bucket.value()) * u128::from(u64::from(bucket.count())));
+        }
+        if total == 0 {
+            return None;
+        }
+        let need = (snapped_ceil(percentile * total as f64) as u128).max(1);
+        let mut have: u128 = 0;
+        for bucket in self.into_iter() {
+            have = have
+                .saturating_add(u128::from(bucket.value()) * u128::from(u64::from(bucket.count())));
+            if have >= need {
+                return Some(bucket.value());
+            }
+        }
+        Some(self.max.get())
     }
 
     /// Return the number of samples which were too high to store in a `Bucket`
@@ -403,13 +886,132 @@ where
         self.too_high.get()
     }
 
-    /// Returns the approximate mean of all values in the `Histogram`
-    pub fn mean(&self) -> u64 {
-        let mut result = 0;
+    /// Returns the nominal value at each of the percentiles specified from
+    /// 0.0-1.0, in the order they were given. The result for each percentile
+    /// is the same as from `percentile()`, but all percentiles are found in a
+    /// single pass over the buckets.
+    pub fn percentiles(&self, percentiles: &[f64]) -> Vec<Option<u64>> {
+        self.percentiles_read(percentiles).0
+    }
+
+    // finds the percentiles as with percentiles(), along with the number of
+    // buckets which were read to find them
+    fn percentiles_read(&self, percentiles: &[f64]) -> (Vec<Option<u64>>, usize) {
+        let total = self.total_count();
+        if total == 0 {
+            return (vec![None; percentiles.len()], 0);
+        }
+        let mut needs: Vec<(usize, u64)> = percentiles
+            .iter()
+            .enumerate()
+            .map(|(i, percentile)| (i, nearest_rank(*percentile, total)))
+            .collect();
+        needs.sort_by_key(|(_, need)| *need);
+        let mut result = vec![Some(self.max.get()); percentiles.len()];
+        let mut next = 0;
+        let mut have: u64 = 0;
+        let mut read = 0;
+        for i in 0..self.index.len() {
+            if next == needs.len() {
+                break;
+            }
+            let count = self.index[i].get();
+            if have.saturating_add(count) >= needs[next].1 {
+                let index = i * 100;
+                for j in index..(index + 100).min(self.buckets.len()) {
+                    read += 1;
+                    have = have.saturating_add(u64::from(self.buckets[j].get()));
+                    while next < needs.len() && have >= needs[next].1 {
+                        result[needs[next].0] = Some(self.get_value(j).unwrap());
+                        next += 1;
+                    }
+                }
+            } else {
+                have = have.saturating_add(count);
+            }
+        }
+        (result, read)
+    }
+
+    /// Returns an iterator over the nominal value and count of each non-empty
+    /// `Bucket`, from lowest to highest value. Samples which were too high to
+    /// store in a `Bucket` are not included, see `too_high()`.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.into_iter().filter_map(|bucket| {
+            let count = u64::from(bucket.count());
+            if count > 0 {
+                Some((bucket.value(), count))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns at most `target_buckets` pairs of nominal value and count, made
+    /// by merging runs of adjacent non-empty `Bucket`s, eg: for a compact
+    /// export. Each run holds roughly an equal share of the samples and is
+    /// reported at the value of its highest `Bucket`, so the total count is
+    /// preserved and a percentile at a multiple of `1 / target_buckets` has
+    /// the same value as from `percentile()`. Samples which were too high to
+    /// store in a `Bucket` are included at the `max`. Returns an empty `Vec`
+    /// if `target_buckets` is zero.
+    pub fn resample(&self, target_buckets: usize) -> Vec<(u64, u64)> {
+        if target_buckets == 0 {
+            return Vec::new();
+        }
+        let mut buckets: Vec<(u64, u64)> = self.buckets().collect();
+        let too_high = self.too_high();
+        if too_high > 0 {
+            buckets.push((self.max.get(), too_high));
+        }
+        if buckets.len() <= target_buckets {
+            return buckets;
+        }
+        let total: u128 = buckets.iter().map(|(_, count)| u128::from(*count)).sum();
+        let target = target_buckets as u128;
+        let mut resampled = Vec::with_capacity(target_buckets);
+        let mut have: u128 = 0;
+        let mut count: u64 = 0;
+        for (value, bucket_count) in buckets {
+            have += u128::from(bucket_count);
+            count = count.saturating_add(bucket_count);
+            // close the run once it reaches the next equal share boundary
+            let boundary = (resampled.len() as u128 + 1) * total;
+            if have * target >= boundary {
+                resampled.push((value, count));
+                count = 0;
+            }
+        }
+        resampled
+    }
+
+    /// Returns the approximate mean of all values in the `Histogram`, or `None`
+    /// if the `Histogram` is empty. Samples which were too high to store in a
+    /// `Bucket` are counted as if they were recorded at the `max`.
+    pub fn mean(&self) -> Option<f64> {
+        let total = self.total_count();
+        if total == 0 {
+            return None;
+        }
+        let mut sum = u128::from(self.too_high()) * u128::from(self.max.get());
+        for bucket in self.into_iter() {
+            sum =
+                sum.saturating_add(u128::from(u64::from(bucket.count)) * u128::from(bucket.value));
+        }
+        Some(sum as f64 / total as f64)
+    }
+
+    /// Returns the approximate population standard deviation of all values in
+    /// the `Histogram`, or `None` if the `Histogram` is empty. Samples which
+    /// were too high to store in a `Bucket` are counted as if they were
+    /// recorded at the `max`.
+    pub fn std_dev(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let mut sum = self.too_high() as f64 * (self.max.get() as f64 - mean).powi(2);
         for bucket in self.into_iter() {
-            result += u64::from(bucket.count) * bucket.value;
+            sum += u64::from(bucket.count) as f64 * (bucket.value as f64 - mean).powi(2);
         }
-        result / self.total_count()
+        Some((sum / self.total_count() as f64).sqrt())
     }
 
     /// Returns the nominal value of the mode of the `Histogram`
@@ -424,6 +1026,149 @@ where
         }
         value
     }
+
+    /// Encodes the layout and counts of the `Histogram` in a compact, portable
+    /// form, eg: to save the histogram of each interval to disk and aggregate
+    /// them later, see `decode()`. The encoding is a format version byte of
+    /// `ENCODING_VERSION`, followed by unsigned LEB128 varints of the `max`,
+    /// the `precision`, and the count of samples which were too high, then for
+    /// each non-empty `Bucket` from lowest to highest a varint of the number of
+    /// empty `Bucket`s since the previous non-empty one, or since the first
+    /// `Bucket`, and a varint of its count. Sparse histograms encode to a few
+    /// bytes per non-empty `Bucket`. This is not the wire format of
+    /// HdrHistogram, though the `max` and `precision` correspond to its
+    /// highest trackable value and significant digits. Retained samples and
+    /// the `window` and `capacity` are not encoded.
+    pub fn encode(&self) -> Vec<u8> {
+        if self.samples.is_some() {
+            self.trim(Instant::now());
+        }
+        let mut encoded = vec![ENCODING_VERSION];
+        write_varint(&mut encoded, self.max());
+        write_varint(&mut encoded, self.precision() as u64);
+        write_varint(&mut encoded, self.too_high());
+        let mut next = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            let count = u64::from(bucket.get());
+            if count > 0 {
+                write_varint(&mut encoded, (index - next) as u64);
+                write_varint(&mut encoded, count);
+                next = index + 1;
+            }
+        }
+        encoded
+    }
+
+    /// Decodes a `Histogram` from the form produced by `encode()`, with the
+    /// same layout and counts. Returns an error if the encoding is truncated
+    /// or malformed, its layout is invalid, or a count does not fit in the
+    /// counter type. Layouts of more than 6 digits of precision or about 4
+    /// million buckets are rejected as invalid, so that a corrupt or hostile
+    /// encoding can't exhaust memory.
+    pub fn decode(encoded: &[u8]) -> Result<Self, DecodeError>
+    where
+        <T as AtomicPrimitive>::Primitive: TryFrom<u64>,
+    {
+        let (version, mut encoded) = encoded.split_first().ok_or(DecodeError::Truncated)?;
+        if *version != ENCODING_VERSION {
+            return Err(DecodeError::UnknownVersion(*version));
+        }
+        let max = read_varint(&mut encoded)?;
+        let precision = read_varint(&mut encoded)?;
+        if precision == 0
+            || precision > MAX_ENCODED_PRECISION as u64
+            || bucket_index(max, precision as u32) >= MAX_DECODED_BUCKETS
+        {
+            return Err(DecodeError::InvalidLayout);
+        }
+        let histogram: Self = Histogram::new(max, precision as u32, None, None);
+        histogram.too_high.set(read_varint(&mut encoded)?);
+        let mut next: u64 = 0;
+        while !encoded.is_empty() {
+            let index = next
+                .checked_add(read_varint(&mut encoded)?)
+                .filter(|index| *index < histogram.buckets.len() as u64)
+                .ok_or(DecodeError::InvalidLayout)? as usize;
+            let count = read_varint(&mut encoded)?;
+            let primitive = <T as AtomicPrimitive>::Primitive::try_from(count)
+                .map_err(|_| DecodeError::Overflow)?;
+            histogram.buckets[index].set(primitive);
+            histogram.index[index / 100].saturating_add(count);
+            next = index as u64 + 1;
+        }
+        Ok(histogram)
+    }
+}
+
+// appends the value as an unsigned LEB128 varint, 7 bits per byte from the
+// lowest, with the high bit set on all but the last byte
+fn write_varint(encoded: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        encoded.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    encoded.push(value as u8);
+}
+
+// reads an unsigned LEB128 varint from the front of the encoding, advancing it
+fn read_varint(encoded: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value: u64 = 0;
+    for (i, byte) in encoded.iter().enumerate() {
+        let shift = 7 * i as u32;
+        let bits = u64::from(byte & 0x7F);
+        if shift >= 64 || (bits << shift) >> shift != bits {
+            return Err(DecodeError::Overflow);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            *encoded = &encoded[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::Truncated)
+}
+
+/// Cloning copies the counts, the retained samples, and the configuration, so
+/// the clone is independent of the original. As with the other queries, a
+/// `Histogram` which is modified while it is cloned may be partially copied.
+impl<T> Clone for Histogram<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating,
+{
+    fn clone(&self) -> Self {
+        // the buckets are copied before the index, which is incremented
+        // first, so that the index includes each copied count
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|bucket| T::new(bucket.get()))
+            .collect();
+        let index = self.index.iter().map(|i| AtomicU64::new(i.get())).collect();
+        Histogram {
+            exact: AtomicU64::new(self.exact.get()),
+            max: AtomicU64::new(self.max.get()),
+            buckets,
+            index,
+            too_high: AtomicU64::new(self.too_high.get()),
+            saturated: AtomicBool::new(self.saturated.load(Ordering::Relaxed)),
+            generation: AtomicU64::new(self.generation.load(Ordering::Acquire)),
+            tracked: AtomicBool::new(self.tracked.load(Ordering::Relaxed)),
+            precision: AtomicU32::new(self.precision.get()),
+            samples: self
+                .samples
+                .as_ref()
+                .map(|samples| Arc::new(Mutex::new(samples.lock().clone()))),
+            window: self
+                .window
+                .as_ref()
+                .map(|window| Arc::new(Mutex::new(*window.lock()))),
+            capacity: self
+                .capacity
+                .as_ref()
+                .map(|capacity| AtomicUsize::new(capacity.get())),
+        }
+    }
 }
 
 pub struct Iter<'a, C>
@@ -478,6 +1223,103 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn clone() {
+        let h = Histogram::<AtomicU64>::new(1_000, 3, Some(Duration::new(60, 0)), Some(10));
+        for i in 1..=10 {
+            h.increment(i, 1);
+        }
+        let clone = h.clone();
+        h.clear();
+        h.increment(500, 1);
+        assert_eq!(clone.total_count(), 10);
+        assert_eq!(clone.percentile(1.0), Some(10));
+        assert_eq!(clone.max(), 1_000);
+        assert_eq!(clone.precision(), 3);
+        // the retained samples are copied, so the capacity is still enforced
+        for i in 11..=15 {
+            clone.increment(i, 1);
+        }
+        assert_eq!(clone.total_count(), 10);
+        assert_eq!(clone.percentile(0.0), Some(6));
+        assert_eq!(h.total_count(), 1);
+    }
+
+    #[test]
+    fn resample() {
+        // the value at the nearest rank of the percentile
+        fn percentile(buckets: &[(u64, u64)], percentile: f64) -> u64 {
+            let total: u64 = buckets.iter().map(|(_, count)| count).sum();
+            let need = ((percentile * total as f64).ceil() as u64).max(1);
+            let mut have = 0;
+            for (value, count) in buckets {
+                have += count;
+                if have >= need {
+                    return *value;
+                }
+            }
+            unreachable!()
+        }
+
+        let h = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        assert!(h.resample(10).is_empty());
+        for value in 1..=10_000 {
+            h.increment(value, 1);
+        }
+        // a skewed tail, including samples which are too high
+        for value in 1..=100 {
+            h.increment(value * 20_000, 1);
+        }
+        let original: Vec<(u64, u64)> = h.buckets().collect();
+        assert!(original.len() > 100);
+        for target in &[1, 10, 100] {
+            let resampled = h.resample(*target);
+            assert!(resampled.len() <= *target);
+            let total: u64 = resampled.iter().map(|(_, count)| count).sum();
+            assert_eq!(total, h.total_count());
+            for p in &[0.5, 0.99] {
+                let value = percentile(&resampled, *p);
+                let expected = h.percentile(*p).unwrap();
+                if *target == 100 {
+                    assert_eq!(value, expected);
+                } else {
+                    assert!(value >= expected);
+                }
+            }
+        }
+        assert_eq!(h.resample(1), vec![(1_000_000, 10_100)]);
+        assert!(h.resample(0).is_empty());
+        assert_eq!(h.resample(10_000).len(), original.len() + 1);
+    }
+
+    #[test]
+    fn percentile_interpolated() {
+        let h = Histogram::<AtomicU64>::new(10_000, 2, None, None);
+        assert_eq!(h.percentile_interpolated(0.5), None);
+        // 100 samples in each of the buckets from 1000 to 1999
+        for value in 1_000..2_000 {
+            h.increment(value, 1);
+        }
+        assert_eq!(h.percentile(0.45), Some(1_499));
+        assert_eq!(h.percentile_interpolated(0.45), Some(1_450.0));
+        assert_eq!(h.percentile(0.41), Some(1_499));
+        assert_eq!(h.percentile_interpolated(0.41), Some(1_410.0));
+        assert_eq!(h.percentile_interpolated(0.5), Some(1_499.0));
+        // bounded by the lowest and highest buckets
+        assert_eq!(h.percentile_interpolated(0.0), Some(1_000.0));
+        assert_eq!(h.percentile_interpolated(1.0), Some(1_999.0));
+        assert_eq!(h.percentile(1.0), Some(1_999));
+
+        // exact buckets do not interpolate
+        let h = Histogram::<AtomicU64>::new(10_000, 2, None, None);
+        h.increment(10, 3);
+        h.increment(20, 1);
+        assert_eq!(h.percentile_interpolated(0.5), Some(10.0));
+        assert_eq!(h.percentile_interpolated(1.0), Some(20.0));
+        h.increment(20_000, 4);
+        assert_eq!(h.percentile_interpolated(0.9), Some(10_000.0));
+    }
+
     #[test]
     fn basic_latched() {
         let h = Histogram::<AtomicU64>::new(100, 3, None, None);
@@ -500,7 +1342,601 @@ mod tests {
         h.clear();
         assert_eq!(h.percentile(0.0), None);
         assert_eq!(h.total_count(), 0);
-        assert_eq!(h.size(), 936);
+        assert_eq!(h.size(), 944);
+    }
+
+    #[test]
+    fn percentiles() {
+        let h = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let percentiles = [0.999, 0.0, 0.5, 0.9, 0.5, 1.0, 0.25, 0.99, 0.9999];
+        assert_eq!(h.percentiles(&percentiles), vec![None; percentiles.len()]);
+        for i in 1..=10_000 {
+            h.increment(i * i % 1_000_003, 1);
+        }
+        h.increment(2_000_000, 5);
+        let expected: Vec<Option<u64>> = percentiles.iter().map(|p| h.percentile(*p)).collect();
+        assert_eq!(h.percentiles(&percentiles), expected);
+        // the percentiles are found in one pass, which reads no bucket twice,
+        // so asking for each of them twice reads no more buckets
+        let (values, read) = h.percentiles_read(&percentiles);
+        assert_eq!(values, expected);
+        assert!(read > 0 && read <= h.buckets.len());
+        let twice = [percentiles, percentiles].concat();
+        assert_eq!(h.percentiles_read(&twice).1, read);
+        assert_eq!(h.percentiles_read(&[0.5]).0, vec![h.percentile(0.5)]);
+        assert_eq!(h.percentiles(&[1.0]), vec![Some(1_000_000)]);
+        assert_eq!(h.percentiles(&[]), Vec::<Option<u64>>::new());
+    }
+
+    #[test]
+    fn percentile_with_count() {
+        let h = Histogram::<AtomicU64>::new(10_000, 3, None, None);
+        assert_eq!(h.percentile_with_count(0.5), None);
+        let values = [(1, 5), (10, 20), (100, 50), (5_000, 20), (9_999, 4)];
+        for (value, count) in &values {
+            h.increment(*value, *count);
+        }
+        h.increment(20_000, 1);
+        for percentile in &[0.0, 0.05, 0.06, 0.25, 0.5, 0.9, 0.95, 0.99, 1.0] {
+            let (value, count) = h.percentile_with_count(*percentile).unwrap();
+            assert_eq!(Some(value), h.percentile(*percentile));
+            let mut expected: u64 = values
+                .iter()
+                .filter(|(v, _)| *v <= value)
+                .map(|(_, c)| c)
+                .sum();
+            if value == h.max() {
+                expected += h.too_high();
+            }
+            assert_eq!(count, expected);
+        }
+        assert_eq!(h.percentile_with_count(0.5), Some((100, 75)));
+        assert_eq!(h.percentile_with_count(1.0), Some((10_000, 100)));
+    }
+
+    #[test]
+    fn rank() {
+        let histogram = Histogram::<AtomicU64>::new(100_000, 3, None, None);
+        assert_eq!(histogram.rank(10), None);
+        for value in 1..=1_000 {
+            histogram.increment(value, 1);
+        }
+        assert_eq!(histogram.rank(0), Some(0.0));
+        assert_eq!(histogram.rank(1), Some(0.001));
+        assert_eq!(histogram.rank(250), Some(0.25));
+        assert_eq!(histogram.rank(100_000), Some(1.0));
+        for value in &[1, 10, 250, 999] {
+            let rank = histogram.rank(*value).unwrap();
+            assert_eq!(histogram.percentile(rank), Some(*value));
+        }
+        // above the exact range, values rank as their whole bucket
+        histogram.increment(10_050, 1_000);
+        assert_eq!(histogram.rank(10_000), Some(1.0));
+        assert_eq!(histogram.rank(9_999), Some(0.5));
+        assert_eq!(
+            histogram.percentile(histogram.rank(10_000).unwrap()),
+            Some(10_099)
+        );
+        histogram.increment(200_000, 2_000);
+        assert_eq!(histogram.rank(10_000), Some(0.5));
+        assert_eq!(histogram.rank(u64::MAX), Some(1.0));
+    }
+
+    #[test]
+    fn percentile_error() {
+        let errors: Vec<f64> = [2, 3, 4]
+            .iter()
+            .map(|precision| {
+                let histogram = Histogram::<AtomicU64>::new(1_000_000, *precision, None, None);
+                assert_eq!(histogram.percentile_error(0.99), None);
+                histogram.increment(5, 1);
+                // exactly stored values have no error
+                assert_eq!(histogram.percentile_error(0.0), Some(0.0));
+                histogram.increment(123_456, 99);
+                histogram.percentile_error(0.99).unwrap()
+            })
+            .collect();
+        assert!(errors[0] > errors[1] && errors[1] > errors[2]);
+        // the bucket from 123_000 to 123_999 at a precision of 3
+        assert!((errors[1] - 499.5 / 123_499.5).abs() < 1e-12);
+        for (error, bound) in errors.iter().zip(&[0.05, 0.005, 0.0005]) {
+            assert!(error < bound);
+        }
+
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        histogram.increment(2_000, 1);
+        assert_eq!(histogram.percentile_error(1.0), None);
+    }
+
+    #[test]
+    fn percentile_bucket() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        assert_eq!(histogram.percentile_bucket(0.5), None);
+        histogram.increment(5, 1);
+        histogram.increment(9_876, 98);
+        histogram.increment(1_000_000, 1);
+        // exactly stored values span one value
+        assert_eq!(histogram.percentile_bucket(0.0), Some((5, 5)));
+        assert_eq!(histogram.percentile_bucket(0.5), Some((9_870, 9_879)));
+        assert_eq!(
+            histogram.percentile_bucket(1.0),
+            Some((1_000_000, 1_000_000))
+        );
+        for percentile in &[0.0, 0.01, 0.5, 0.99, 1.0] {
+            let (low, high) = histogram.percentile_bucket(*percentile).unwrap();
+            let value = histogram.percentile(*percentile).unwrap();
+            assert!(low <= value && value <= high);
+            assert_eq!(high, value);
+        }
+
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        histogram.increment(2_000, 1);
+        assert_eq!(histogram.percentile_bucket(1.0), Some((1_000, 1_000)));
+    }
+
+    #[test]
+    fn percentile_bp() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        assert_eq!(histogram.percentile_bp(5_000), None);
+        for value in 1..=10_000 {
+            histogram.increment(value * 7, 1);
+        }
+        for bp in &[
+            0, 1, 10, 100, 2_500, 5_000, 9_000, 9_900, 9_990, 9_999, 10_000,
+        ] {
+            assert_eq!(
+                histogram.percentile_bp(*bp),
+                histogram.percentile(f64::from(*bp) / 10_000.0)
+            );
+        }
+        assert_eq!(histogram.percentile_bp(20_000), histogram.percentile(1.0));
+
+        // 0.07 * 100.0 rounds above 7, but is taken as the 7th sample
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        for value in 1..=100 {
+            histogram.increment(value, 1);
+        }
+        assert_eq!(histogram.percentile_bp(700), Some(7));
+        assert_eq!(histogram.percentile(0.07), Some(7));
+    }
+
+    #[test]
+    fn percentile_ties() {
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        for value in 1..=10 {
+            histogram.increment(value, 1);
+        }
+        // the p50 of an even number of samples is the lower middle sample, and
+        // any percentile above it takes the next
+        assert_eq!(histogram.percentile(0.5), Some(5));
+        assert_eq!(histogram.percentile(0.500_001), Some(6));
+        assert_eq!(histogram.percentile(0.499_999), Some(5));
+        assert_eq!(histogram.percentile(0.0), Some(1));
+        assert_eq!(histogram.percentile(0.1), Some(1));
+        assert_eq!(histogram.percentile(1.0), Some(10));
+        // ranks which are whole but for rounding, 0.3 * 10.0 and 0.7 * 10.0
+        assert_eq!(histogram.percentile(0.3), Some(3));
+        assert_eq!(histogram.percentile(0.1 + 0.2), Some(3));
+        assert_eq!(histogram.percentile(0.7), Some(7));
+        assert_eq!(histogram.percentile_bp(3_000), Some(3));
+
+        // every query follows the same rule
+        let percentiles = [0.0, 0.1, 0.3, 0.5, 0.500_001, 0.7, 0.1 + 0.2, 1.0];
+        let snapshot = histogram.snapshot();
+        let all = histogram.percentiles(&percentiles);
+        for (percentile, value) in percentiles.iter().zip(all) {
+            assert_eq!(histogram.percentile(*percentile), value);
+            assert_eq!(snapshot.percentile(*percentile), value);
+            assert_eq!(
+                histogram
+                    .percentile_bucket(*percentile)
+                    .map(|(_, high)| high),
+                value
+            );
+        }
+
+        // the same holds within a bucket of several values
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        histogram.increment(10_000, 2);
+        histogram.increment(20_000, 2);
+        assert_eq!(histogram.percentile(0.5), Some(10_099));
+        assert_eq!(histogram.percentile(0.51), Some(20_099));
+    }
+
+    #[test]
+    fn percentile_large_total() {
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        histogram.increment(1, 500_000_000);
+        histogram.increment(2, 4_500_000_001);
+        assert_eq!(histogram.total_count(), 5_000_000_001);
+        // the rank of p10 is 500_000_000.1, which is rounded up to a sample of
+        // the second value
+        assert_eq!(nearest_rank(0.1, 5_000_000_001), 500_000_001);
+        assert_eq!(histogram.percentile(0.1), Some(2));
+        assert_eq!(histogram.snapshot().percentile(0.1), Some(2));
+        assert_eq!(histogram.percentiles(&[0.1]), vec![Some(2)]);
+        // while a whole rank is taken as it is
+        assert_eq!(nearest_rank(0.5, 10_000_000_000), 5_000_000_000);
+        assert_eq!(nearest_rank(0.3, 10_000_000_000), 3_000_000_000);
+    }
+
+    #[test]
+    fn encode() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        for value in 1..=1_000 {
+            histogram.increment(value * value % 999_983, value);
+        }
+        histogram.increment(5_000_000, 3);
+        let encoded = histogram.encode();
+        assert_eq!(encoded[0], ENCODING_VERSION);
+        let decoded = Histogram::<AtomicU64>::decode(&encoded).unwrap();
+        assert_eq!(decoded.max(), 1_000_000);
+        assert_eq!(decoded.precision(), 3);
+        assert_eq!(decoded.size(), histogram.size());
+        assert_eq!(decoded.snapshot(), histogram.snapshot());
+        assert_eq!(decoded.total_count(), histogram.total_count());
+        assert_eq!(decoded.percentile(0.5), histogram.percentile(0.5));
+        assert_eq!(decoded.encode(), encoded);
+
+        // an empty histogram encodes only its layout
+        let empty = Histogram::<AtomicU8>::new(1_000, 2, None, None);
+        assert_eq!(empty.encode(), vec![ENCODING_VERSION, 0xE8, 0x07, 2, 0]);
+        let decoded = Histogram::<AtomicU8>::decode(&empty.encode()).unwrap();
+        assert!(decoded.is_empty());
+        assert_eq!(decoded.max(), 1_000);
+    }
+
+    #[test]
+    fn decode_errors() {
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        histogram.increment(10, 300);
+        let encoded = histogram.encode();
+        assert_eq!(encoded.len(), 8);
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&[]).err(),
+            Some(DecodeError::Truncated)
+        );
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&encoded[..7]).err(),
+            Some(DecodeError::Truncated)
+        );
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&[2]).err(),
+            Some(DecodeError::UnknownVersion(2))
+        );
+        // a count too large for the counter type
+        assert_eq!(
+            Histogram::<AtomicU8>::decode(&encoded).err(),
+            Some(DecodeError::Overflow)
+        );
+        // a precision of zero, and a bucket beyond the max
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&[ENCODING_VERSION, 10, 0, 0]).err(),
+            Some(DecodeError::InvalidLayout)
+        );
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&[ENCODING_VERSION, 10, 1, 0, 20, 1]).err(),
+            Some(DecodeError::InvalidLayout)
+        );
+        // layouts too large to allocate, with a max of 1e9 at precision 9 and
+        // of u64::MAX at precision 6
+        let mut huge = vec![ENCODING_VERSION];
+        write_varint(&mut huge, 1_000_000_000);
+        huge.extend_from_slice(&[9, 0]);
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&huge).err(),
+            Some(DecodeError::InvalidLayout)
+        );
+        let mut huge = vec![ENCODING_VERSION];
+        write_varint(&mut huge, u64::MAX);
+        huge.extend_from_slice(&[6, 0]);
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&huge).err(),
+            Some(DecodeError::InvalidLayout)
+        );
+        // while a wide range at a modest precision is accepted
+        let wide = Histogram::<AtomicU64>::new(u64::MAX, 3, None, None);
+        wide.increment(u64::MAX - 1, 2);
+        let decoded = Histogram::<AtomicU64>::decode(&wide.encode()).unwrap();
+        assert_eq!(decoded.percentile(1.0), wide.percentile(1.0));
+
+        let mut long = vec![ENCODING_VERSION];
+        long.extend_from_slice(&[0xFF; 10]);
+        long.push(1);
+        assert_eq!(
+            Histogram::<AtomicU64>::decode(&long).err(),
+            Some(DecodeError::Overflow)
+        );
+    }
+
+    #[test]
+    fn generation() {
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        assert_eq!(histogram.generation(), None);
+        histogram.increment(10, 1);
+        assert_eq!(histogram.generation(), None);
+        histogram.track_generation(true);
+        let mut previous = histogram.generation();
+        let mut changed = |histogram: &Histogram<AtomicU64>| {
+            let generation = histogram.generation();
+            let changed = generation != previous;
+            previous = generation;
+            changed
+        };
+        assert!(!changed(&histogram));
+        histogram.increment(10, 1);
+        assert!(changed(&histogram));
+        let _ = histogram.percentile(0.5);
+        assert!(!changed(&histogram));
+        histogram.decrement(10, 1);
+        assert!(changed(&histogram));
+        histogram.increment(2_000, 1);
+        assert!(changed(&histogram));
+        histogram.clear_below(100);
+        assert!(changed(&histogram));
+        let _ = histogram.drain();
+        assert!(changed(&histogram));
+        histogram.clear();
+        assert!(changed(&histogram));
+        // a clone keeps tracking
+        assert!(histogram.clone().generation().is_some());
+        histogram.track_generation(false);
+        assert_eq!(histogram.generation(), None);
+
+        // expiry of samples from the window is a change
+        let windowed = Histogram::<AtomicU64>::new(1_000, 3, Some(Duration::from_millis(1)), None);
+        windowed.track_generation(true);
+        windowed.increment(10, 1);
+        let generation = windowed.generation();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_ne!(windowed.generation(), generation);
+        assert!(windowed.is_empty());
+    }
+
+    #[test]
+    fn is_empty() {
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        assert!(histogram.is_empty());
+        histogram.increment(2_000, 1);
+        assert!(!histogram.is_empty());
+        histogram.clear();
+        assert!(histogram.is_empty());
+    }
+
+    #[test]
+    fn saturated() {
+        let histogram = Histogram::<AtomicU8>::new(1_000, 3, None, None);
+        histogram.increment(10, 200);
+        histogram.increment(20, 1);
+        assert!(!histogram.saturated());
+        histogram.increment(10, 100);
+        histogram.increment(10, 1);
+        assert!(histogram.saturated());
+        let bucket = histogram.into_iter().find(|bucket| bucket.value() == 10);
+        assert_eq!(bucket.map(|bucket| bucket.count()), Some(255));
+        // the total only includes the counts the buckets hold
+        assert_eq!(histogram.total_count(), 256);
+        assert_eq!(histogram.percentile(1.0), Some(20));
+        histogram.clear();
+        assert!(histogram.saturated());
+    }
+
+    #[test]
+    fn saturated_windowed() {
+        let histogram =
+            Histogram::<AtomicU8>::new(1_000, 3, Some(Duration::from_secs(60)), Some(2));
+        histogram.increment(10, 200);
+        histogram.increment(10, 100);
+        histogram.increment(20, 5);
+        histogram.increment(30, 1);
+        histogram.increment(40, 1);
+        assert!(histogram.saturated());
+        // evicting both samples of 10 removes only the 255 it held, and the
+        // sample of 20 is evicted when counting
+        assert_eq!(histogram.total_count(), 2);
+        let bucket = histogram.into_iter().find(|bucket| bucket.value() == 10);
+        assert_eq!(bucket.map(|bucket| bucket.count()), Some(0));
+        assert_eq!(histogram.percentile(0.0), Some(30));
+    }
+
+    #[test]
+    fn clear_below() {
+        let histogram = Histogram::<AtomicU64>::new(100_000, 3, None, None);
+        for value in &[1, 5, 999, 1_000, 10_000, 10_050, 10_100] {
+            histogram.increment(*value, 2);
+        }
+        histogram.increment(200_000, 1);
+        assert_eq!(histogram.total_count(), 15);
+        histogram.clear_below(1_000);
+        assert_eq!(histogram.total_count(), 9);
+        assert_eq!(histogram.percentile(0.0), Some(1_009));
+        // 10_050 shares a bucket with 10_000, so it is kept
+        histogram.clear_below(10_050);
+        assert_eq!(histogram.total_count(), 7);
+        assert_eq!(histogram.percentile(0.0), Some(10_099));
+        assert_eq!(histogram.percentile(0.5), Some(10_099));
+        assert_eq!(histogram.percentile(0.8), Some(10_199));
+        assert_eq!(histogram.too_high(), 1);
+        // a threshold above the max clears every bucket
+        histogram.clear_below(u64::MAX);
+        assert_eq!(histogram.total_count(), 1);
+
+        let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, Some(4));
+        for value in &[1, 2, 500, 600] {
+            histogram.increment(*value, 1);
+        }
+        histogram.clear_below(100);
+        assert_eq!(histogram.total_count(), 2);
+        // evicting the samples of cleared buckets does not remove others
+        for value in &[700, 800] {
+            histogram.increment(*value, 1);
+        }
+        assert_eq!(histogram.total_count(), 4);
+        assert_eq!(histogram.percentile(0.0), Some(500));
+    }
+
+    #[test]
+    fn drain() {
+        let h = Histogram::<AtomicU64>::new(10_000, 2, None, None);
+        assert_eq!(h.drain().total_count(), 0);
+        h.increment(5, 2);
+        h.increment(1234, 3);
+        h.increment(20_000, 4);
+        let snapshot = h.snapshot();
+        assert_eq!(snapshot.buckets(), &[(5, 2), (1299, 3)]);
+        assert_eq!(snapshot.too_high(), 4);
+        assert_eq!(snapshot.total_count(), 9);
+        assert_eq!(h.total_count(), 9);
+        for percentile in &[0.0, 0.2, 0.5, 0.6, 1.0] {
+            assert_eq!(snapshot.percentile(*percentile), h.percentile(*percentile));
+        }
+
+        let drained = h.drain();
+        assert_eq!(drained, snapshot);
+        assert_eq!(h.total_count(), 0);
+        assert_eq!(h.percentile(0.5), None);
+        assert_eq!(h.too_high(), 0);
+        h.increment(50, 1);
+        assert_eq!(h.percentile(1.0), Some(50));
+        assert_eq!(h.drain().buckets(), &[(50, 1)]);
+    }
+
+    #[test]
+    fn drain_concurrent() {
+        let h = Arc::new(Histogram::<AtomicU64>::new(1_000_000, 3, None, None));
+        let threads = 4;
+        let per_thread = 100_000;
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let h = h.clone();
+                std::thread::spawn(move || {
+                    for i in 0..per_thread {
+                        h.increment((i * 7 + t) % 2_000_000, 1);
+                    }
+                })
+            })
+            .collect();
+        let mut total = 0;
+        while handles.iter().any(|handle| !handle.is_finished()) {
+            total += h.drain().total_count();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        total += h.drain().total_count();
+        assert_eq!(total, threads * per_thread);
+    }
+
+    #[test]
+    fn buckets() {
+        let h = Histogram::<AtomicU64>::new(10_000, 2, None, None);
+        assert_eq!(h.buckets().count(), 0);
+        h.increment(5, 2);
+        h.increment(50, 1);
+        h.increment(1234, 3);
+        h.increment(1299, 1);
+        h.increment(20_000, 4);
+        let buckets: Vec<(u64, u64)> = h.buckets().collect();
+        assert_eq!(buckets, vec![(5, 2), (50, 1), (1299, 4)]);
+    }
+
+    #[test]
+    fn merge() {
+        let a = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        let b = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        for i in 1..=50 {
+            a.increment(i, 1);
+        }
+        for i in 51..=100 {
+            b.increment(i, 1);
+        }
+        b.increment(2_000, 2);
+        a.merge(&b);
+        assert_eq!(a.total_count(), 102);
+        assert_eq!(a.too_high(), 2);
+        assert_eq!(a.percentile(0.0), Some(1));
+        assert_eq!(a.percentile(0.49), Some(50));
+        assert_eq!(a.percentile(0.98), Some(100));
+        assert_eq!(a.percentile(1.0), Some(1_000));
+        assert_eq!(b.total_count(), 52);
+    }
+
+    #[test]
+    fn mean() {
+        let h = Histogram::<AtomicU64>::new(100, 3, None, None);
+        assert_eq!(h.mean(), None);
+        h.increment(10, 1);
+        assert_eq!(h.mean(), Some(10.0));
+        h.increment(20, 3);
+        assert_eq!(h.mean(), Some(17.5));
+        h.increment(1000, 4);
+        assert_eq!(h.mean(), Some(58.75));
+    }
+
+    #[test]
+    fn std_dev() {
+        let h = Histogram::<AtomicU64>::new(100, 3, None, None);
+        assert_eq!(h.std_dev(), None);
+        h.increment(10, 1);
+        assert_eq!(h.std_dev(), Some(0.0));
+        h.increment(20, 1);
+        assert_eq!(h.std_dev(), Some(5.0));
+        h.clear();
+        h.increment(2, 1);
+        h.increment(4, 3);
+        h.increment(5, 2);
+        h.increment(7, 1);
+        h.increment(9, 1);
+        assert_eq!(h.std_dev(), Some(2.0));
+    }
+
+    #[test]
+    fn large_max() {
+        let h = Histogram::<AtomicU64>::new(u64::MAX, 3, None, None);
+        assert_eq!(h.mean(), None);
+        h.increment(u64::MAX, u64::MAX);
+        h.increment(u64::MAX - 1, 1);
+        h.increment(10_000_000_000_000_000_000, 1);
+        assert_eq!(h.total_count(), u64::MAX);
+        assert_eq!(h.percentile(0.0), Some(10_099_999_999_999_999_999));
+        assert_eq!(h.percentile(1.0), Some(u64::MAX));
+        assert_eq!(h.weighted_percentile(1.0), Some(u64::MAX));
+        assert!(h.mean().unwrap() >= 18_440_000_000_000_000_000.0);
+        let buckets: Vec<Bucket<u64>> = h.into_iter().collect();
+        let top = buckets.last().unwrap();
+        assert_eq!(top.value(), u64::MAX);
+        assert_eq!(top.max(), u64::MAX);
+    }
+
+    #[test]
+    fn configured_range() {
+        let narrow = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+        assert_eq!(narrow.max(), 1_000);
+        assert_eq!(narrow.precision(), 3);
+        narrow.increment(10, 1);
+        narrow.increment(1_000, 1);
+        narrow.increment(5_000, 2);
+        narrow.increment(u64::MAX, 1);
+        assert_eq!(narrow.too_high(), 3);
+        assert_eq!(narrow.total_count(), 5);
+        assert_eq!(narrow.percentile(0.0), Some(10));
+        assert_eq!(narrow.percentile(0.4), Some(1_000));
+        assert_eq!(narrow.percentile(1.0), Some(1_000));
+        assert_eq!(narrow.mean(), Some((10.0 + 4.0 * 1_000.0) / 5.0));
+
+        let wide = Histogram::<AtomicU64>::new(60_000_000_000, 3, None, None);
+        assert!(wide.size() > narrow.size());
+        wide.increment(5_000, 1);
+        assert_eq!(wide.too_high(), 0);
+        assert_eq!(wide.percentile(1.0), Some(5_009));
+
+        let coarse = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let fine = Histogram::<AtomicU64>::new(1_000_000, 5, None, None);
+        assert!(fine.size() > coarse.size());
+        coarse.increment(123_456, 1);
+        fine.increment(123_456, 1);
+        assert_eq!(coarse.percentile(1.0), Some(123_999));
+        assert_eq!(fine.percentile(1.0), Some(123_459));
     }
 
     #[test]
@@ -576,4 +2012,29 @@ mod tests {
         std::thread::sleep(Duration::from_millis(1));
         assert_eq!(h.total_count(), 0);
     }
+
+    #[test]
+    fn from_values() {
+        // pseudo-random values below the exact range of the precision
+        let mut state = 12_345_u64;
+        let values: Vec<u64> = (0..10_000)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                (state >> 33) % 1_000
+            })
+            .collect();
+        let h = Histogram::<AtomicU64>::from_values(&values, 1_000, 3);
+        assert_eq!(h.total_count(), 10_000);
+        let mut sorted = values.clone();
+        sorted.sort();
+        for percentile in &[0.0, 0.01, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999, 1.0] {
+            let rank = ((percentile * sorted.len() as f64).ceil() as usize).max(1);
+            assert_eq!(h.percentile(*percentile), Some(sorted[rank - 1]));
+        }
+
+        let h = Histogram::<AtomicU8>::from_values(&[], 1_000, 3);
+        assert!(h.is_empty());
+    }
 }
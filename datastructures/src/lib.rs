@@ -8,8 +8,12 @@ mod buffer;
 mod counter;
 mod heatmap;
 mod histogram;
+mod hyperloglog;
+mod sliding_histogram;
 
 pub use crate::buffer::*;
 pub use crate::counter::*;
 pub use crate::heatmap::*;
 pub use crate::histogram::*;
+pub use crate::hyperloglog::*;
+pub use crate::sliding_histogram::*;
@@ -0,0 +1,150 @@
+// Copyright 2019 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::counter::*;
+use atomics::*;
+use std::convert::From;
+
+use crate::histogram::Histogram;
+
+/// A `SlidingHistogram` holds the samples of the most recent `intervals`
+/// intervals, eg: the last 60 seconds with 60 intervals which are rotated each
+/// second. Each interval has its own `Histogram`, and queries sum them, so
+/// percentiles cover the whole window regardless of when they are read. The
+/// caller marks the end of each interval with `rotate()`, which discards the
+/// samples of the oldest interval.
+pub struct SlidingHistogram<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating,
+{
+    slices: Vec<Histogram<T>>, // one `Histogram` per interval
+    current: AtomicUsize,      // indicates which `Histogram` is being recorded
+}
+
+impl<T> SlidingHistogram<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    /// Create a new `SlidingHistogram` covering the given number of
+    /// `intervals`, at least one, where each `Histogram` will store from
+    /// 0..`max` with a specified `precision`
+    pub fn new(max: u64, precision: u32, intervals: usize) -> Self {
+        let intervals = intervals.max(1);
+        let mut slices = Vec::with_capacity(intervals);
+        for _ in 0..intervals {
+            slices.push(Histogram::new(max, precision, None, None));
+        }
+        SlidingHistogram {
+            slices,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of intervals covered by the window
+    pub fn intervals(&self) -> usize {
+        self.slices.len()
+    }
+
+    /// Increment the count for `value` in the current interval
+    pub fn increment(&self, value: u64, count: <T as AtomicPrimitive>::Primitive) {
+        self.slices[self.current.get()].increment(value, count);
+    }
+
+    /// Ends the current interval, clearing the oldest interval and recording
+    /// into it from now on. Once `intervals` rotations have passed, the
+    /// samples of an interval no longer count towards the window. A sample
+    /// recorded concurrently with the rotation may be cleared with the
+    /// oldest interval.
+    pub fn rotate(&self) {
+        let next = (self.current.get() + 1) % self.slices.len();
+        self.slices[next].clear();
+        self.current.set(next);
+    }
+
+    /// Clears the samples of every interval
+    pub fn clear(&self) {
+        for slice in &self.slices {
+            slice.clear();
+        }
+    }
+
+    /// Returns a `Histogram` holding the samples of every interval in the
+    /// window
+    pub fn histogram(&self) -> Histogram<T> {
+        let histogram =
+            Histogram::new(self.slices[0].max(), self.slices[0].precision(), None, None);
+        for slice in &self.slices {
+            histogram.merge(slice);
+        }
+        histogram
+    }
+
+    /// Returns the total count of the samples in the window
+    pub fn total_count(&self) -> u64 {
+        self.slices
+            .iter()
+            .fold(0, |total, slice| total.saturating_add(slice.total_count()))
+    }
+
+    /// Returns the nominal value at the percentile specified from 0.0-1.0
+    /// over the whole window, see `Histogram::percentile`
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        self.histogram().percentile(percentile)
+    }
+
+    /// Returns the nominal value at each of the percentiles over the whole
+    /// window, summing the intervals only once
+    pub fn percentiles(&self, percentiles: &[f64]) -> Vec<Option<u64>> {
+        self.histogram().percentiles(percentiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn age_out() {
+        let histogram = SlidingHistogram::<AtomicU64>::new(1_000_000, 3, 3);
+        assert_eq!(histogram.intervals(), 3);
+        assert_eq!(histogram.percentile(0.5), None);
+
+        // each interval records 100 samples of a larger value
+        for interval in 1..=5 {
+            histogram.increment(interval * 100, 100);
+            let oldest = interval.saturating_sub(2).max(1);
+            assert_eq!(histogram.total_count(), 100 * (interval - oldest + 1));
+            assert_eq!(histogram.percentile(0.0), Some(oldest * 100));
+            assert_eq!(histogram.percentile(1.0), Some(interval * 100));
+            histogram.rotate();
+        }
+        // the last rotation cleared the interval of 300, leaving 400 and 500
+        assert_eq!(histogram.total_count(), 200);
+        assert_eq!(
+            histogram.percentiles(&[0.0, 0.5, 1.0]),
+            vec![Some(400), Some(400), Some(500)]
+        );
+        histogram.rotate();
+        histogram.rotate();
+        assert_eq!(histogram.total_count(), 0);
+        assert_eq!(histogram.percentile(0.5), None);
+
+        histogram.increment(10, 1);
+        histogram.clear();
+        assert_eq!(histogram.total_count(), 0);
+    }
+
+    #[test]
+    fn single_interval() {
+        let histogram = SlidingHistogram::<AtomicU32>::new(1_000, 3, 0);
+        assert_eq!(histogram.intervals(), 1);
+        histogram.increment(10, 5);
+        assert_eq!(histogram.histogram().total_count(), 5);
+        histogram.rotate();
+        assert_eq!(histogram.total_count(), 0);
+    }
+}
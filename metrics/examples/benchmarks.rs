@@ -15,6 +15,8 @@
 extern crate metrics;
 
 use metrics::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::{thread, time};
 
 pub const NS_PER_SEC: usize = 1_000_000_000;
@@ -62,6 +64,61 @@ pub fn main() {
         MeasurementType::TimeInterval,
         "Time Interval".to_string(),
     );
+    for i in [1, 2, 4, 8, 16].iter() {
+        readings_run(*i, 100_000);
+    }
+}
+
+// measures readings() while another thread continuously changes the outputs
+pub fn readings_run(threads: usize, max: usize) {
+    let histogram = Histogram::<AtomicU64>::new(2_000_000_000, 3, None, None);
+    let channel = Arc::new(Channel::new(
+        "test".to_string(),
+        Source::Distribution,
+        Some(histogram),
+    ));
+    channel.add_output(Output::Counter);
+    channel.add_output(Output::Percentile(Percentile::p50));
+    for value in 0..1_000 {
+        let _ = channel.record(Measurement::Distribution {
+            value,
+            count: 1,
+            time: 1,
+        });
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let mutator = {
+        let channel = channel.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                channel.add_output(Output::Mean);
+                channel.delete_output(Output::Mean);
+            }
+        })
+    };
+    let t0 = time::Instant::now();
+    let readers: Vec<_> = (0..threads)
+        .map(|_| {
+            let channel = channel.clone();
+            thread::spawn(move || {
+                for _ in 0..(max / threads) {
+                    let _ = channel.readings();
+                }
+            })
+        })
+        .collect();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let duration = t0.elapsed();
+    running.store(false, Ordering::Relaxed);
+    mutator.join().unwrap();
+    let rate = max as f64 / duration.as_secs_f64();
+    println!(
+        "Readings with concurrent output changes (threads: {}): {:.2e} readings/s",
+        threads, rate
+    );
 }
 
 pub fn runner(runtime: f64, source: Source, measurement_type: MeasurementType, label: String) {
@@ -140,7 +197,7 @@ pub fn sized_run(
                         stop: value as u64,
                     },
                 };
-                recorder.record(label.clone(), measurement);
+                let _ = recorder.record(label.clone(), measurement);
             }
         }));
     }
@@ -16,32 +16,221 @@ use crate::*;
 
 use datastructures::*;
 
+use std::any::Any;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
+// each bucket in an exported digest is encoded as a little-endian u64 value
+// followed by a little-endian u64 count
+const DIGEST_ENTRY_SIZE: usize = 16;
+
+// the defaults for when a thread flushes its buffered measurements
+const DEFAULT_BUFFER_RECORDS: usize = 1_024;
+const DEFAULT_BUFFER_INTERVAL: Duration = Duration::from_millis(100);
+
+// the precision of the cardinality estimator of Set channels, which uses 16KiB
+// for a standard error of 0.81%
+const SET_PRECISION: u8 = 14;
+
+// the percentiles registered by with_default_percentiles()
+const DEFAULT_PERCENTILES: [Percentile; 4] = [
+    Percentile::p50,
+    Percentile::p90,
+    Percentile::p99,
+    Percentile::p999,
+];
+
+// the percentiles compared by approx_diff()
+const COMPARED_PERCENTILES: [f64; 7] = [0.0, 0.25, 0.5, 0.9, 0.99, 0.999, 1.0];
+
+// the ewma_alpha of a channel without a moving average, which is not the bits
+// of any clamped alpha
+const EWMA_DISABLED: u64 = u64::MAX;
+
+// the ewma of a channel which has had no reading since it was enabled or zeroed
+const EWMA_EMPTY: u64 = u64::MAX;
+
+// identifies each channel in the thread-local buffers
+static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+thread_local! {
+    // the measurements buffered by this thread for each channel, by id. each
+    // entry is a LocalBuffer for the counter type of the channel
+    static BUFFERS: RefCell<HashMap<u64, Box<dyn Any>>> = RefCell::new(HashMap::new());
+    // the number and the summed counts of the measurements this thread has
+    // skipped since it last applied one, by channel id, see
+    // Channel::set_sampling()
+    static SAMPLES: RefCell<HashMap<u64, (u64, u64)>> = RefCell::new(HashMap::new());
+}
+
+// measurements accumulated by a single thread for a channel
+struct LocalBuffer<C> {
+    records: usize,
+    since: Instant,
+    counter: u64,
+    // histogram counts by value
+    values: HashMap<u64, C>,
+    // the value and time of the max and min timing
+    max: Option<(u64, u64)>,
+    min: Option<(u64, u64)>,
+    last_write: u64,
+}
+
+impl<C> LocalBuffer<C>
+where
+    C: Default + Copy + Saturating,
+{
+    fn new() -> Self {
+        Self {
+            records: 0,
+            since: Instant::now(),
+            counter: 0,
+            values: HashMap::new(),
+            max: None,
+            min: None,
+            last_write: 0,
+        }
+    }
+
+    fn add(&mut self, counter: u64, value: u64, count: C, time: u64) {
+        self.records += 1;
+        self.counter = self.counter.wrapping_add(counter);
+        let total = self.values.entry(value).or_default();
+        *total = total.saturating_add(count);
+        self.last_write = time;
+    }
+}
+
+// percentiles of the histogram, by the bits of the percentile, which are valid
+// while the histogram remains at the generation they were computed from
+#[derive(Default)]
+struct PercentileCache {
+    generation: Option<u64>,
+    values: HashMap<u64, Option<u64>>,
+}
+
+/// The fixed-point scale used to store fractional gauge readings, see
+/// `Channel::record_gauge_f64`
+pub const GAUGE_F64_SCALE: u64 = 1_000;
+
+/// The fixed-point scale used to store `DistributionF64` observations, so a
+/// histogram holding them is configured in units of `1 / DISTRIBUTION_F64_SCALE`
+pub const DISTRIBUTION_F64_SCALE: u64 = 1_000_000;
+
+#[derive(Clone, Copy, Debug)]
 pub enum Measurement<C> {
     // taken from a counter eg: number of requests
     Counter { value: u64, time: u64 },
+    // the increase of a counter since its previous measurement eg: requests
+    // in the interval
+    CounterDelta { delta: u64, time: u64 },
     // taken from a distribution eg: an external histogram
     Distribution { value: u64, count: C, time: u64 },
+    // taken from a distribution of fractional values eg: latency in ms
+    DistributionF64 { value: f64, count: C, time: u64 },
     // taken from a gauge eg: bytes of memory used
     Gauge { value: u64, time: u64 },
+    // a value whose distinct occurrences are counted eg: a key
+    Set { value: u64, time: u64 },
+    // taken from a gauge which may be negative eg: replication lag
+    SignedGauge { value: i64, time: u64 },
     // incremental count to sum into a counter
     Increment { count: C, time: u64 },
     // the start and stop of an event
     TimeInterval { start: u64, stop: u64 },
+    // the duration of an event which occurred at time
+    Timing { duration: u64, time: u64 },
+}
+
+impl<C> Measurement<C> {
+    /// Returns the `Source` of the `Channel`s which accept the measurement
+    pub fn source(&self) -> Source {
+        match self {
+            Measurement::Counter { .. }
+            | Measurement::CounterDelta { .. }
+            | Measurement::Increment { .. } => Source::Counter,
+            Measurement::Distribution { .. } => Source::Distribution,
+            Measurement::DistributionF64 { .. } => Source::DistributionF64,
+            Measurement::Gauge { .. } => Source::Gauge,
+            Measurement::Set { .. } => Source::Set,
+            Measurement::SignedGauge { .. } => Source::SignedGauge,
+            Measurement::TimeInterval { .. } | Measurement::Timing { .. } => Source::TimeInterval,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DigestError {
+    // the channel has no histogram to export from or merge into
+    NoHistogram,
+    // the encoded digest is not a whole number of entries
+    Truncated,
+    // a bucket count exceeds what the histogram counter type can hold
+    Overflow,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordError {
+    // the measurement is of a kind which the channel source does not accept
+    SourceMismatch { expected: Source, got: Source },
+    // the value is negative, infinite, or NaN
+    InvalidValue,
+    // the count is too large for the counter type of the channel
+    CountOverflow,
+    // the time was to be read from a clock, but the channel has none
+    NoClock,
+    // a composite channel has no channel of the source of the measurement
+    NoChannel(Source),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputSpecError {
+    // the tokens of the spec which do not name an output, in the order given
+    Unrecognized(Vec<String>),
+}
+
+/// A point-in-time view of the statistics tracked by a `Channel`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelSnapshot {
+    pub name: String,
+    pub counter: u64,
+    // the value of each percentile output, ordered from lowest to highest
+    pub percentiles: Vec<(Percentile, u64)>,
+    // the value and time of the max point, if there is one
+    pub max: Option<(u64, u64)>,
+    // the value and time of the min point, if there is one
+    pub min: Option<(u64, u64)>,
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub enum Source {
     Counter,
     Distribution,
+    DistributionF64,
     Gauge,
+    Set,
+    SignedGauge,
     TimeInterval,
 }
 
-// #[derive(Clone)]
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Counter => write!(f, "counter"),
+            Source::Distribution => write!(f, "distribution"),
+            Source::DistributionF64 => write!(f, "distribution_f64"),
+            Source::Gauge => write!(f, "gauge"),
+            Source::Set => write!(f, "set"),
+            Source::SignedGauge => write!(f, "signed_gauge"),
+            Source::TimeInterval => write!(f, "time_interval"),
+        }
+    }
+}
+
 pub struct Channel<T>
 where
     T: Counter + Unsigned,
@@ -50,13 +239,66 @@ where
     name: Arc<Mutex<String>>,
     source: Source,
     counter: AtomicU64,
+    // the number of times summing into the counter has wrapped past u64::MAX
+    overflows: AtomicU64,
+    // how distribution values above the histogram max are recorded, see
+    // OutOfRangePolicy::to_u8()
+    out_of_range_policy: AtomicU8,
+    // the count of distribution values excluded from the histogram
+    out_of_range: AtomicU64,
+    // counter value as of the last readings_and_reset()
+    reported: AtomicU64,
+    // counter value as of the last latch()
+    latched_counter: AtomicU64,
     histogram: Option<Histogram<T>>,
+    // the estimator of distinct values, for Set channels
+    set: Option<HyperLogLog>,
+    // histograms for measurements recorded with a label
+    labeled: Mutex<HashMap<String, Histogram<T>>>,
     last_write: AtomicU64,
     latched: bool,
     max: Point,
+    // the trace id recorded with the max point, if it had one
+    exemplar: Mutex<Option<u128>>,
     min: Point,
-    outputs: Arc<Mutex<HashSet<Output>>>,
-    has_data: AtomicBool,
+    // the largest and smallest readings of a SignedGauge channel
+    signed_max: SignedPoint,
+    signed_min: SignedPoint,
+    // the most recent rate derived from Counter measurements
+    rate: Point,
+    // the bits of the smoothing factor of the moving average of Gauge
+    // readings, or EWMA_DISABLED
+    ewma_alpha: AtomicU64,
+    // the moving average of Gauge readings, scaled by GAUGE_F64_SCALE, or
+    // EWMA_EMPTY before the first reading
+    ewma: AtomicU64,
+    // replaced, rather than modified, while readers hold the current set
+    outputs: Mutex<Arc<HashSet<Output>>>,
+    // dimensions attached to each reading, eg: backend="cache01"
+    labels: Mutex<HashMap<String, String>>,
+    // the counter baseline was seeded by a Counter or CounterDelta measurement
+    seeded: AtomicBool,
+    paused: AtomicBool,
+    // the RoundingMode, see RoundingMode::to_u8()
+    rounding: AtomicU8,
+    weighted_increments: AtomicBool,
+    // the fewest samples from which percentile outputs are reported
+    min_samples: AtomicU64,
+    // present when percentiles are cached, see set_percentile_cache(). the
+    // flag is checked first, so uncached reads don't take the lock
+    percentile_cache: Mutex<Option<PercentileCache>>,
+    percentile_cached: AtomicBool,
+    // the source of times for record_now()
+    clock: Mutex<Option<Arc<dyn Clock>>>,
+    // the key of this channel's thread-local buffers
+    id: u64,
+    // thresholds at which a thread flushes its buffered measurements
+    buffer_records: AtomicUsize,
+    buffer_interval: AtomicU64,
+    // one in this many sampled measurements is applied, see set_sampling()
+    sampling: AtomicU64,
+    // the unit of the recorded times, see percentile_as() and TimeUnit::to_u8()
+    unit: AtomicU8,
 }
 
 impl<T: 'static> PartialEq for Channel<T>
@@ -78,6 +320,64 @@ where
 {
 }
 
+/// Cloning copies the accumulated data, including the histograms, so that the
+/// clone can be kept, eg: to compare against a later interval, while the
+/// original continues recording. The clone has its own copy of the name,
+/// outputs, and labels, so changing them on either `Channel` does not affect
+/// the other.
+impl<T: 'static> Clone for Channel<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    fn clone(&self) -> Self {
+        let point = |point: &Point| Point::new(point.value(), point.time());
+        Self {
+            name: Arc::new(Mutex::new(self.name())),
+            source: self.source,
+            counter: AtomicU64::new(self.counter.get()),
+            overflows: AtomicU64::new(self.overflows()),
+            out_of_range_policy: AtomicU8::new(self.out_of_range_policy().to_u8()),
+            out_of_range: AtomicU64::new(self.out_of_range()),
+            reported: AtomicU64::new(self.reported.get()),
+            latched_counter: AtomicU64::new(self.latched_counter.get()),
+            histogram: self.histogram.clone(),
+            set: self.set.clone(),
+            labeled: Mutex::new(self.lock_labeled().clone()),
+            last_write: AtomicU64::new(self.last_write.get()),
+            latched: self.latched,
+            max: point(&self.max),
+            exemplar: Mutex::new(self.exemplar()),
+            min: point(&self.min),
+            signed_max: SignedPoint::new(self.signed_max.value(), self.signed_max.time()),
+            signed_min: SignedPoint::new(self.signed_min.value(), self.signed_min.time()),
+            rate: point(&self.rate),
+            ewma_alpha: AtomicU64::new(self.ewma_alpha.get()),
+            ewma: AtomicU64::new(self.ewma.get()),
+            outputs: Mutex::new(self.current_outputs()),
+            labels: Mutex::new(self.labels()),
+            seeded: AtomicBool::new(self.seeded.load(Ordering::SeqCst)),
+            paused: AtomicBool::new(self.is_paused()),
+            rounding: AtomicU8::new(self.rounding().to_u8()),
+            weighted_increments: AtomicBool::new(self.weighted_increments()),
+            min_samples: AtomicU64::new(self.min_samples()),
+            percentile_cache: Mutex::new(if self.percentile_cache() {
+                Some(PercentileCache::default())
+            } else {
+                None
+            }),
+            percentile_cached: AtomicBool::new(self.percentile_cache()),
+            clock: Mutex::new(self.clock()),
+            id: NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            buffer_records: AtomicUsize::new(self.buffer_records.get()),
+            buffer_interval: AtomicU64::new(self.buffer_interval.get()),
+            sampling: AtomicU64::new(self.sampling()),
+            unit: AtomicU8::new(self.unit().to_u8()),
+        }
+    }
+}
+
 impl<T: 'static> Channel<T>
 where
     T: Counter + Unsigned,
@@ -85,265 +385,4849 @@ where
     u64: From<<T as AtomicPrimitive>::Primitive>,
 {
     pub fn new(name: String, source: Source, histogram: Option<Histogram<T>>) -> Self {
+        Self::with_latched(name, source, histogram, true)
+    }
+
+    /// Create a new `Channel` which, if `latched` is false, keeps the contents
+    /// of its histogram when `latch()` is called
+    pub fn with_latched(
+        name: String,
+        source: Source,
+        histogram: Option<Histogram<T>>,
+        latched: bool,
+    ) -> Self {
         Self {
             name: Arc::new(Mutex::new(name)),
             source,
             counter: AtomicU64::default(),
+            overflows: AtomicU64::default(),
+            out_of_range_policy: AtomicU8::new(OutOfRangePolicy::default().to_u8()),
+            out_of_range: AtomicU64::default(),
+            reported: AtomicU64::default(),
+            latched_counter: AtomicU64::default(),
             histogram,
+            set: if source == Source::Set {
+                Some(HyperLogLog::new(SET_PRECISION))
+            } else {
+                None
+            },
+            labeled: Mutex::new(HashMap::new()),
             last_write: AtomicU64::default(),
-            latched: true,
+            latched,
             max: Point::new(0, 0),
+            exemplar: Mutex::new(None),
             min: Point::new(0, 0),
-            outputs: Arc::new(Mutex::new(HashSet::new())),
-            has_data: AtomicBool::new(false),
+            signed_max: SignedPoint::new(0, 0),
+            signed_min: SignedPoint::new(0, 0),
+            rate: Point::new(0, 0),
+            ewma_alpha: AtomicU64::new(EWMA_DISABLED),
+            ewma: AtomicU64::new(EWMA_EMPTY),
+            outputs: Mutex::new(Arc::new(HashSet::new())),
+            labels: Mutex::new(HashMap::new()),
+            seeded: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            rounding: AtomicU8::new(RoundingMode::default().to_u8()),
+            weighted_increments: AtomicBool::new(false),
+            min_samples: AtomicU64::default(),
+            percentile_cache: Mutex::new(None),
+            percentile_cached: AtomicBool::new(false),
+            clock: Mutex::new(None),
+            id: NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            buffer_records: AtomicUsize::new(DEFAULT_BUFFER_RECORDS),
+            buffer_interval: AtomicU64::new(DEFAULT_BUFFER_INTERVAL.as_nanos() as u64),
+            sampling: AtomicU64::new(1),
+            unit: AtomicU8::new(TimeUnit::default().to_u8()),
         }
     }
 
     pub fn name(&self) -> String {
-        self.name.lock().unwrap().clone()
+        self.name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the name without blocking, or `None` if the name is currently
+    /// locked by another thread. Like `name()`, this recovers the name if a
+    /// thread panicked while holding the lock.
+    pub fn try_name(&self) -> Option<String> {
+        match self.name.try_lock() {
+            Ok(name) => Some(name.clone()),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner().clone()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Renames the `Channel` while keeping its accumulated data. Equality of
+    /// `Channel`s is based on the name, so a `Channel` must not be renamed
+    /// while it is held in a collection which relies on that equality, such
+    /// as a set. Similarly, the `Recorder` continues to store the `Channel`
+    /// under the name it was added with.
+    pub fn set_name(&self, name: String) {
+        *self.name.lock().unwrap_or_else(PoisonError::into_inner) = name;
     }
 
     pub fn source(&self) -> Source {
         self.source
     }
 
-    pub fn record(&self, measurement: Measurement<<T as AtomicPrimitive>::Primitive>) {
+    /// Sets a label which is attached to each `Reading` of the `Channel`, so
+    /// that exporters can report it as a dimension, eg: `protocol="redis"`.
+    /// Setting an existing key replaces its value. Labels do not affect the
+    /// equality of `Channel`s, which is based on the name.
+    pub fn set_label(&self, key: String, value: String) {
+        self.labels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, value);
+    }
+
+    /// Returns the labels set on the `Channel`
+    pub fn labels(&self) -> HashMap<String, String> {
+        self.labels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the `RoundingMode` used by this `Channel`
+    pub fn rounding(&self) -> RoundingMode {
+        RoundingMode::from_u8(self.rounding.get())
+    }
+
+    /// Sets the `RoundingMode` used by this `Channel`. This affects the rate
+    /// derived from `Counter` measurements, which is stored as an integer in
+    /// the histogram and therefore determines the `Percentile` outputs of
+    /// counter channels, the `Mean` and `StdDev` outputs, and the values
+    /// returned by `percentile_scaled()`. The `RoundingMode` is chosen per
+    /// `Channel`, so all of its outputs are rounded alike.
+    pub fn set_rounding(&self, rounding: RoundingMode) {
+        self.rounding.set(rounding.to_u8());
+    }
+
+    /// Returns the `TimeUnit` of the values recorded into this `Channel`
+    pub fn unit(&self) -> TimeUnit {
+        TimeUnit::from_u8(self.unit.get())
+    }
+
+    /// Sets the `TimeUnit` of the values recorded into this `Channel`, which
+    /// `percentile_as()` converts from. Defaults to nanoseconds.
+    pub fn set_unit(&self, unit: TimeUnit) {
+        self.unit.set(unit.to_u8());
+    }
+
+    /// Returns the `OutOfRangePolicy` used by this `Channel`
+    pub fn out_of_range_policy(&self) -> OutOfRangePolicy {
+        OutOfRangePolicy::from_u8(self.out_of_range_policy.get())
+    }
+
+    /// Sets how `Distribution` and `DistributionF64` values above the max of
+    /// the histogram are recorded, see `OutOfRangePolicy`
+    pub fn set_out_of_range_policy(&self, policy: OutOfRangePolicy) {
+        self.out_of_range_policy.set(policy.to_u8());
+    }
+
+    /// Returns the smoothing factor of the moving average of `Gauge` readings,
+    /// or `None` if it is not enabled
+    pub fn ewma_alpha(&self) -> Option<f64> {
+        match self.ewma_alpha.get() {
+            EWMA_DISABLED => None,
+            bits => Some(f64::from_bits(bits)),
+        }
+    }
+
+    /// Enables an exponentially weighted moving average of `Gauge` readings
+    /// with the smoothing factor `alpha`, which is clamped to 0.0 - 1.0. Each
+    /// reading moves the average by `alpha` of the difference between them,
+    /// so a larger `alpha` follows changes more quickly. The first reading
+    /// after enabling, and after `zero()`, seeds the average. `None` disables
+    /// it. The counter continues to hold the latest reading.
+    pub fn set_ewma_alpha(&self, alpha: Option<f64>) {
+        let alpha = alpha.map_or(EWMA_DISABLED, |alpha| alpha.clamp(0.0, 1.0).to_bits());
+        self.ewma_alpha.set(alpha);
+        self.ewma.set(EWMA_EMPTY);
+    }
+
+    /// Returns true if `Increment` measurements are weighted by their count in
+    /// the histogram
+    pub fn weighted_increments(&self) -> bool {
+        self.weighted_increments.load(Ordering::SeqCst)
+    }
+
+    /// Sets whether `Increment` measurements are weighted by their count in
+    /// the histogram. By default, each increment adds a single sample to the
+    /// bucket for its count. When weighted, the sample is added with a weight
+    /// of its count, so the histogram reflects how much of the total was
+    /// contributed by increments of each size. The counter is unaffected.
+    pub fn set_weighted_increments(&self, weighted: bool) {
+        self.weighted_increments.store(weighted, Ordering::SeqCst);
+    }
+
+    /// Returns the fewest samples from which percentile outputs are reported,
+    /// see `set_min_samples()`
+    pub fn min_samples(&self) -> u64 {
+        self.min_samples.get()
+    }
+
+    /// Sets the fewest samples a histogram must hold for its `Percentile`
+    /// outputs to be reported, so that the readings omit tail percentiles
+    /// taken from a handful of samples rather than presenting noise as a
+    /// trend. This applies to the main and labeled histograms alike. Defaults
+    /// to zero, which reports percentiles of any non-empty histogram.
+    pub fn set_min_samples(&self, min: u64) {
+        self.min_samples.set(min);
+    }
+
+    /// Returns the sampling factor, see `set_sampling()`
+    pub fn sampling(&self) -> u64 {
+        self.sampling.get()
+    }
+
+    /// Sets the `Channel` to apply only one in `n` of the `Distribution`,
+    /// `DistributionF64`, `Increment`, `TimeInterval` and `Timing`
+    /// measurements passed to `record()`, for metrics recorded so often that
+    /// the atomic updates of each measurement become the bottleneck. The
+    /// decision is made by a counter local to each thread, so every `n`th
+    /// measurement of a thread is applied and no random number is drawn.
+    /// The counts of the skipped measurements are summed by the thread and
+    /// added to the counter with the next applied measurement, so it is
+    /// exact once a multiple of `n` measurements have been recorded on each
+    /// thread, and otherwise is short by the counts of fewer than `n`
+    /// measurements per thread. The histograms, including those of
+    /// `record_labeled()`, and the max and min only see the applied
+    /// measurements, so percentiles are estimated from a sample of one in `n`,
+    /// and the extremes may miss the true max and min. Measurements are
+    /// validated before sampling, so an invalid one is rejected whether or
+    /// not it would have been applied. Measurements recorded with
+    /// `record_traced()` or `record_buffered()`, and those of other kinds,
+    /// are always applied. Defaults to one, which applies every measurement,
+    /// as does zero.
+    pub fn set_sampling(&self, n: u64) {
+        self.sampling.set(n.max(1));
+    }
+
+    /// Returns true if percentiles read from the histogram are cached, see
+    /// `set_percentile_cache()`
+    pub fn percentile_cache(&self) -> bool {
+        self.percentile_cached.load(Ordering::SeqCst)
+    }
+
+    /// Sets whether percentiles read from the histogram are cached, eg: when
+    /// a scraper reads them far more often than the histogram changes. Each
+    /// percentile is computed once and returned from the cache until the
+    /// histogram changes, which is detected from its generation, see
+    /// `Histogram::generation`. Readers of a cached channel serialize on the
+    /// cache, and each change to the histogram also advances its generation.
+    /// Disabled by default.
+    pub fn set_percentile_cache(&self, enabled: bool) {
+        let mut cache = self.lock_percentile_cache();
+        if let Some(ref histogram) = self.histogram {
+            histogram.track_generation(enabled);
+        }
+        *cache = if enabled {
+            Some(PercentileCache::default())
+        } else {
+            None
+        };
+        self.percentile_cached.store(enabled, Ordering::SeqCst);
+    }
+
+    fn lock_percentile_cache(&self) -> MutexGuard<'_, Option<PercentileCache>> {
+        self.percentile_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // returns the percentiles from the cache if it is enabled and holds all of
+    // them for the current generation of the histogram, otherwise computes them
+    // and, if enabled, caches them
+    fn lookup_percentiles<F>(
+        &self,
+        histogram: &Histogram<T>,
+        percentiles: &[f64],
+        compute: F,
+    ) -> Vec<Option<u64>>
+    where
+        F: FnOnce() -> Vec<Option<u64>>,
+    {
+        if !self.percentile_cached.load(Ordering::Relaxed) {
+            return compute();
+        }
+        let mut cache = self.lock_percentile_cache();
+        // read before computing, so a change during the computation leaves
+        // the cache at a previous generation
+        let (cache, generation) = match (cache.as_mut(), histogram.generation()) {
+            (Some(cache), Some(generation)) => (cache, generation),
+            _ => return compute(),
+        };
+        if cache.generation != Some(generation) {
+            cache.values.clear();
+            cache.generation = Some(generation);
+        }
+        let cached: Option<Vec<Option<u64>>> = percentiles
+            .iter()
+            .map(|percentile| cache.values.get(&percentile.to_bits()).cloned())
+            .collect();
+        if let Some(values) = cached {
+            return values;
+        }
+        let values = compute();
+        for (percentile, value) in percentiles.iter().zip(&values) {
+            cache.values.insert(percentile.to_bits(), *value);
+        }
+        values
+    }
+
+    /// Records a `Measurement` into the `Channel`. Returns an error if the kind
+    /// of `Measurement` does not match the `Source` of the `Channel`.
+    pub fn record(
+        &self,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<(), RecordError> {
+        self.record_sampled(measurement).map(|_| ())
+    }
+
+    // records the measurement as with record(), returning whether it was
+    // applied rather than skipped by sampling
+    fn record_sampled(
+        &self,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<bool, RecordError> {
+        let skipped = match self.sample(&measurement)? {
+            Some(skipped) => skipped,
+            None => return Ok(false),
+        };
+        // the skipped measurements were valid, so they are counted even if
+        // this one can't be recorded
+        if skipped > 0 {
+            self.add_to_counter(skipped);
+        }
         match measurement {
             Measurement::Counter { value, time } => {
-                self.record_counter(value, time);
+                self.record_counter(value, time, self.rounding())
+            }
+            Measurement::CounterDelta { delta, time } => {
+                self.record_counter_delta(delta, time, self.rounding())
             }
             Measurement::Distribution { value, count, time } => {
-                self.record_distribution(value, count, time);
+                self.record_distribution(value, count, time)
             }
-            Measurement::Gauge { value, time } => {
-                self.record_gauge(value, time);
+            Measurement::DistributionF64 { value, count, time } => {
+                self.record_distribution_f64(value, count, time)
             }
+            Measurement::Gauge { value, time } => self.record_gauge(value, time),
+            Measurement::Set { value, time } => self.record_set(value, time),
+            Measurement::SignedGauge { value, time } => self.record_signed_gauge(value, time),
             Measurement::Increment { count, time } => self.record_increment(count, time),
-            Measurement::TimeInterval { start, stop } => self.record_time_interval(start, stop),
-        }
+            Measurement::TimeInterval { start, stop } => {
+                self.record_time_interval(start, stop, None)
+            }
+            Measurement::Timing { duration, time } => self.record_timing(duration, time, None),
+        }?;
+        Ok(true)
     }
 
-    // for Counter measurements:
-    // counter tracks value
-    // histogram tracks rate of change
-    fn record_counter(&self, value: u64, time: u64) {
-        if self.source == Source::Counter {
-            if self.has_data.load(Ordering::SeqCst) {
-                // calculate the difference between consecutive readings and the rate
-                let delta_value = value.wrapping_sub(self.counter.get());
-                let delta_time = time.wrapping_sub(self.last_write.get());
-                let rate = (delta_value as f64 * (1_000_000_000.0 / delta_time as f64)) as u64;
-                self.counter.add(delta_value);
-                if let Some(ref histogram) = self.histogram {
-                    histogram.increment(rate, <T as AtomicPrimitive>::Primitive::from(1_u8));
-                }
-                // track the point of max rate
-                if self.max.time() > 0 {
-                    if rate > self.max.value() {
-                        self.max.set(rate, time);
-                    }
-                } else {
-                    self.max.set(rate, time);
-                }
-                // track the point of min rate
-                if self.min.time() > 0 {
-                    if rate < self.min.value() {
-                        self.min.set(rate, time);
-                    }
+    // decides whether a measurement is applied when sampling. returns None if
+    // it is skipped, or else the summed count of the measurements skipped
+    // before it, to be added to the counter. a measurement which could not be
+    // recorded is rejected first, so it is neither skipped nor applied
+    fn sample(
+        &self,
+        measurement: &Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<Option<u64>, RecordError> {
+        let sampling = self.sampling();
+        if sampling <= 1 {
+            return Ok(Some(0));
+        }
+        let count = match *measurement {
+            Measurement::DistributionF64 { value, count, .. } => {
+                scale_f64(value)?;
+                u64::from(count)
+            }
+            Measurement::Distribution { count, .. } | Measurement::Increment { count, .. } => {
+                u64::from(count)
+            }
+            Measurement::TimeInterval { start, stop } => {
+                if stop >= start {
+                    1
                 } else {
-                    self.min.set(rate, time);
+                    0
                 }
+            }
+            Measurement::Timing { .. } => 1,
+            _ => return Ok(Some(0)),
+        };
+        self.check_source(measurement.source())?;
+        let sampled = SAMPLES.with(|samples| {
+            let mut samples = samples.borrow_mut();
+            let (skipped, skipped_count) = samples.entry(self.id).or_insert((0, 0));
+            if *skipped + 1 >= sampling {
+                let sum = *skipped_count;
+                *skipped = 0;
+                *skipped_count = 0;
+                Some(sum)
             } else {
-                self.counter.set(value);
-                self.has_data.store(true, Ordering::SeqCst);
+                *skipped += 1;
+                *skipped_count = skipped_count.wrapping_add(count);
+                None
+            }
+        });
+        Ok(sampled)
+    }
+
+    /// Records a `TimeInterval` or `Timing` measurement along with the id of
+    /// the trace it was taken from. If the measurement becomes the max point,
+    /// the trace id is kept as an exemplar of it and attached to the
+    /// `MaxPointTime` reading, so a slow sample can be looked up in a tracing
+    /// system. Other measurements are recorded as with `record()` and the
+    /// trace id is ignored.
+    pub fn record_traced(
+        &self,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+        trace_id: u128,
+    ) -> Result<(), RecordError> {
+        match measurement {
+            Measurement::TimeInterval { start, stop } => {
+                self.record_time_interval(start, stop, Some(trace_id))
+            }
+            Measurement::Timing { duration, time } => {
+                self.record_timing(duration, time, Some(trace_id))
             }
-            self.last_write.set(time);
+            measurement => self.record(measurement),
         }
     }
 
-    // for Distribution measurements:
-    // counter tracks sum of all counts
-    // histogram tracks values
-    fn record_distribution(&self, value: u64, count: <T as AtomicPrimitive>::Primitive, time: u64) {
-        if self.source == Source::Distribution {
-            self.counter.add(u64::from(count));
-            if let Some(ref histogram) = self.histogram {
-                histogram.increment(value, count);
+    /// Returns the `Clock` used by `record_now()`, if one is set
+    pub fn clock(&self) -> Option<Arc<dyn Clock>> {
+        self.clock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Sets the `Clock` which `record_now()` reads the time of measurements
+    /// from, or removes it with `None`. A clone of the `Channel` shares the
+    /// `Clock`.
+    pub fn set_clock(&self, clock: Option<Arc<dyn Clock>>) {
+        *self.clock.lock().unwrap_or_else(PoisonError::into_inner) = clock;
+    }
+
+    /// Records a `Measurement` as with `record()`, with its time replaced by
+    /// the current time of the `Clock` of the `Channel`, so that all of its
+    /// measurements are timed consistently. `TimeInterval` measurements carry
+    /// their own start and stop, and are recorded unchanged. Returns
+    /// `RecordError::NoClock` if no `Clock` is set.
+    pub fn record_now(
+        &self,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<(), RecordError> {
+        let now = self.clock().ok_or(RecordError::NoClock)?.now();
+        let measurement = match measurement {
+            Measurement::Counter { value, .. } => Measurement::Counter { value, time: now },
+            Measurement::CounterDelta { delta, .. } => {
+                Measurement::CounterDelta { delta, time: now }
             }
-            self.last_write.set(time);
+            Measurement::Distribution { value, count, .. } => Measurement::Distribution {
+                value,
+                count,
+                time: now,
+            },
+            Measurement::DistributionF64 { value, count, .. } => Measurement::DistributionF64 {
+                value,
+                count,
+                time: now,
+            },
+            Measurement::Gauge { value, .. } => Measurement::Gauge { value, time: now },
+            Measurement::Increment { count, .. } => Measurement::Increment { count, time: now },
+            Measurement::Set { value, .. } => Measurement::Set { value, time: now },
+            Measurement::SignedGauge { value, .. } => Measurement::SignedGauge { value, time: now },
+            Measurement::TimeInterval { start, stop } => Measurement::TimeInterval { start, stop },
+            Measurement::Timing { duration, .. } => Measurement::Timing {
+                duration,
+                time: now,
+            },
+        };
+        self.record(measurement)
+    }
+
+    /// Records each of the `Measurement`s into the `Channel` in order, as with
+    /// `record()`. The `RoundingMode` is read once for the whole batch, so its
+    /// rates are rounded alike even if it is changed meanwhile, and rates are
+    /// derived from consecutive `Counter` measurements within the batch as
+    /// they would be individually.
+    /// Stops at the first `Measurement` which does not match the `Source`,
+    /// returning the error, having recorded those before it.
+    pub fn record_batch(
+        &self,
+        measurements: &[Measurement<<T as AtomicPrimitive>::Primitive>],
+    ) -> Result<(), RecordError> {
+        let rounding = self.rounding();
+        for measurement in measurements {
+            match *measurement {
+                Measurement::Counter { value, time } => self.record_counter(value, time, rounding),
+                Measurement::CounterDelta { delta, time } => {
+                    self.record_counter_delta(delta, time, rounding)
+                }
+                measurement => self.record(measurement),
+            }?;
         }
+        Ok(())
     }
 
-    // for Gauge measurements:
-    // counter tracks latest reading
-    // histogram tracks readings
-    // max tracks largest reading
-    // min tracks smallest reading
-    fn record_gauge(&self, value: u64, time: u64) {
-        if self.source == Source::Gauge {
-            self.counter.set(value);
-            if let Some(ref histogram) = self.histogram {
-                histogram.increment(value, <T as AtomicPrimitive>::Primitive::from(1_u8));
+    /// Sets when a thread flushes the measurements it has buffered with
+    /// `record_buffered()`: once it has buffered `records` measurements, or
+    /// on the first measurement at least `interval` after its previous flush.
+    /// Defaults to 1024 measurements or 100ms.
+    pub fn set_buffer_flush(&self, records: usize, interval: Duration) {
+        self.buffer_records.set(records);
+        self.buffer_interval.set(interval.as_nanos() as u64);
+    }
+
+    /// Records a `Measurement` into a buffer local to the calling thread,
+    /// which is flushed into the `Channel` at the thresholds given by
+    /// `set_buffer_flush()`. This avoids contending with other threads on the
+    /// shared counters for each measurement, at the cost of the buffered
+    /// measurements not yet being visible. `Distribution`, `Increment`,
+    /// `TimeInterval` and `Timing` measurements are buffered, with the
+    /// histogram counts combined by value. Other measurements depend on the
+    /// order they are recorded in, so they flush the buffer and are recorded
+    /// directly. Buffered measurements are only flushed by the thread which
+    /// recorded them, so a thread should call `flush_thread_local()` before
+    /// it exits or the `Channel` is read for the last time.
+    pub fn record_buffered(
+        &self,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<(), RecordError>
+    where
+        <T as AtomicPrimitive>::Primitive: 'static,
+    {
+        let one = <T as AtomicPrimitive>::Primitive::from(1_u8);
+        let (counter, value, count, time, extremes) = match measurement {
+            Measurement::Distribution { value, count, time } => {
+                self.check_source(Source::Distribution)?;
+                if u64::from(count) == 0 {
+                    return Ok(());
+                }
+                (u64::from(count), value, count, time, true)
+            }
+            Measurement::Increment { count, time } => {
+                self.check_source(Source::Counter)?;
+                let weight = if self.weighted_increments() {
+                    count
+                } else {
+                    one
+                };
+                (u64::from(count), u64::from(count), weight, time, false)
             }
-            // track the point of max gauge reading
-            if self.max.time() > 0 {
-                if value > self.max.value() {
-                    self.max.set(value, time);
+            Measurement::TimeInterval { start, stop } => {
+                self.check_source(Source::TimeInterval)?;
+                match stop.checked_sub(start) {
+                    Some(duration) => (1, duration, one, start, true),
+                    None => return Ok(()),
                 }
-            } else {
-                self.max.set(value, time);
             }
-            // track the point of min rate
-            if self.min.time() > 0 {
-                if value < self.min.value() {
-                    self.min.set(value, time);
+            Measurement::Timing { duration, time } => {
+                self.check_source(Source::TimeInterval)?;
+                (1, duration, one, time, true)
+            }
+            measurement => {
+                self.flush_thread_local();
+                return self.record(measurement);
+            }
+        };
+        let records = self.buffer_records.get();
+        let interval = Duration::from_nanos(self.buffer_interval.get());
+        BUFFERS.with(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            let buffer = buffers
+                .entry(self.id)
+                .or_insert_with(
+                    || Box::new(LocalBuffer::<<T as AtomicPrimitive>::Primitive>::new()),
+                )
+                .downcast_mut::<LocalBuffer<<T as AtomicPrimitive>::Primitive>>()
+                .unwrap();
+            buffer.add(counter, value, count, time);
+            if extremes {
+                if buffer.max.is_none_or(|(max, _)| value > max) {
+                    buffer.max = Some((value, time));
+                }
+                if buffer.min.is_none_or(|(min, _)| value < min) {
+                    buffer.min = Some((value, time));
                 }
-            } else {
-                self.min.set(value, time);
             }
-            self.last_write.set(time);
-        }
+            if buffer.records >= records || buffer.since.elapsed() >= interval {
+                let buffer = std::mem::replace(buffer, LocalBuffer::new());
+                self.flush_buffer(buffer);
+            }
+        });
+        Ok(())
     }
 
-    // for Increment measurements:
-    // counter tracks sum of all increments
-    // histogram tracks magnitude of increments
-    fn record_increment(&self, count: <T as AtomicPrimitive>::Primitive, time: u64) {
-        if self.source == Source::Counter {
-            self.counter.add(u64::from(count));
-            if let Some(ref histogram) = self.histogram {
-                histogram.increment(
-                    u64::from(count),
-                    <T as AtomicPrimitive>::Primitive::from(1_u8),
-                );
+    /// Flushes the measurements which the calling thread has buffered with
+    /// `record_buffered()` into the `Channel`
+    pub fn flush_thread_local(&self)
+    where
+        <T as AtomicPrimitive>::Primitive: 'static,
+    {
+        let buffer = BUFFERS.with(|buffers| buffers.borrow_mut().remove(&self.id));
+        if let Some(buffer) = buffer {
+            if let Ok(buffer) = buffer.downcast::<LocalBuffer<<T as AtomicPrimitive>::Primitive>>()
+            {
+                self.flush_buffer(*buffer);
             }
-            self.last_write.set(time);
         }
     }
 
-    // for TimeInterval measurements, we increment the histogram with duration of event
-    fn record_time_interval(&self, start: u64, stop: u64) {
-        if self.source == Source::TimeInterval {
-            self.counter.add(1);
-            let duration = stop - start;
-            if let Some(ref histogram) = self.histogram {
-                histogram.increment(duration, <T as AtomicPrimitive>::Primitive::from(1_u8));
-            }
-            // track point of largest interval
-            if self.max.time() > 0 {
-                if duration > self.max.value() {
-                    self.max.set(duration, start);
-                }
-            } else {
-                self.max.set(duration, start);
+    // applies the buffered measurements as they would have been recorded
+    fn flush_buffer(&self, buffer: LocalBuffer<<T as AtomicPrimitive>::Primitive>) {
+        if buffer.records == 0 {
+            return;
+        }
+        self.add_to_counter(buffer.counter);
+        for (value, count) in buffer.values {
+            if self.source == Source::Distribution {
+                self.increment_distribution(value, count);
+            } else if let Some(ref histogram) = self.histogram {
+                histogram.increment(value, count);
             }
-            // track point of smallest interval
-            if self.min.time() > 0 {
-                if duration < self.min.value() {
-                    self.min.set(duration, start);
-                }
-            } else {
-                self.min.set(duration, start);
+        }
+        if let Some((value, time)) = buffer.max {
+            self.update_max(value, time, None);
+        }
+        if let Some((value, time)) = buffer.min {
+            self.min.update_min(value, time);
+        }
+        self.last_write.set(buffer.last_write);
+    }
+
+    /// Records a `Measurement` into the `Channel` and additionally into a
+    /// histogram kept for the `label`, allowing a distribution to be broken
+    /// down, eg: by response status. The labeled histogram is created on first
+    /// use with the range and precision of the `Channel`'s histogram, and none
+    /// is kept if the `Channel` has no histogram. Only the values of
+    /// `Distribution`, `DistributionF64`, `Gauge`, `TimeInterval` and `Timing`
+    /// measurements are recorded into the labeled histogram, and only if the
+    /// measurement is applied, see `set_sampling()`.
+    pub fn record_labeled(
+        &self,
+        label: &str,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<(), RecordError> {
+        let sample = match measurement {
+            Measurement::Distribution { value, count, .. } => Some((value, count)),
+            Measurement::DistributionF64 { value, count, .. } => {
+                scale_f64(value).ok().map(|value| (value, count))
             }
+            Measurement::Gauge { value, .. }
+            | Measurement::Timing {
+                duration: value, ..
+            } => Some((value, <T as AtomicPrimitive>::Primitive::from(1_u8))),
+            Measurement::TimeInterval { start, stop } => stop
+                .checked_sub(start)
+                .map(|duration| (duration, <T as AtomicPrimitive>::Primitive::from(1_u8))),
+            Measurement::Counter { .. }
+            | Measurement::CounterDelta { .. }
+            | Measurement::Increment { .. }
+            | Measurement::Set { .. }
+            | Measurement::SignedGauge { .. } => None,
+        };
+        if !self.record_sampled(measurement)? {
+            return Ok(());
+        }
+        if let (Some((value, count)), Some(histogram)) = (sample, &self.histogram) {
+            self.lock_labeled()
+                .entry(label.to_string())
+                .or_insert_with(|| {
+                    Histogram::new(histogram.max(), histogram.precision(), None, None)
+                })
+                .increment(value, count);
         }
+        Ok(())
     }
 
-    pub fn counter(&self) -> u64 {
-        self.counter.get()
+    /// Records a fractional `Gauge` reading. The value is stored in fixed-point
+    /// as `value * GAUGE_F64_SCALE` rounded to the nearest integer, so it is
+    /// kept to within half of `1 / GAUGE_F64_SCALE`. Negative values are
+    /// stored as zero. The counter, histogram, and max and min points hold the
+    /// scaled values, use `gauge_f64()` to read the latest value back.
+    pub fn record_gauge_f64(&self, value: f64, time: u64) -> Result<(), RecordError> {
+        let value = RoundingMode::Nearest.round(value * GAUGE_F64_SCALE as f64);
+        self.record_gauge(value, time)
     }
 
-    pub fn percentile(&self, percentile: f64) -> Option<u64> {
-        if let Some(ref histogram) = self.histogram {
-            histogram.percentile(percentile)
-        } else {
-            None
+    /// Returns the latest reading recorded with `record_gauge_f64()`
+    pub fn gauge_f64(&self) -> f64 {
+        self.counter() as f64 / GAUGE_F64_SCALE as f64
+    }
+
+    // sums the value into the counter, which wraps on overflow. since the value
+    // is at most u64::MAX, a single add wraps at most once
+    fn add_to_counter(&self, value: u64) {
+        let previous = self.counter.add(value);
+        if previous.checked_add(value).is_none() {
+            self.overflows.add(1);
         }
     }
 
-    pub fn add_output(&self, output: Output) {
-        let mut outputs = self.outputs.lock().unwrap();
-        outputs.insert(output);
+    fn check_source(&self, source: Source) -> Result<(), RecordError> {
+        if self.source == source {
+            Ok(())
+        } else {
+            Err(RecordError::SourceMismatch {
+                expected: self.source,
+                got: source,
+            })
+        }
     }
 
-    pub fn delete_output(&self, output: Output) {
-        let mut outputs = self.outputs.lock().unwrap();
-        outputs.remove(&output);
+    // for Counter measurements:
+    // counter tracks value
+    // histogram tracks rate of change
+    fn record_counter(
+        &self,
+        value: u64,
+        time: u64,
+        rounding: RoundingMode,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::Counter)?;
+        if self.seeded.load(Ordering::SeqCst) && !self.paused.load(Ordering::SeqCst) {
+            // calculate the difference between consecutive readings and the rate
+            let delta_time = time.wrapping_sub(self.last_write.get());
+            if delta_time == 0 {
+                // no rate can be derived, so the sample is dropped and the
+                // next one is compared against the previous baseline
+                return Ok(());
+            }
+            let previous = self.counter.get();
+            let delta_value = value.wrapping_sub(previous);
+            if value < previous && previous - value <= delta_value {
+                // the counter dropped by less than it would have needed to
+                // wrap, so it was reset, eg: the process restarted. re-seed
+                // with the new value instead of recording a bogus rate
+                self.counter.set(value);
+                self.reported.set(0);
+                self.latched_counter.set(0);
+                self.last_write.set(time);
+                return Ok(());
+            }
+            self.counter.add(delta_value);
+            self.record_rate(delta_value, delta_time, time, rounding);
+        } else {
+            self.counter.set(value);
+            self.seeded.store(true, Ordering::SeqCst);
+        }
+        self.last_write.set(time);
+        Ok(())
     }
 
-    pub fn latch(&self) {
-        if self.latched {
-            if let Some(ref histogram) = self.histogram {
-                histogram.clear();
+    // for CounterDelta measurements:
+    // counter tracks sum of deltas
+    // histogram tracks rate of change
+    // unlike a Counter measurement, the first delta is counted, and a delta at
+    // the same time as the previous one is counted without a rate
+    fn record_counter_delta(
+        &self,
+        delta: u64,
+        time: u64,
+        rounding: RoundingMode,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::Counter)?;
+        self.add_to_counter(delta);
+        if self.seeded.load(Ordering::SeqCst) && !self.paused.load(Ordering::SeqCst) {
+            let delta_time = time.wrapping_sub(self.last_write.get());
+            if delta_time > 0 {
+                self.record_rate(delta, delta_time, time, rounding);
             }
+        } else {
+            self.seeded.store(true, Ordering::SeqCst);
         }
-        self.max.set(0, 0);
-        self.min.set(0, 0);
+        self.last_write.set(time);
+        Ok(())
     }
 
-    pub fn zero(&self) {
-        self.has_data.store(false, Ordering::SeqCst);
-        self.last_write.set(0);
-        self.counter.set(0);
+    // records the rate of a counter which increased by delta over delta_time
+    // nanoseconds into the histogram, the rate, and the max and min points.
+    // the rate per second is computed exactly in integers, and saturates if
+    // it does not fit in a u64. delta_time must not be zero
+    fn record_rate(&self, delta: u64, delta_time: u64, time: u64, rounding: RoundingMode) {
+        let rate = rounding.scale_wide(u128::from(delta) * 1_000_000_000, u128::from(delta_time));
+        let rate = u64::try_from(rate).unwrap_or(u64::MAX);
         if let Some(ref histogram) = self.histogram {
-            histogram.clear();
+            histogram.increment(rate, <T as AtomicPrimitive>::Primitive::from(1_u8));
         }
-        self.max.set(0, 0);
-        self.min.set(0, 0);
+        self.rate.set(rate, time);
+        // track the point of max rate
+        self.update_max(rate, time, None);
+        // track the point of min rate
+        self.min.update_min(rate, time);
     }
 
-    pub fn readings(&self) -> Vec<Reading> {
-        let mut result = Vec::new();
-        let outputs = self.outputs.lock().unwrap();
-        for output in &*outputs {
-            match output {
-                Output::Counter => {
-                    result.push(Reading::new(self.name(), output.clone(), self.counter()));
-                }
-                Output::MaxPointTime => {
-                    if self.max.time() > 0 {
-                        result.push(Reading::new(self.name(), output.clone(), self.max.time()));
-                    }
-                }
-                Output::MinPointTime => {
-                    if self.max.time() > 0 {
-                        result.push(Reading::new(self.name(), output.clone(), self.min.time()));
-                    }
-                }
-                Output::Percentile(percentile) => {
-                    if let Some(value) = self.percentile(percentile.as_f64()) {
-                        result.push(Reading::new(self.name(), output.clone(), value));
-                    }
-                }
-            }
+    // for Distribution measurements:
+    // counter tracks sum of all counts
+    // histogram tracks values
+    // max and min track the largest and smallest values, whatever their count
+    // a zero count is not an observation, so nothing is recorded
+    fn record_distribution(
+        &self,
+        value: u64,
+        count: <T as AtomicPrimitive>::Primitive,
+        time: u64,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::Distribution)?;
+        if u64::from(count) == 0 {
+            return Ok(());
         }
-        result
+        self.add_to_counter(u64::from(count));
+        self.increment_distribution(value, count);
+        self.update_max(value, time, None);
+        self.min.update_min(value, time);
+        self.last_write.set(time);
+        Ok(())
     }
 
-    pub fn hash_map(&self) -> HashMap<Output, u64> {
-        let mut result = HashMap::new();
-        let outputs = self.outputs.lock().unwrap();
-        for output in &*outputs {
-            match output {
-                Output::Counter => {
-                    result.insert(output.clone(), self.counter());
-                }
-                Output::MaxPointTime => {
-                    if self.max.time() > 0 {
-                        result.insert(output.clone(), self.max.time());
-                    }
-                }
-                Output::MinPointTime => {
-                    if self.max.time() > 0 {
-                        result.insert(output.clone(), self.min.time());
-                    }
-                }
-                Output::Percentile(percentile) => {
-                    if let Some(value) = self.percentile(percentile.as_f64()) {
-                        result.insert(output.clone(), value);
-                    }
-                }
+    // records a distribution value into the histogram, or counts it as out of
+    // range if it is above the max and the policy excludes it
+    fn increment_distribution(&self, value: u64, count: <T as AtomicPrimitive>::Primitive) {
+        if let Some(ref histogram) = self.histogram {
+            if value > histogram.max() && self.out_of_range_policy() == OutOfRangePolicy::Count {
+                self.out_of_range.saturating_add(u64::from(count));
+            } else {
+                histogram.increment(value, count);
             }
         }
-        result
+    }
+
+    // for DistributionF64 measurements:
+    // counter tracks sum of all counts
+    // histogram tracks values in fixed-point
+    // a zero count is not an observation, so nothing is recorded
+    fn record_distribution_f64(
+        &self,
+        value: f64,
+        count: <T as AtomicPrimitive>::Primitive,
+        time: u64,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::DistributionF64)?;
+        let value = scale_f64(value)?;
+        if u64::from(count) == 0 {
+            return Ok(());
+        }
+        self.add_to_counter(u64::from(count));
+        self.increment_distribution(value, count);
+        self.last_write.set(time);
+        Ok(())
+    }
+
+    // for Gauge measurements:
+    // counter tracks latest reading
+    // histogram tracks readings
+    // max tracks largest reading
+    // min tracks smallest reading
+    fn record_gauge(&self, value: u64, time: u64) -> Result<(), RecordError> {
+        self.check_source(Source::Gauge)?;
+        self.counter.set(value);
+        if let Some(ref histogram) = self.histogram {
+            histogram.increment(value, <T as AtomicPrimitive>::Primitive::from(1_u8));
+        }
+        // track the point of max gauge reading
+        self.update_max(value, time, None);
+        // track the point of min gauge reading
+        self.min.update_min(value, time);
+        if let Some(alpha) = self.ewma_alpha() {
+            let value = value as f64 * GAUGE_F64_SCALE as f64;
+            let mut previous = self.ewma.load(Ordering::Relaxed);
+            loop {
+                let ewma = match previous {
+                    EWMA_EMPTY => value,
+                    previous => previous as f64 + alpha * (value - previous as f64),
+                };
+                // the largest average is kept below EWMA_EMPTY
+                let ewma = RoundingMode::Nearest.round(ewma).min(EWMA_EMPTY - 1);
+                match self.ewma.compare_exchange_weak(
+                    previous,
+                    ewma,
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => previous = current,
+                }
+            }
+        }
+        self.last_write.set(time);
+        Ok(())
+    }
+
+    // for SignedGauge measurements:
+    // counter tracks latest reading, as the bits of an i64
+    // signed max tracks largest reading
+    // signed min tracks smallest reading
+    fn record_signed_gauge(&self, value: i64, time: u64) -> Result<(), RecordError> {
+        self.check_source(Source::SignedGauge)?;
+        self.counter.set(value as u64);
+        self.signed_max.update_max(value, time);
+        self.signed_min.update_min(value, time);
+        self.last_write.set(time);
+        Ok(())
+    }
+
+    // for Set measurements:
+    // counter tracks number of observations
+    // estimator tracks distinct values
+    fn record_set(&self, value: u64, time: u64) -> Result<(), RecordError> {
+        self.check_source(Source::Set)?;
+        self.add_to_counter(1);
+        if let Some(ref set) = self.set {
+            set.insert(value);
+        }
+        self.last_write.set(time);
+        Ok(())
+    }
+
+    // for Increment measurements:
+    // counter tracks sum of all increments
+    // histogram tracks magnitude of increments, optionally weighted by count
+    fn record_increment(
+        &self,
+        count: <T as AtomicPrimitive>::Primitive,
+        time: u64,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::Counter)?;
+        self.add_to_counter(u64::from(count));
+        if let Some(ref histogram) = self.histogram {
+            let weight = if self.weighted_increments() {
+                count
+            } else {
+                <T as AtomicPrimitive>::Primitive::from(1_u8)
+            };
+            histogram.increment(u64::from(count), weight);
+        }
+        self.last_write.set(time);
+        Ok(())
+    }
+
+    // for TimeInterval measurements, we increment the histogram with duration of event
+    // intervals which stop before they start are dropped
+    fn record_time_interval(
+        &self,
+        start: u64,
+        stop: u64,
+        trace_id: Option<u128>,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::TimeInterval)?;
+        match stop.checked_sub(start) {
+            Some(duration) => self.record_timing(duration, start, trace_id),
+            None => Ok(()),
+        }
+    }
+
+    // for Timing measurements, we increment the histogram with the duration
+    // and track the max and min at the time of the event
+    fn record_timing(
+        &self,
+        duration: u64,
+        time: u64,
+        trace_id: Option<u128>,
+    ) -> Result<(), RecordError> {
+        self.check_source(Source::TimeInterval)?;
+        self.add_to_counter(1);
+        if let Some(ref histogram) = self.histogram {
+            histogram.increment(duration, <T as AtomicPrimitive>::Primitive::from(1_u8));
+        }
+        // track point of largest interval
+        self.update_max(duration, time, trace_id);
+        // track point of smallest interval
+        self.min.update_min(duration, time);
+        self.last_write.set(time);
+        Ok(())
+    }
+
+    // updates the max point, keeping the exemplar with it
+    fn update_max(&self, value: u64, time: u64, exemplar: Option<u128>) {
+        if self.max.update_max(value, time) {
+            *self.exemplar.lock().unwrap_or_else(PoisonError::into_inner) = exemplar;
+        }
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.counter.get()
+    }
+
+    /// Returns the trace id recorded with the max point by `record_traced()`,
+    /// or `None` if the max point was recorded without one
+    pub fn exemplar(&self) -> Option<u128> {
+        *self.exemplar.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the latest reading of a `SignedGauge` channel. The counter holds
+    /// the bits of the reading, so `counter()` and the `Counter` output's
+    /// `Reading::value()` are only meaningful as an `i64`, see
+    /// `Reading::signed_value()`.
+    pub fn signed_gauge(&self) -> i64 {
+        self.counter() as i64
+    }
+
+    /// Returns the value and time of the largest reading of a `SignedGauge`
+    /// channel in the current interval, if there is one
+    pub fn signed_max(&self) -> Option<(i64, u64)> {
+        signed_point(&self.signed_max)
+    }
+
+    /// Returns the value and time of the smallest reading of a `SignedGauge`
+    /// channel in the current interval, if there is one
+    pub fn signed_min(&self) -> Option<(i64, u64)> {
+        signed_point(&self.signed_min)
+    }
+
+    /// Returns the moving average of `Gauge` readings, or `None` if it is not
+    /// enabled or there has been no reading since, see `set_ewma_alpha()`. The
+    /// average is kept in fixed-point to within `1 / GAUGE_F64_SCALE`.
+    pub fn ewma(&self) -> Option<f64> {
+        match self.ewma.get() {
+            EWMA_EMPTY => None,
+            ewma => Some(ewma as f64 / GAUGE_F64_SCALE as f64),
+        }
+    }
+
+    /// Returns the increase of the counter since the last `latch()`, eg: the
+    /// number of requests in the current interval, while `counter()` remains
+    /// the lifetime total. For `Gauge` and `SignedGauge` channels the counter
+    /// holds the latest reading, so this is not meaningful and the
+    /// `CounterInterval` output is not reported.
+    pub fn counter_since_latch(&self) -> u64 {
+        self.counter().wrapping_sub(self.latched_counter.get())
+    }
+
+    /// Returns the estimated number of distinct values recorded into a `Set`
+    /// channel, or `None` for other sources. The estimate has a standard error
+    /// of 0.81%, so roughly 99% of estimates are within 2.5% of the true
+    /// cardinality. Small cardinalities are estimated more closely, typically
+    /// exactly below a few hundred.
+    pub fn cardinality(&self) -> Option<u64> {
+        self.set.as_ref().map(HyperLogLog::estimate)
+    }
+
+    /// Returns true if anything has been recorded into the `Channel` which it
+    /// still reports, that is the counter is non-zero or the histogram holds
+    /// samples, eg: so that a reporter can skip empty series. A `Gauge` whose
+    /// latest reading is zero and has no histogram reports false.
+    pub fn has_data(&self) -> bool {
+        self.counter() != 0
+            || self
+                .histogram
+                .as_ref()
+                .is_some_and(|histogram| !histogram.is_empty())
+    }
+
+    /// Returns true if the `Channel` holds the equivalent of the data in the
+    /// `other`, see `approx_diff()`. Unlike `==`, which compares only the
+    /// names, this is meant for verifying recorded data, eg: in tests.
+    pub fn approx_eq(&self, other: &Channel<T>, tolerance: f64) -> bool {
+        self.approx_diff(other, tolerance).is_empty()
+    }
+
+    /// Returns a description of each difference between the data of the
+    /// `Channel` and that of the `other`, or none if they are equivalent. The
+    /// sources must match, and the counters and a spread of percentiles from
+    /// the minimum to the maximum must be within the relative `tolerance`, eg:
+    /// 0.01 for 1%, of the larger of the two. A percentile present in only one
+    /// of the `Channel`s is a difference. The names are not compared.
+    pub fn approx_diff(&self, other: &Channel<T>, tolerance: f64) -> Vec<String> {
+        let mut diff = Vec::new();
+        if self.source != other.source {
+            diff.push(format!("source: {} != {}", self.source, other.source));
+        }
+        if !within(self.counter(), other.counter(), tolerance) {
+            diff.push(format!(
+                "counter: {} != {}",
+                self.counter(),
+                other.counter()
+            ));
+        }
+        for percentile in &COMPARED_PERCENTILES {
+            let values = (self.percentile(*percentile), other.percentile(*percentile));
+            let equal = match values {
+                (Some(a), Some(b)) => within(a, b, tolerance),
+                (a, b) => a == b,
+            };
+            if !equal {
+                diff.push(format!(
+                    "p{}: {:?} != {:?}",
+                    percentile * 100.0,
+                    values.0,
+                    values.1
+                ));
+            }
+        }
+        diff
+    }
+
+    /// Returns the number of samples in the histogram, including those above
+    /// its max, or zero if there is no histogram. This differs from `counter()`
+    /// whenever the histogram does not hold one sample per unit of the
+    /// counter, eg: each `Increment` is a single sample of its magnitude unless
+    /// increments are weighted, and latching clears the histogram but keeps
+    /// the counter.
+    pub fn sample_count(&self) -> u64 {
+        if let Some(ref histogram) = self.histogram {
+            histogram.total_count()
+        } else {
+            0
+        }
+    }
+
+    /// Returns true if a bucket of the histogram, or of a labeled histogram,
+    /// has saturated at the max of the counter type `T`, see
+    /// `Histogram::saturated`. If so, a wider `T` is needed for accurate
+    /// percentiles.
+    pub fn saturated(&self) -> bool {
+        self.histogram.as_ref().is_some_and(Histogram::saturated)
+            || self.lock_labeled().values().any(Histogram::saturated)
+    }
+
+    /// Returns the number of times the counter has wrapped past `u64::MAX`
+    /// while summing `Distribution`, `DistributionF64`, `Increment`, or timing
+    /// measurements. The counter of a `Counter` channel mirrors the measured
+    /// value, which wraps with its source, and is not counted.
+    pub fn overflows(&self) -> u64 {
+        self.overflows.get()
+    }
+
+    /// Returns the count of `Distribution` and `DistributionF64` values which
+    /// were above the max of the histogram and excluded from it under
+    /// `OutOfRangePolicy::Count`
+    pub fn out_of_range(&self) -> u64 {
+        self.out_of_range.get()
+    }
+
+    /// Returns the time of the most recent measurement, or zero if nothing
+    /// has been recorded, eg: to detect a stale `Channel`
+    pub fn last_write(&self) -> u64 {
+        self.last_write.get()
+    }
+
+    /// Returns the rate per second derived from the two most recent `Counter`
+    /// measurements, or `None` if no rate has been derived since the `Channel`
+    /// was created or zeroed
+    pub fn rate(&self) -> Option<u64> {
+        if self.rate.time() > 0 {
+            Some(self.rate.value())
+        } else {
+            None
+        }
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        if let Some(ref histogram) = self.histogram {
+            self.lookup_percentiles(histogram, &[percentile], || {
+                vec![histogram.percentile(percentile)]
+            })[0]
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value at the percentile from 0.0-1.0, as with `percentile()`,
+    /// or `None` if the histogram holds fewer than `min` samples
+    pub fn percentile_min_samples(&self, percentile: f64, min: u64) -> Option<u64> {
+        if self.sample_count() < min {
+            None
+        } else {
+            self.percentile(percentile)
+        }
+    }
+
+    /// Returns the fraction of samples at or below `value`, from 0.0-1.0, see
+    /// `Histogram::rank`. Returns `None` if there is no histogram or it is
+    /// empty.
+    pub fn rank(&self, value: u64) -> Option<f64> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.rank(value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the relative error of the percentile from 0.0-1.0 due to the
+    /// width of the histogram bucket holding it, see
+    /// `Histogram::percentile_error`. Returns `None` if there is no histogram
+    /// or it is empty.
+    pub fn percentile_error(&self, percentile: f64) -> Option<f64> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.percentile_error(percentile)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the lowest and highest values of the histogram bucket holding
+    /// the percentile from 0.0-1.0, see `Histogram::percentile_bucket`.
+    /// Returns `None` if there is no histogram or it is empty.
+    pub fn percentile_bucket(&self, percentile: f64) -> Option<(u64, u64)> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.percentile_bucket(percentile)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value at the percentile, interpolated within its histogram
+    /// bucket, see `Histogram::percentile_interpolated`. Returns `None` if
+    /// there is no histogram or it is empty.
+    pub fn percentile_interpolated(&self, percentile: f64) -> Option<f64> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.percentile_interpolated(percentile)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value at the percentile of a `DistributionF64` channel. The
+    /// histogram is log-linear over the fixed-point values, so values below
+    /// `10^precision / DISTRIBUTION_F64_SCALE` are exact to within
+    /// `1 / DISTRIBUTION_F64_SCALE` and larger values to within the relative
+    /// error of the histogram precision.
+    pub fn percentile_f64(&self, percentile: f64) -> Option<f64> {
+        self.percentile(percentile)
+            .map(|value| value as f64 / DISTRIBUTION_F64_SCALE as f64)
+    }
+
+    /// Returns the value at the percentile of the measurements recorded with
+    /// the `label`, or `None` if there are none
+    pub fn labeled_percentile(&self, label: &str, percentile: f64) -> Option<u64> {
+        self.lock_labeled()
+            .get(label)
+            .and_then(|histogram| histogram.percentile(percentile))
+    }
+
+    /// Returns the value at the percentile along with the count of samples at
+    /// or below it, see `Histogram::percentile_with_count`. Returns `None` if
+    /// there is no histogram or it is empty.
+    pub fn percentile_with_count(&self, percentile: f64) -> Option<(u64, u64)> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.percentile_with_count(percentile)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the mean of the values in the histogram, or `None` if there is
+    /// no histogram or it is empty
+    pub fn mean(&self) -> Option<f64> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.mean()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value at each of the percentiles, in the order given, with
+    /// all of them found in a single pass over the histogram
+    pub fn percentiles(&self, percentiles: &[f64]) -> Vec<(f64, Option<u64>)> {
+        let values = if let Some(ref histogram) = self.histogram {
+            self.lookup_percentiles(histogram, percentiles, || {
+                histogram.percentiles(percentiles)
+            })
+        } else {
+            vec![None; percentiles.len()]
+        };
+        percentiles.iter().cloned().zip(values).collect()
+    }
+
+    /// Returns the nominal value and count of each non-empty bucket in the
+    /// histogram, eg: for rendering the full distribution as a heatmap
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.buckets().collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns the standard deviation of the values in the histogram, or `None`
+    /// if there is no histogram or it is empty
+    pub fn std_dev(&self) -> Option<f64> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.std_dev()
+        } else {
+            None
+        }
+    }
+
+    /// Pauses rate computation for `Counter` measurements. While paused, the
+    /// counter continues to track the latest reading, but no rate is recorded
+    /// into the histogram or used for the max and min points. Unlike dropping
+    /// measurements, the `Channel` stays current throughout the pause.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes rate computation after a `pause()`. The next `Counter`
+    /// measurement re-seeds the baseline instead of producing a rate across
+    /// the paused interval.
+    pub fn resume(&self) {
+        self.seeded.store(false, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Returns the value at the percentile when each sample is weighted by its
+    /// magnitude, eg: the request size below which 99% of all bytes were sent
+    pub fn weighted_percentile(&self, percentile: f64) -> Option<u64> {
+        if let Some(ref histogram) = self.histogram {
+            histogram.weighted_percentile(percentile)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the percentile divided by `divisor`, eg: to convert from ns to
+    /// ms, with the quotient rounded according to the `RoundingMode`
+    pub fn percentile_scaled(&self, percentile: f64, divisor: u64) -> Option<u64> {
+        let rounding = self.rounding();
+        self.percentile(percentile)
+            .map(|value| rounding.scale(value, divisor))
+    }
+
+    /// Records each of the `values` into the histogram with a count of one,
+    /// eg: to replay latencies captured from a trace for offline analysis.
+    /// Only the histogram is changed, the counter and the max and min points
+    /// are left as they are. Does nothing if the `Channel` has no histogram.
+    pub fn load_values(&self, values: &[u64]) {
+        if let Some(ref histogram) = self.histogram {
+            let one = <T as AtomicPrimitive>::Primitive::from(1_u8);
+            for value in values {
+                histogram.increment(*value, one);
+            }
+        }
+    }
+
+    /// Returns the percentile converted from the `TimeUnit` of the `Channel`
+    /// into the `unit`, eg: to report a latency recorded in ns as ms. Unlike
+    /// `percentile_scaled()`, the fractional part is kept.
+    pub fn percentile_as(&self, percentile: f64, unit: TimeUnit) -> Option<f64> {
+        let recorded = self.unit();
+        self.percentile(percentile)
+            .map(|value| recorded.convert(value as f64, unit))
+    }
+
+    /// Exports the histogram as a compact digest containing the nominal value
+    /// and count of each non-empty bucket. Samples above the histogram's
+    /// maximum are encoded with a value one greater than the maximum. The
+    /// digest may be merged into another `Channel` with `merge_digest()`.
+    pub fn export_digest(&self) -> Vec<u8> {
+        let mut digest = Vec::new();
+        if let Some(ref histogram) = self.histogram {
+            let mut top = 0;
+            for bucket in histogram {
+                let count = u64::from(bucket.count());
+                if count > 0 {
+                    digest.extend_from_slice(&bucket.value().to_le_bytes());
+                    digest.extend_from_slice(&count.to_le_bytes());
+                }
+                top = bucket.max();
+            }
+            let too_high = histogram.too_high();
+            if too_high > 0 {
+                digest.extend_from_slice(&top.to_le_bytes());
+                digest.extend_from_slice(&too_high.to_le_bytes());
+            }
+        }
+        digest
+    }
+
+    /// Merges a digest produced by `export_digest()` into this `Channel`'s
+    /// histogram. Each value is re-bucketed, so the accuracy of the result is
+    /// bounded by the precision of this `Channel`'s histogram.
+    pub fn merge_digest(&self, encoded: &[u8]) -> Result<(), DigestError>
+    where
+        <T as AtomicPrimitive>::Primitive: TryFrom<u64>,
+    {
+        let histogram = self.histogram.as_ref().ok_or(DigestError::NoHistogram)?;
+        let chunks = encoded.chunks_exact(DIGEST_ENTRY_SIZE);
+        if !chunks.remainder().is_empty() {
+            return Err(DigestError::Truncated);
+        }
+        let mut entries = Vec::with_capacity(chunks.len());
+        for entry in chunks {
+            let mut value = [0; 8];
+            let mut count = [0; 8];
+            value.copy_from_slice(&entry[0..8]);
+            count.copy_from_slice(&entry[8..16]);
+            let count = <T as AtomicPrimitive>::Primitive::try_from(u64::from_le_bytes(count))
+                .map_err(|_| DigestError::Overflow)?;
+            entries.push((u64::from_le_bytes(value), count));
+        }
+        for (value, count) in entries {
+            histogram.increment(value, count);
+        }
+        Ok(())
+    }
+
+    /// Merges another `Channel` with the same `Source` into this one. The
+    /// counters are summed, the histograms are merged if both `Channel`s have
+    /// one, and the max and min points are the extremes of the two.
+    pub fn merge(&self, other: &Channel<T>) -> Result<(), RecordError> {
+        self.check_source(other.source)?;
+        self.add_to_counter(other.counter());
+        self.overflows.add(other.overflows());
+        if let (Some(histogram), Some(other)) = (&self.histogram, &other.histogram) {
+            histogram.merge(other);
+        }
+        if let (Some(set), Some(other)) = (&self.set, &other.set) {
+            set.merge(other);
+        }
+        if other.max.time() > 0 {
+            self.update_max(other.max.value(), other.max.time(), other.exemplar());
+        }
+        if other.min.time() > 0 {
+            self.min.update_min(other.min.value(), other.min.time());
+        }
+        if let Some((value, time)) = other.signed_max() {
+            self.signed_max.update_max(value, time);
+        }
+        if let Some((value, time)) = other.signed_min() {
+            self.signed_min.update_min(value, time);
+        }
+        if other.last_write() > self.last_write() {
+            self.last_write.set(other.last_write());
+        }
+        Ok(())
+    }
+
+    pub fn add_output(&self, output: Output) {
+        Arc::make_mut(&mut self.lock_outputs()).insert(output);
+    }
+
+    /// Registers the outputs commonly reported by benchmarks: the counter, the
+    /// max value, and the p50, p90, p99, and p999 percentiles. Any outputs
+    /// already registered are kept.
+    pub fn with_default_percentiles(self) -> Self {
+        self.add_output(Output::Counter);
+        self.add_output(Output::MaxValue);
+        for percentile in &DEFAULT_PERCENTILES {
+            self.add_output(Output::Percentile(*percentile));
+        }
+        self
+    }
+
+    pub fn delete_output(&self, output: Output) {
+        Arc::make_mut(&mut self.lock_outputs()).remove(&output);
+    }
+
+    /// Removes all outputs from the `Channel`
+    pub fn clear_outputs(&self) {
+        *self.lock_outputs() = Arc::new(HashSet::new());
+    }
+
+    /// Replaces the outputs of the `Channel` with those named in a comma
+    /// separated `spec`, eg: `counter,p50,p99,max_time`. Each token is parsed
+    /// with `Output::from_str` after trimming whitespace, and empty tokens are
+    /// ignored, so an empty spec removes all outputs. If any token is not
+    /// recognized, the outputs are left unchanged and an error listing the
+    /// unrecognized tokens is returned.
+    pub fn configure_outputs(&self, spec: &str) -> Result<(), OutputSpecError> {
+        let mut outputs = HashSet::new();
+        let mut unrecognized = Vec::new();
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match token.parse::<Output>() {
+                Ok(output) => {
+                    outputs.insert(output);
+                }
+                Err(_) => unrecognized.push(token.to_string()),
+            }
+        }
+        if !unrecognized.is_empty() {
+            return Err(OutputSpecError::Unrecognized(unrecognized));
+        }
+        *self.lock_outputs() = Arc::new(outputs);
+        Ok(())
+    }
+
+    /// Returns the outputs currently registered with the `Channel`
+    pub fn outputs(&self) -> Vec<Output> {
+        self.current_outputs().iter().cloned().collect()
+    }
+
+    // outputs are only inserted and removed under the lock, so the set is
+    // always consistent and can be recovered if a thread panicked holding it.
+    // a set which is shared with a reader is copied before it is modified
+    fn lock_outputs(&self) -> MutexGuard<'_, Arc<HashSet<Output>>> {
+        self.outputs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // the lock is only held to clone the set, so readers iterate over the
+    // outputs without blocking changes to them
+    fn current_outputs(&self) -> Arc<HashSet<Output>> {
+        self.lock_outputs().clone()
+    }
+
+    fn lock_labeled(&self) -> MutexGuard<'_, HashMap<String, Histogram<T>>> {
+        self.labeled.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Begins a new interval. For latched channels the histograms and the
+    /// distinct values of a `Set` channel are cleared, and the max and min
+    /// points are always reset. The counter and the rate baseline are kept, so
+    /// the first `Counter` measurement after a latch derives a rate against
+    /// the last measurement before it, see `latch_and_reset_rate()` to start
+    /// the interval without a baseline. The counter is noted as the start of
+    /// the interval for `counter_since_latch()`.
+    pub fn latch(&self) {
+        self.latched_counter.set(self.counter());
+        if self.latched {
+            if let Some(ref histogram) = self.histogram {
+                histogram.clear();
+            }
+            if let Some(ref set) = self.set {
+                set.clear();
+            }
+            self.lock_labeled().clear();
+        }
+        self.reset_extremes();
+    }
+
+    /// Resets the max and min points without clearing the histogram or the
+    /// counter, eg: to report the max rate of each minute while percentiles
+    /// cover a longer window. `latch()` also resets them.
+    pub fn reset_extremes(&self) {
+        self.max.set(0, 0);
+        *self.exemplar.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        self.min.set(0, 0);
+        self.signed_max.reset();
+        self.signed_min.reset();
+    }
+
+    /// Latches the `Channel` as with `latch()` and also discards the rate
+    /// baseline, eg: when the source of `Counter` measurements was paused for
+    /// the whole of the previous interval. The next `Counter` measurement then
+    /// re-seeds the counter without deriving a rate, as it would for a new
+    /// `Channel`, and `rate()` returns `None` until another is derived.
+    pub fn latch_and_reset_rate(&self) {
+        self.latch();
+        self.seeded.store(false, Ordering::SeqCst);
+        self.rate.reset();
+    }
+
+    /// Latches the `Channel` as with `latch()`, returning the contents of the
+    /// histogram for the interval. For latched channels the histogram is
+    /// drained, so the snapshot holds exactly the samples which were cleared
+    /// and samples recorded concurrently are never lost. Returns `None` if the
+    /// `Channel` has no histogram.
+    pub fn latch_and_snapshot(&self) -> Option<HistogramSnapshot> {
+        self.latched_counter.set(self.counter());
+        let snapshot = self.histogram.as_ref().map(|histogram| {
+            if self.latched {
+                histogram.drain()
+            } else {
+                histogram.snapshot()
+            }
+        });
+        if self.latched {
+            if let Some(ref set) = self.set {
+                set.clear();
+            }
+            self.lock_labeled().clear();
+        }
+        self.reset_extremes();
+        snapshot
+    }
+
+    /// Discards the samples below the `threshold` from the histogram and the
+    /// labeled histograms, eg: near-zero latencies from connection setup
+    /// during warmup, see `Histogram::clear_below`. The counter and the max
+    /// and min points are kept.
+    pub fn trim_below(&self, threshold: u64) {
+        if let Some(ref histogram) = self.histogram {
+            histogram.clear_below(threshold);
+        }
+        for histogram in self.lock_labeled().values() {
+            histogram.clear_below(threshold);
+        }
+    }
+
+    pub fn zero(&self) {
+        self.seeded.store(false, Ordering::SeqCst);
+        self.last_write.set(0);
+        self.counter.set(0);
+        self.overflows.set(0);
+        self.out_of_range.set(0);
+        self.reported.set(0);
+        self.latched_counter.set(0);
+        if let Some(ref histogram) = self.histogram {
+            histogram.clear();
+        }
+        if let Some(ref set) = self.set {
+            set.clear();
+        }
+        self.lock_labeled().clear();
+        self.reset_extremes();
+        self.rate.reset();
+        self.ewma.set(EWMA_EMPTY);
+    }
+
+    /// Captures the counter, the percentile outputs, and the max and min
+    /// points in a single pass. No lock is held while reading the outputs or
+    /// taken against recording, so a measurement recorded concurrently may
+    /// still land between the reads. As with `percentile()`, the percentiles
+    /// are approximate within the precision of the histogram.
+    pub fn snapshot(&self) -> ChannelSnapshot {
+        let mut percentiles: Vec<Percentile> = self
+            .current_outputs()
+            .iter()
+            .filter_map(|output| match output {
+                Output::Percentile(percentile) => Some(*percentile),
+                _ => None,
+            })
+            .collect();
+        percentiles.sort_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).unwrap());
+        let values: Vec<f64> = percentiles.iter().map(|p| p.as_f64()).collect();
+        let point = |point: &Point| {
+            if point.time() > 0 {
+                Some((point.value(), point.time()))
+            } else {
+                None
+            }
+        };
+        ChannelSnapshot {
+            name: self.name(),
+            counter: self.counter(),
+            percentiles: self
+                .percentiles(&values)
+                .into_iter()
+                .zip(percentiles)
+                .filter_map(|((_, value), p)| value.map(|value| (p, value)))
+                .collect(),
+            max: point(&self.max),
+            min: point(&self.min),
+        }
+    }
+
+    /// Returns a `Reading` for each registered output which has a value. The
+    /// `Percentile` outputs are also produced for each label recorded with
+    /// `record_labeled()`, named with the label appended, eg: `name/label`.
+    /// Each `Reading` carries the labels set with `set_label()` and, once a
+    /// measurement has been recorded, the time of the latest one from
+    /// `last_write()`. This is also the time of the latest reading of a
+    /// `Counter` channel, as its value only changes when one is recorded.
+    pub fn readings(&self) -> Vec<Reading> {
+        let mut readings: Vec<Reading> = self
+            .values()
+            .into_iter()
+            .map(|(output, value)| self.reading(output, value))
+            .collect();
+        readings.extend(self.labeled_readings());
+        self.annotate(readings)
+    }
+
+    // the counter of a SignedGauge channel holds a signed reading
+    // and the max time reading carries the exemplar of the max point
+    fn reading(&self, output: Output, value: u64) -> Reading {
+        match output {
+            Output::Counter | Output::MaxValue | Output::MinValue
+                if self.source == Source::SignedGauge =>
+            {
+                Reading::new_signed(self.name(), output, value as i64)
+            }
+            Output::MaxPointTime => match self.exemplar() {
+                Some(trace_id) => Reading::new(self.name(), output, value).with_exemplar(trace_id),
+                None => Reading::new(self.name(), output, value),
+            },
+            _ => Reading::new(self.name(), output, value),
+        }
+    }
+
+    // attaches the labels and the time to each reading
+    fn annotate(&self, readings: Vec<Reading>) -> Vec<Reading> {
+        let labels = self.labels();
+        let time = self.last_write();
+        readings
+            .into_iter()
+            .map(|reading| {
+                let reading = if labels.is_empty() {
+                    reading
+                } else {
+                    reading.with_labels(labels.clone())
+                };
+                if time > 0 {
+                    reading.with_time(time)
+                } else {
+                    reading
+                }
+            })
+            .collect()
+    }
+
+    // the percentile outputs of each labeled histogram
+    fn labeled_readings(&self) -> Vec<Reading> {
+        let percentiles: Vec<Percentile> = self
+            .current_outputs()
+            .iter()
+            .filter_map(|output| match output {
+                Output::Percentile(percentile) => Some(*percentile),
+                _ => None,
+            })
+            .collect();
+        if percentiles.is_empty() {
+            return Vec::new();
+        }
+        let ps: Vec<f64> = percentiles.iter().map(|p| p.as_f64()).collect();
+        let name = self.name();
+        let min_samples = self.min_samples();
+        let mut readings = Vec::new();
+        for (label, histogram) in self.lock_labeled().iter() {
+            if histogram.total_count() < min_samples {
+                continue;
+            }
+            for (percentile, value) in percentiles.iter().zip(histogram.percentiles(&ps)) {
+                if let Some(value) = value {
+                    readings.push(Reading::new(
+                        format!("{}/{}", name, label),
+                        Output::Percentile(*percentile),
+                        value,
+                    ));
+                }
+            }
+        }
+        readings
+    }
+
+    /// Returns the `Reading`s with the `Counter` output reporting only the
+    /// increase since the previous call, for backends which expect delta
+    /// counters. The counter itself is not modified, so `counter()` and
+    /// `readings()` remain monotonic and rates derived from `Counter`
+    /// measurements are unaffected. For `Gauge` channels the counter holds the
+    /// latest reading and is reported unchanged.
+    pub fn readings_and_reset(&self) -> Vec<Reading> {
+        let delta = self.counter_delta();
+        let mut readings: Vec<Reading> = self
+            .values()
+            .into_iter()
+            .map(|(output, value)| {
+                if output == Output::Counter {
+                    self.reading(output, delta)
+                } else {
+                    self.reading(output, value)
+                }
+            })
+            .collect();
+        readings.extend(self.labeled_readings());
+        self.annotate(readings)
+    }
+
+    // advances the reported baseline to the current counter value, returning
+    // the increase since the previous baseline. the baseline only moves by a
+    // successful compare-exchange, so concurrent callers partition the
+    // increments between them and none are counted twice or lost
+    fn counter_delta(&self) -> u64 {
+        if self.source == Source::Gauge || self.source == Source::SignedGauge {
+            return self.counter();
+        }
+        loop {
+            let previous = self.reported.get();
+            let current = self.counter();
+            if self
+                .reported
+                .compare_exchange(previous, current, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return current.wrapping_sub(previous);
+            }
+        }
+    }
+
+    pub fn hash_map(&self) -> HashMap<Output, u64> {
+        self.values().into_iter().collect()
+    }
+
+    /// Returns the value of every registered output, with `None` for those
+    /// which have no value, eg: a percentile while the histogram is empty.
+    /// Unlike `hash_map()`, each output is present as a key, so "no data" can
+    /// be told apart from an output which is not registered.
+    pub fn hash_map_with_status(&self) -> HashMap<Output, Option<u64>> {
+        self.statuses().into_iter().collect()
+    }
+
+    // the current value of each registered output which has one
+    fn values(&self) -> Vec<(Output, u64)> {
+        self.statuses()
+            .into_iter()
+            .filter_map(|(output, value)| value.map(|value| (output, value)))
+            .collect()
+    }
+
+    // the current value of each registered output, with all the percentiles
+    // found in a single pass over the histogram
+    fn statuses(&self) -> Vec<(Output, Option<u64>)> {
+        let outputs = self.current_outputs();
+        let percentiles: Vec<f64> = outputs
+            .iter()
+            .filter_map(|output| match output {
+                Output::Percentile(percentile) => Some(percentile.as_f64()),
+                _ => None,
+            })
+            .collect();
+        let enough = self.sample_count() >= self.min_samples();
+        let mut percentiles = if enough && percentiles.len() > 1 {
+            self.percentiles(&percentiles).into_iter()
+        } else {
+            Vec::new().into_iter()
+        };
+        let mut result = Vec::new();
+        for output in &*outputs {
+            let value = match output {
+                Output::Percentile(_) if !enough => None,
+                Output::Percentile(_) => match percentiles.next() {
+                    Some((_, value)) => value,
+                    None => self.value(output),
+                },
+                _ => self.value(output),
+            };
+            result.push((output.clone(), value));
+        }
+        result
+    }
+
+    // the current value for an output, if there is one
+    fn value(&self, output: &Output) -> Option<u64> {
+        match output {
+            Output::Cardinality => self.cardinality(),
+            Output::Counter => Some(self.counter()),
+            Output::CounterInterval => match self.source {
+                Source::Gauge | Source::SignedGauge => None,
+                _ => Some(self.counter_since_latch()),
+            },
+            Output::Ewma => self.ewma().map(|ewma| self.rounding().round(ewma)),
+            Output::OutOfRange => Some(self.out_of_range()),
+            Output::Overflows => Some(self.overflows()),
+            Output::LastWriteTime => {
+                if self.last_write() > 0 {
+                    Some(self.last_write())
+                } else {
+                    None
+                }
+            }
+            Output::MaxPointTime => {
+                if self.max.time() > 0 {
+                    Some(self.max.time())
+                } else {
+                    self.signed_max().map(|(_, time)| time)
+                }
+            }
+            Output::MaxValue => {
+                if self.max.time() > 0 {
+                    Some(self.max.value())
+                } else {
+                    self.signed_max().map(|(value, _)| value as u64)
+                }
+            }
+            Output::Mean => self.mean().map(|mean| self.rounding().round(mean)),
+            Output::MinPointTime => {
+                if self.min.time() > 0 {
+                    Some(self.min.time())
+                } else {
+                    self.signed_min().map(|(_, time)| time)
+                }
+            }
+            Output::MinValue => {
+                if self.min.time() > 0 {
+                    Some(self.min.value())
+                } else {
+                    self.signed_min().map(|(value, _)| value as u64)
+                }
+            }
+            Output::Percentile(percentile) => {
+                self.percentile_min_samples(percentile.as_f64(), self.min_samples())
+            }
+            Output::Rate => self.rate(),
+            Output::StdDev => self.std_dev().map(|std_dev| self.rounding().round(std_dev)),
+        }
+    }
+}
+
+/// Returns the `Reading`s of all the `Channel`s sorted by name, then by the
+/// `Display` name of the output, and then by the labels, so exports are in a
+/// stable order, eg: for comparing against a file of expected output. A
+/// `Reading` with the same name, output, and labels as an earlier one is
+/// dropped, such as when a `Channel` appears twice.
+pub fn collect_readings<T>(channels: &[&Channel<T>]) -> Vec<Reading>
+where
+    T: Counter + Unsigned + 'static,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    let mut readings: Vec<(ReadingKey, Reading)> = channels
+        .iter()
+        .flat_map(|channel| channel.readings())
+        .map(|reading| {
+            let mut labels: Vec<(String, String)> = reading.labels().into_iter().collect();
+            labels.sort();
+            (
+                (reading.label(), reading.output().to_string(), labels),
+                reading,
+            )
+        })
+        .collect();
+    readings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    readings.dedup_by(|(b, _), (a, _)| a == b);
+    readings.into_iter().map(|(_, reading)| reading).collect()
+}
+
+// the name, output, and sorted labels which identify a reading
+type ReadingKey = (String, String, Vec<(String, String)>);
+
+// whether the values differ by at most the tolerance relative to the larger
+fn within(a: u64, b: u64, tolerance: f64) -> bool {
+    (a as f64 - b as f64).abs() <= tolerance * a.max(b) as f64
+}
+
+fn signed_point(point: &SignedPoint) -> Option<(i64, u64)> {
+    if point.time() > 0 {
+        Some((point.value(), point.time()))
+    } else {
+        None
+    }
+}
+
+// converts a fractional observation to fixed-point. values are rounded to the
+// nearest multiple of 1 / DISTRIBUTION_F64_SCALE, so positive values too small
+// to be represented are recorded as zero rather than rejected
+fn scale_f64(value: f64) -> Result<u64, RecordError> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(RoundingMode::Nearest.round(value * DISTRIBUTION_F64_SCALE as f64))
+    } else {
+        Err(RecordError::InvalidValue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point_times(channel: &Channel<AtomicU64>) -> (Option<u64>, Option<u64>) {
+        let map = channel.hash_map();
+        let readings = channel.readings();
+        let reading = |output: Output| {
+            readings
+                .iter()
+                .find(|r| r.output() == output)
+                .map(|r| r.value())
+        };
+        assert_eq!(
+            reading(Output::MaxPointTime),
+            map.get(&Output::MaxPointTime).cloned()
+        );
+        assert_eq!(
+            reading(Output::MinPointTime),
+            map.get(&Output::MinPointTime).cloned()
+        );
+        (reading(Output::MaxPointTime), reading(Output::MinPointTime))
+    }
+
+    #[test]
+    fn point_times_independent() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        channel.add_output(Output::MaxPointTime);
+        channel.add_output(Output::MinPointTime);
+        assert_eq!(point_times(&channel), (None, None));
+        channel
+            .record(Measurement::Gauge { value: 5, time: 1 })
+            .unwrap();
+        assert_eq!(point_times(&channel), (Some(1), Some(1)));
+        channel
+            .record(Measurement::Gauge { value: 10, time: 2 })
+            .unwrap();
+        channel
+            .record(Measurement::Gauge { value: 1, time: 3 })
+            .unwrap();
+        assert_eq!(point_times(&channel), (Some(2), Some(3)));
+    }
+
+    #[test]
+    fn point_times_min_reset() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        channel.add_output(Output::MaxPointTime);
+        channel.add_output(Output::MinPointTime);
+        channel
+            .record(Measurement::Gauge { value: 5, time: 1 })
+            .unwrap();
+        channel.min.reset();
+        assert_eq!(point_times(&channel), (Some(1), None));
+        channel
+            .record(Measurement::Gauge { value: 3, time: 2 })
+            .unwrap();
+        assert_eq!(point_times(&channel), (Some(1), Some(2)));
+        channel.max.reset();
+        assert_eq!(point_times(&channel), (None, Some(2)));
+    }
+
+    #[test]
+    fn counter_same_time() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+        channel
+            .record(Measurement::Counter {
+                value: 0,
+                time: 1_000_000_000,
+            })
+            .unwrap();
+        channel
+            .record(Measurement::Counter {
+                value: 10,
+                time: 1_000_000_000,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(1.0), None);
+        assert_eq!(channel.max.time(), 0);
+        assert_eq!(channel.min.time(), 0);
+        assert_eq!(channel.counter(), 0);
+
+        // the next sample measures the rate across the dropped one
+        channel
+            .record(Measurement::Counter {
+                value: 20,
+                time: 2_000_000_000,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(1.0), Some(20));
+        assert_eq!(channel.max.value(), 20);
+        assert_eq!(channel.min.value(), 20);
+        assert_eq!(channel.counter(), 20);
+    }
+
+    #[test]
+    fn time_interval_backwards() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        channel
+            .record(Measurement::TimeInterval {
+                start: 100,
+                stop: 50,
+            })
+            .unwrap();
+        assert_eq!(channel.counter(), 0);
+        assert_eq!(channel.percentile(1.0), None);
+        assert_eq!(channel.max.time(), 0);
+        assert_eq!(channel.min.time(), 0);
+
+        channel
+            .record(Measurement::TimeInterval {
+                start: 100,
+                stop: 150,
+            })
+            .unwrap();
+        assert_eq!(channel.counter(), 1);
+        assert_eq!(channel.percentile(1.0), Some(50));
+    }
+
+    #[test]
+    fn timing() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        for duration in 1..=100 {
+            channel
+                .record(Measurement::Timing {
+                    duration,
+                    time: 1_000 + duration,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 100);
+        assert_eq!(channel.percentile(0.0), Some(1));
+        assert_eq!(channel.percentile(0.5), Some(50));
+        assert_eq!(channel.percentile(0.99), Some(99));
+        assert_eq!(channel.percentile(1.0), Some(100));
+        assert_eq!(channel.max.value(), 100);
+        assert_eq!(channel.max.time(), 1_100);
+        assert_eq!(channel.min.value(), 1);
+        assert_eq!(channel.min.time(), 1_001);
+    }
+
+    #[test]
+    fn mean() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.mean(), None);
+
+        let channel = distribution_channel();
+        assert_eq!(channel.mean(), None);
+        channel
+            .record(Measurement::Distribution {
+                value: 42,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.mean(), Some(42.0));
+
+        let channel = distribution_channel();
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 99,
+                time: 1,
+            })
+            .unwrap();
+        channel
+            .record(Measurement::Distribution {
+                value: 901,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.mean(), Some(10.0));
+    }
+
+    #[test]
+    fn mean_std_dev_outputs() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        channel.add_output(Output::Mean);
+        channel.add_output(Output::StdDev);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert!(channel.readings().is_empty());
+
+        let channel = distribution_channel();
+        channel.add_output(Output::Mean);
+        channel.add_output(Output::StdDev);
+        assert!(channel.readings().is_empty());
+        assert!(channel.hash_map().is_empty());
+
+        for (value, count) in &[(2, 1), (4, 3), (5, 2), (7, 1), (10, 1)] {
+            channel
+                .record(Measurement::Distribution {
+                    value: *value,
+                    count: *count,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        // mean is 5.125 and the standard deviation is ~2.32
+        let map = channel.hash_map();
+        assert_eq!(map.get(&Output::Mean), Some(&5));
+        assert_eq!(map.get(&Output::StdDev), Some(&2));
+        let readings = channel.readings();
+        assert_eq!(readings.len(), 2);
+        for reading in readings {
+            assert_eq!(Some(&reading.value()), map.get(&reading.output()));
+        }
+
+        channel.set_rounding(RoundingMode::Up);
+        let map = channel.hash_map();
+        assert_eq!(map.get(&Output::Mean), Some(&6));
+        assert_eq!(map.get(&Output::StdDev), Some(&3));
+    }
+
+    #[test]
+    fn latched() {
+        for latched in &[true, false] {
+            let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+            let channel = Channel::with_latched(
+                "test".to_string(),
+                Source::Distribution,
+                Some(histogram),
+                *latched,
+            );
+            channel
+                .record(Measurement::Distribution {
+                    value: 100,
+                    count: 1,
+                    time: 1,
+                })
+                .unwrap();
+            channel.latch();
+            if *latched {
+                assert_eq!(channel.percentile(0.5), None);
+            } else {
+                assert_eq!(channel.percentile(0.5), Some(100));
+            }
+            assert_eq!(channel.counter(), 1);
+        }
+        assert!(distribution_channel().latched);
+    }
+
+    #[test]
+    fn latched_rate() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+        let second = 1_000_000_000;
+        channel
+            .record(Measurement::Counter {
+                value: 0,
+                time: second,
+            })
+            .unwrap();
+        channel
+            .record(Measurement::Counter {
+                value: 100,
+                time: 2 * second,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(1.0), Some(100));
+
+        // the baseline is kept over a latch, so a rate is derived at once
+        channel.latch();
+        assert_eq!(channel.percentile(1.0), None);
+        channel
+            .record(Measurement::Counter {
+                value: 400,
+                time: 4 * second,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), Some(150));
+        assert_eq!(channel.percentile(1.0), Some(150));
+        assert_eq!(channel.counter(), 400);
+
+        // without a baseline, the next measurement only re-seeds the counter
+        channel.latch_and_reset_rate();
+        assert_eq!(channel.rate(), None);
+        channel
+            .record(Measurement::Counter {
+                value: 10_000,
+                time: 5 * second,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), None);
+        assert_eq!(channel.percentile(1.0), None);
+        assert_eq!(channel.counter(), 10_000);
+        channel
+            .record(Measurement::Counter {
+                value: 10_050,
+                time: 6 * second,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), Some(50));
+        assert_eq!(channel.percentile(1.0), Some(50));
+    }
+
+    #[test]
+    fn sample_count() {
+        let channel = distribution_channel();
+        assert_eq!(channel.sample_count(), 0);
+        for (value, count) in &[(10, 3), (20, 5)] {
+            channel
+                .record(Measurement::Distribution {
+                    value: *value,
+                    count: *count,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 8);
+        assert_eq!(channel.sample_count(), 8);
+        channel.latch();
+        assert_eq!(channel.counter(), 8);
+        assert_eq!(channel.sample_count(), 0);
+
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+        for count in &[3, 5, 1] {
+            channel
+                .record(Measurement::Increment {
+                    count: *count,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 9);
+        assert_eq!(channel.sample_count(), 3);
+
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 2,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.counter(), 2);
+        assert_eq!(channel.sample_count(), 0);
+    }
+
+    #[test]
+    fn set_name() {
+        let channel = distribution_channel();
+        channel.add_output(Output::Counter);
+        channel
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        let other = distribution_channel();
+        assert!(channel == other);
+        channel.set_name("renamed".to_string());
+        assert_eq!(channel.name(), "renamed");
+        assert!(channel != other);
+        let readings = channel.readings();
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].label(), "renamed");
+        assert_eq!(readings[0].value(), 1);
+        assert_eq!(channel.percentile(1.0), Some(100));
+    }
+
+    #[test]
+    fn source_mismatch() {
+        let measurements = || {
+            vec![
+                (Source::Counter, Measurement::Counter { value: 1, time: 1 }),
+                (
+                    Source::Distribution,
+                    Measurement::Distribution {
+                        value: 1,
+                        count: 1,
+                        time: 1,
+                    },
+                ),
+                (
+                    Source::DistributionF64,
+                    Measurement::DistributionF64 {
+                        value: 0.5,
+                        count: 1,
+                        time: 1,
+                    },
+                ),
+                (Source::Gauge, Measurement::Gauge { value: 1, time: 1 }),
+                (
+                    Source::Counter,
+                    Measurement::Increment { count: 1, time: 1 },
+                ),
+                (
+                    Source::TimeInterval,
+                    Measurement::TimeInterval { start: 1, stop: 2 },
+                ),
+                (
+                    Source::TimeInterval,
+                    Measurement::TimeInterval { start: 2, stop: 1 },
+                ),
+                (
+                    Source::TimeInterval,
+                    Measurement::Timing {
+                        duration: 1,
+                        time: 1,
+                    },
+                ),
+            ]
+        };
+        for source in &[
+            Source::Counter,
+            Source::Distribution,
+            Source::DistributionF64,
+            Source::Gauge,
+            Source::TimeInterval,
+        ] {
+            let channel = Channel::<AtomicU64>::new("test".to_string(), *source, None);
+            for (got, measurement) in measurements() {
+                if got == *source {
+                    assert_eq!(channel.record(measurement), Ok(()));
+                } else {
+                    assert_eq!(
+                        channel.record(measurement),
+                        Err(RecordError::SourceMismatch {
+                            expected: *source,
+                            got,
+                        })
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Gauge, Some(histogram));
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::Percentile(Percentile::p99));
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel.add_output(Output::Percentile(Percentile::Maximum));
+        assert_eq!(
+            channel.snapshot(),
+            ChannelSnapshot {
+                name: "test".to_string(),
+                counter: 0,
+                percentiles: Vec::new(),
+                max: None,
+                min: None,
+            }
+        );
+        for time in 1..=100 {
+            channel
+                .record(Measurement::Gauge { value: time, time })
+                .unwrap();
+        }
+        channel
+            .record(Measurement::Gauge {
+                value: 20,
+                time: 101,
+            })
+            .unwrap();
+        assert_eq!(
+            channel.snapshot(),
+            ChannelSnapshot {
+                name: "test".to_string(),
+                counter: 20,
+                percentiles: vec![
+                    (Percentile::p50, 50),
+                    (Percentile::p99, 99),
+                    (Percentile::Maximum, 100),
+                ],
+                max: Some((100, 100)),
+                min: Some((1, 1)),
+            }
+        );
+    }
+
+    #[test]
+    fn weighted_increments() {
+        for weighted in &[false, true] {
+            let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+            let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+            assert!(!channel.weighted_increments());
+            channel.set_weighted_increments(*weighted);
+            for _ in 0..9 {
+                channel
+                    .record(Measurement::Increment { count: 1, time: 1 })
+                    .unwrap();
+            }
+            channel
+                .record(Measurement::Increment { count: 91, time: 1 })
+                .unwrap();
+            assert_eq!(channel.counter(), 100);
+            let histogram = channel.histogram.as_ref().unwrap();
+            if *weighted {
+                assert_eq!(histogram.total_count(), 100);
+                assert_eq!(channel.percentile(0.5), Some(91));
+            } else {
+                assert_eq!(histogram.total_count(), 10);
+                assert_eq!(channel.percentile(0.5), Some(1));
+            }
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let channel = distribution_channel();
+        let all = [
+            Percentile::Minimum,
+            Percentile::p001,
+            Percentile::p01,
+            Percentile::p1,
+            Percentile::p5,
+            Percentile::p10,
+            Percentile::p25,
+            Percentile::p50,
+            Percentile::p75,
+            Percentile::p90,
+            Percentile::p95,
+            Percentile::p99,
+            Percentile::p999,
+            Percentile::p9999,
+            Percentile::Maximum,
+        ];
+        for percentile in &all {
+            channel.add_output(Output::Percentile(*percentile));
+        }
+        assert!(channel.readings().is_empty());
+        for value in 1..=100_000 {
+            channel
+                .record(Measurement::Distribution {
+                    value: value * 7 % 1_000_000,
+                    count: 1,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        let ps: Vec<f64> = all.iter().map(|p| p.as_f64()).collect();
+        let batch = channel.percentiles(&ps);
+        let map = channel.hash_map();
+        assert_eq!(map.len(), all.len());
+        for (i, percentile) in all.iter().enumerate() {
+            let single = channel.percentile(percentile.as_f64());
+            assert_eq!(batch[i], (percentile.as_f64(), single));
+            assert_eq!(map.get(&Output::Percentile(*percentile)).cloned(), single);
+        }
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        assert_eq!(channel.percentiles(&[0.5]), vec![(0.5, None)]);
+    }
+
+    #[test]
+    fn poisoned_locks() {
+        let channel = Arc::new(distribution_channel());
+        channel.add_output(Output::Counter);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        let poisoner = channel.clone();
+        let result = std::thread::spawn(move || {
+            let _name = poisoner.name.lock().unwrap();
+            let _outputs = poisoner.outputs.lock().unwrap();
+            panic!("poisoning locks");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(channel.name.is_poisoned());
+        assert!(channel.outputs.is_poisoned());
+
+        assert_eq!(channel.name(), "test");
+        assert_eq!(channel.try_name(), Some("test".to_string()));
+        channel.add_output(Output::Percentile(Percentile::Maximum));
+        assert_eq!(channel.readings().len(), 2);
+        assert_eq!(channel.hash_map().get(&Output::Counter), Some(&1));
+        channel.delete_output(Output::Counter);
+        assert_eq!(channel.readings().len(), 1);
+        channel.set_name("renamed".to_string());
+        assert_eq!(channel.name(), "renamed");
+    }
+
+    #[test]
+    fn concurrent_outputs() {
+        let channel = Arc::new(distribution_channel());
+        channel.add_output(Output::Counter);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        let mutator = {
+            let channel = channel.clone();
+            std::thread::spawn(move || {
+                for _ in 0..1_000 {
+                    channel.add_output(Output::Mean);
+                    channel.add_output(Output::Percentile(Percentile::p50));
+                    channel.delete_output(Output::Mean);
+                    channel.delete_output(Output::Percentile(Percentile::p50));
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let channel = channel.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1_000 {
+                        let readings = channel.readings();
+                        // each set of outputs seen is one which was installed
+                        assert!(!readings.is_empty() && readings.len() <= 3);
+                        assert!(readings
+                            .iter()
+                            .any(|reading| reading.output() == Output::Counter));
+                        let outputs = channel.hash_map();
+                        assert_eq!(outputs.get(&Output::Counter), Some(&1));
+                    }
+                })
+            })
+            .collect();
+        mutator.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(channel.outputs(), vec![Output::Counter]);
+    }
+
+    #[test]
+    fn labels() {
+        let channel = distribution_channel();
+        channel.add_output(Output::Counter);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert!(channel.labels().is_empty());
+        assert!(channel.readings()[0].labels().is_empty());
+
+        channel.set_label("backend".to_string(), "cache01".to_string());
+        channel.set_label("protocol".to_string(), "memcache".to_string());
+        channel.set_label("protocol".to_string(), "redis".to_string());
+        let mut expected = HashMap::new();
+        expected.insert("backend".to_string(), "cache01".to_string());
+        expected.insert("protocol".to_string(), "redis".to_string());
+        assert_eq!(channel.labels(), expected);
+        for readings in &[channel.readings(), channel.readings_and_reset()] {
+            assert_eq!(readings.len(), 1);
+            assert_eq!(readings[0].labels(), expected);
+        }
+
+        // equality is unaffected by labels
+        assert!(channel == distribution_channel());
+    }
+
+    #[test]
+    fn reading_time() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        channel.add_output(Output::Counter);
+        assert!(channel.readings()[0].time().is_none());
+        channel
+            .record(Measurement::Gauge {
+                value: 5,
+                time: 1_000,
+            })
+            .unwrap();
+        channel
+            .record(Measurement::Gauge {
+                value: 6,
+                time: 2_000,
+            })
+            .unwrap();
+        let readings = channel.readings();
+        assert_eq!(readings[0].value(), 6);
+        assert_eq!(readings[0].time(), Some(2_000));
+
+        let channel = distribution_channel();
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel
+            .record_labeled(
+                "ok",
+                Measurement::Distribution {
+                    value: 10,
+                    count: 1,
+                    time: 3_000,
+                },
+            )
+            .unwrap();
+        let readings = channel.readings_and_reset();
+        assert_eq!(readings.len(), 2);
+        for reading in readings {
+            assert_eq!(reading.value(), 10);
+            assert_eq!(reading.time(), Some(3_000));
+        }
+
+        let reading = Reading::new("test".to_string(), Output::Counter, 1);
+        assert_eq!(reading.time(), None);
+        assert_eq!(reading.with_time(4_000).time(), Some(4_000));
+    }
+
+    #[test]
+    fn clone() {
+        let channel = distribution_channel();
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::Percentile(Percentile::Maximum));
+        channel.set_label("backend".to_string(), "cache01".to_string());
+        for value in 1..=10 {
+            channel
+                .record_labeled(
+                    "ok",
+                    Measurement::Distribution {
+                        value,
+                        count: 1,
+                        time: value,
+                    },
+                )
+                .unwrap();
+        }
+        let clone = channel.clone();
+        assert!(clone == channel);
+
+        channel
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 5,
+                time: 20,
+            })
+            .unwrap();
+        channel.latch();
+        channel.add_output(Output::Mean);
+        channel.set_label("backend".to_string(), "cache02".to_string());
+        channel.set_name("renamed".to_string());
+
+        assert_eq!(clone.name(), "test");
+        assert_eq!(clone.counter(), 10);
+        assert_eq!(clone.last_write(), 10);
+        assert_eq!(clone.percentile(1.0), Some(10));
+        assert_eq!(clone.labeled_percentile("ok", 1.0), Some(10));
+        assert_eq!(clone.labels().get("backend").unwrap(), "cache01");
+        assert_eq!(clone.outputs().len(), 2);
+        assert_eq!(clone.readings().len(), 3);
+        assert_eq!(channel.counter(), 15);
+        assert_eq!(channel.percentile(1.0), None);
+    }
+
+    #[test]
+    fn record_batch() {
+        let measurements: Vec<Measurement<u64>> = (0..100)
+            .map(|i| Measurement::Counter {
+                value: i * i,
+                time: (i + 1) * 1_000_000_000,
+            })
+            .collect();
+        let channels: Vec<Channel<AtomicU64>> = (0..2)
+            .map(|_| {
+                let histogram = Histogram::new(1_000_000, 3, None, None);
+                let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+                channel.set_rounding(RoundingMode::Nearest);
+                channel.add_output(Output::Counter);
+                channel.add_output(Output::Rate);
+                channel.add_output(Output::Percentile(Percentile::p50));
+                channel.add_output(Output::Percentile(Percentile::Maximum));
+                channel
+            })
+            .collect();
+        for measurement in &measurements {
+            channels[0].record(*measurement).unwrap();
+        }
+        channels[1].record_batch(&measurements).unwrap();
+        assert_eq!(channels[0].snapshot(), channels[1].snapshot());
+        assert_eq!(channels[0].hash_map(), channels[1].hash_map());
+        assert_eq!(channels[1].counter(), 99 * 99);
+        assert_eq!(channels[1].rate(), Some(197));
+
+        let channel = distribution_channel();
+        assert_eq!(
+            channel.record_batch(&[
+                Measurement::Distribution {
+                    value: 1,
+                    count: 1,
+                    time: 1,
+                },
+                Measurement::Gauge { value: 1, time: 2 },
+                Measurement::Distribution {
+                    value: 1,
+                    count: 1,
+                    time: 3,
+                },
+            ]),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Distribution,
+                got: Source::Gauge,
+            })
+        );
+        assert_eq!(channel.counter(), 1);
+    }
+
+    #[test]
+    fn overflows() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        channel.add_output(Output::Overflows);
+        let increment = |count| {
+            channel
+                .record(Measurement::Increment { count, time: 1 })
+                .unwrap()
+        };
+        increment(u64::MAX - 1);
+        assert_eq!(channel.overflows(), 0);
+        increment(1);
+        assert_eq!(channel.counter(), u64::MAX);
+        assert_eq!(channel.overflows(), 0);
+        increment(1);
+        assert_eq!(channel.counter(), 0);
+        assert_eq!(channel.overflows(), 1);
+        increment(u64::MAX);
+        assert_eq!(channel.overflows(), 1);
+        increment(2);
+        assert_eq!(channel.counter(), 1);
+        assert_eq!(channel.overflows(), 2);
+        assert_eq!(channel.hash_map().get(&Output::Overflows), Some(&2));
+        channel.zero();
+        assert_eq!(channel.overflows(), 0);
+
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        for _ in 0..3 {
+            channel
+                .record(Measurement::Distribution {
+                    value: 1,
+                    count: u64::MAX / 2 + 1,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.overflows(), 1);
+    }
+
+    #[test]
+    fn ewma() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        channel.add_output(Output::Ewma);
+        let gauge = |value, time| channel.record(Measurement::Gauge { value, time }).unwrap();
+        gauge(10, 1);
+        assert_eq!(channel.ewma(), None);
+        assert!(channel.readings().is_empty());
+
+        channel.set_ewma_alpha(Some(0.5));
+        assert_eq!(channel.ewma_alpha(), Some(0.5));
+        gauge(0, 2);
+        assert_eq!(channel.ewma(), Some(0.0));
+        // after a step change, each reading halves the remaining distance
+        let mut expected = 0.0;
+        for time in 3..13 {
+            gauge(1_000, time);
+            expected += 0.5 * (1_000.0 - expected);
+            let ewma = channel.ewma().unwrap();
+            assert!((ewma - expected).abs() <= 1.0 / GAUGE_F64_SCALE as f64);
+            assert_eq!(channel.counter(), 1_000);
+        }
+        assert!(channel.ewma().unwrap() > 999.0);
+        assert_eq!(channel.hash_map().get(&Output::Ewma), Some(&999));
+
+        channel.zero();
+        assert_eq!(channel.ewma(), None);
+        channel.set_ewma_alpha(Some(2.0));
+        assert_eq!(channel.ewma_alpha(), Some(1.0));
+        gauge(5, 1);
+        gauge(7, 2);
+        assert_eq!(channel.ewma(), Some(7.0));
+        channel.set_ewma_alpha(None);
+        gauge(9, 3);
+        assert_eq!(channel.ewma(), None);
+    }
+
+    #[test]
+    fn out_of_range() {
+        for policy in &[OutOfRangePolicy::Clamp, OutOfRangePolicy::Count] {
+            let histogram = Histogram::<AtomicU64>::new(1_000, 3, None, None);
+            let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+            channel.add_output(Output::OutOfRange);
+            assert_eq!(channel.out_of_range_policy(), OutOfRangePolicy::Clamp);
+            channel.set_out_of_range_policy(*policy);
+            for value in &[10, 1_000, 5_000] {
+                channel
+                    .record(Measurement::Distribution {
+                        value: *value,
+                        count: 2,
+                        time: 1,
+                    })
+                    .unwrap();
+            }
+            assert_eq!(channel.counter(), 6);
+            match policy {
+                OutOfRangePolicy::Clamp => {
+                    assert_eq!(channel.sample_count(), 6);
+                    assert_eq!(channel.percentile(1.0), Some(1_000));
+                    assert_eq!(channel.out_of_range(), 0);
+                }
+                OutOfRangePolicy::Count => {
+                    assert_eq!(channel.sample_count(), 4);
+                    assert_eq!(channel.percentile(1.0), Some(1_000));
+                    assert_eq!(channel.out_of_range(), 2);
+                }
+            }
+            assert_eq!(
+                channel.hash_map().get(&Output::OutOfRange),
+                Some(&channel.out_of_range())
+            );
+            channel.zero();
+            assert_eq!(channel.out_of_range(), 0);
+        }
+    }
+
+    #[test]
+    fn percentile_interpolated() {
+        let channel = distribution_channel();
+        assert_eq!(channel.percentile_interpolated(0.5), None);
+        channel
+            .record(Measurement::Distribution {
+                value: 10_000,
+                count: 10,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(0.25), Some(10_099));
+        assert_eq!(channel.percentile_interpolated(0.25), Some(10_025.0));
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        assert_eq!(channel.percentile_interpolated(0.5), None);
+    }
+
+    #[test]
+    fn record_buffered() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Arc::new(Channel::new(
+            "test".to_string(),
+            Source::TimeInterval,
+            Some(histogram),
+        ));
+        channel.set_buffer_flush(usize::MAX, Duration::from_secs(3600));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let channel = channel.clone();
+                std::thread::spawn(move || {
+                    for duration in 1..=100_000 {
+                        channel
+                            .record_buffered(Measurement::Timing { duration, time: 1 })
+                            .unwrap();
+                    }
+                    channel.flush_thread_local();
+                })
+            })
+            .collect();
+        // a thread can only flush its own buffer
+        channel.flush_thread_local();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(channel.counter(), 800_000);
+        assert_eq!(channel.sample_count(), 800_000);
+        assert_eq!(channel.max.value(), 100_000);
+        assert_eq!(channel.min.value(), 1);
+
+        // buffers flush on reaching the number of records
+        let channel = distribution_channel();
+        channel.set_buffer_flush(3, Duration::from_secs(3600));
+        for time in 1..=4 {
+            channel
+                .record_buffered(Measurement::Distribution {
+                    value: 10,
+                    count: 2,
+                    time,
+                })
+                .unwrap();
+        }
+        // the fourth is not visible until the buffer is flushed
+        assert_eq!(channel.counter(), 6);
+        assert_eq!(channel.last_write(), 3);
+        assert_eq!(
+            channel.record_buffered(Measurement::Increment { count: 1, time: 5 }),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Distribution,
+                got: Source::Counter,
+            })
+        );
+        channel.flush_thread_local();
+        assert_eq!(channel.counter(), 8);
+        assert_eq!(channel.buckets(), vec![(10, 8)]);
+        // unbuffered measurements flush the buffer first
+        channel
+            .record_buffered(Measurement::Distribution {
+                value: 20,
+                count: 1,
+                time: 6,
+            })
+            .unwrap();
+        channel.set_buffer_flush(3, Duration::from_secs(0));
+        channel
+            .record_buffered(Measurement::Distribution {
+                value: 20,
+                count: 1,
+                time: 7,
+            })
+            .unwrap();
+        assert_eq!(channel.counter(), 10);
+    }
+
+    #[test]
+    fn try_name() {
+        let channel = distribution_channel();
+        assert_eq!(channel.try_name(), Some("test".to_string()));
+        let _name = channel.name.lock().unwrap();
+        assert_eq!(channel.try_name(), None);
+    }
+
+    #[test]
+    fn configure_outputs() {
+        let channel = distribution_channel();
+        channel.add_output(Output::Mean);
+        channel
+            .configure_outputs("counter, p50,p99,max_time,p99.95,")
+            .unwrap();
+        let mut outputs = channel.outputs();
+        outputs.sort_by_key(|output| format!("{:?}", output));
+        assert_eq!(
+            outputs,
+            vec![
+                Output::Counter,
+                Output::MaxPointTime,
+                Output::Percentile(Percentile::from_ppm(999_500).unwrap()),
+                Output::Percentile(Percentile::p50),
+                Output::Percentile(Percentile::p99),
+            ]
+        );
+
+        assert_eq!(
+            channel.configure_outputs("counter,p101,mean"),
+            Err(OutputSpecError::Unrecognized(vec!["p101".to_string()]))
+        );
+        assert_eq!(channel.outputs().len(), 5);
+
+        channel.configure_outputs("").unwrap();
+        assert!(channel.outputs().is_empty());
+    }
+
+    #[test]
+    fn clear_outputs() {
+        let channel = distribution_channel();
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert!(channel.outputs().is_empty());
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::Mean);
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel.add_output(Output::Counter);
+        let mut outputs = channel.outputs();
+        outputs.sort_by_key(|output| format!("{:?}", output));
+        assert_eq!(
+            outputs,
+            vec![
+                Output::Counter,
+                Output::Mean,
+                Output::Percentile(Percentile::p50)
+            ]
+        );
+        assert_eq!(channel.readings().len(), 3);
+        channel.clear_outputs();
+        assert!(channel.outputs().is_empty());
+        assert!(channel.readings().is_empty());
+        assert!(channel.hash_map().is_empty());
+    }
+
+    #[test]
+    fn buckets() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        assert!(channel.buckets().is_empty());
+        let channel = distribution_channel();
+        assert!(channel.buckets().is_empty());
+        for (value, count) in &[(1, 10), (2, 20), (999, 1), (150_000, 5), (150_001, 5)] {
+            channel
+                .record(Measurement::Distribution {
+                    value: *value,
+                    count: *count,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            channel.buckets(),
+            vec![(1, 10), (2, 20), (999, 1), (150_999, 10)]
+        );
+    }
+
+    #[test]
+    fn percentile_with_count() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        assert_eq!(channel.percentile_with_count(0.5), None);
+        let channel = distribution_channel();
+        assert_eq!(channel.percentile_with_count(0.5), None);
+        for value in 1..=100 {
+            channel
+                .record(Measurement::Distribution {
+                    value,
+                    count: 2,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.percentile_with_count(0.5), Some((50, 100)));
+        assert_eq!(channel.percentile_with_count(0.99), Some((99, 198)));
+        assert_eq!(channel.percentile_with_count(1.0), Some((100, 200)));
+    }
+
+    #[test]
+    fn record_labeled() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("latency".to_string(), Source::TimeInterval, Some(histogram));
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel.add_output(Output::Percentile(Percentile::Maximum));
+        for duration in 1..=100 {
+            channel
+                .record_labeled("hit", Measurement::Timing { duration, time: 1 })
+                .unwrap();
+            channel
+                .record_labeled(
+                    "miss",
+                    Measurement::TimeInterval {
+                        start: 1_000,
+                        stop: 1_000 + duration * 5,
+                    },
+                )
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 200);
+        assert_eq!(channel.labeled_percentile("hit", 0.5), Some(50));
+        assert_eq!(channel.labeled_percentile("hit", 1.0), Some(100));
+        assert_eq!(channel.labeled_percentile("miss", 0.5), Some(250));
+        assert_eq!(channel.labeled_percentile("miss", 1.0), Some(500));
+        assert_eq!(channel.labeled_percentile("error", 0.5), None);
+        assert_eq!(channel.percentile(1.0), Some(500));
+
+        let mut readings: Vec<(String, Output, u64)> = channel
+            .readings()
+            .iter()
+            .map(|r| (r.label(), r.output(), r.value()))
+            .filter(|(label, _, _)| label != "latency")
+            .collect();
+        readings.sort_by_key(|(label, _, value)| (label.clone(), *value));
+        assert_eq!(
+            readings,
+            vec![
+                (
+                    "latency/hit".to_string(),
+                    Output::Percentile(Percentile::p50),
+                    50
+                ),
+                (
+                    "latency/hit".to_string(),
+                    Output::Percentile(Percentile::Maximum),
+                    100
+                ),
+                (
+                    "latency/miss".to_string(),
+                    Output::Percentile(Percentile::p50),
+                    250
+                ),
+                (
+                    "latency/miss".to_string(),
+                    Output::Percentile(Percentile::Maximum),
+                    500
+                ),
+            ]
+        );
+
+        assert_eq!(
+            channel.record_labeled("hit", Measurement::Gauge { value: 1, time: 1 }),
+            Err(RecordError::SourceMismatch {
+                expected: Source::TimeInterval,
+                got: Source::Gauge,
+            })
+        );
+        channel.latch();
+        assert_eq!(channel.labeled_percentile("hit", 0.5), None);
+        assert!(channel.readings().is_empty());
+    }
+
+    #[test]
+    fn rate() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        channel.add_output(Output::Rate);
+        assert_eq!(channel.rate(), None);
+        assert!(channel.readings().is_empty());
+        for i in 0..5 {
+            channel
+                .record(Measurement::Counter {
+                    value: i * 250,
+                    time: 1_000_000_000 + i * 500_000_000,
+                })
+                .unwrap();
+            if i == 0 {
+                assert_eq!(channel.rate(), None);
+            } else {
+                assert_eq!(channel.rate(), Some(500));
+            }
+        }
+        channel
+            .record(Measurement::Counter {
+                value: 1_001,
+                time: 4_000_000_000,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), Some(1));
+        assert_eq!(channel.hash_map().get(&Output::Rate), Some(&1));
+        channel.latch();
+        assert_eq!(channel.rate(), Some(1));
+        channel.zero();
+        assert_eq!(channel.rate(), None);
+
+        let channel = distribution_channel();
+        channel.add_output(Output::Rate);
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert!(channel.readings().is_empty());
+    }
+
+    #[test]
+    fn gauge_f64() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("load".to_string(), Source::Gauge, Some(histogram));
+        channel.record_gauge_f64(0.333, 1).unwrap();
+        assert!((channel.gauge_f64() - 0.333).abs() < 0.5 / GAUGE_F64_SCALE as f64);
+        assert_eq!(channel.counter(), 333);
+        channel.record_gauge_f64(2.0 / 3.0, 2).unwrap();
+        assert!((channel.gauge_f64() - 2.0 / 3.0).abs() < 0.5 / GAUGE_F64_SCALE as f64);
+        assert_eq!(channel.counter(), 667);
+        assert_eq!(channel.percentile(1.0), Some(667));
+        channel.record_gauge_f64(-1.0, 3).unwrap();
+        assert_eq!(channel.gauge_f64(), 0.0);
+
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        assert_eq!(
+            channel.record_gauge_f64(0.5, 1),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Counter,
+                got: Source::Gauge,
+            })
+        );
+    }
+
+    #[test]
+    fn distribution_f64() {
+        let histogram = Histogram::<AtomicU64>::new(1_000 * DISTRIBUTION_F64_SCALE, 3, None, None);
+        let channel = Channel::new(
+            "latency-ms".to_string(),
+            Source::DistributionF64,
+            Some(histogram),
+        );
+        // observations spanning several orders of magnitude, from 0.01 to 100
+        let values: Vec<f64> = (0..10_000)
+            .map(|i| 10.0_f64.powf(-2.0 + 4.0 * f64::from(i) / 10_000.0))
+            .collect();
+        for value in &values {
+            channel
+                .record(Measurement::DistributionF64 {
+                    value: *value,
+                    count: 1,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 10_000);
+        // the nearest-rank quantile of the sorted observations
+        let reference =
+            |percentile: f64| values[(percentile * values.len() as f64).ceil() as usize - 1];
+        for percentile in &[0.5, 0.99] {
+            let expected = reference(*percentile);
+            let value = channel.percentile_f64(*percentile).unwrap();
+            assert!(
+                (value - expected).abs() <= expected * 0.001,
+                "p{}: {} expected {}",
+                percentile,
+                value,
+                expected
+            );
+        }
+
+        // values near zero are kept to the fixed-point resolution
+        let channel = Channel::new(
+            "ratio".to_string(),
+            Source::DistributionF64,
+            Some(Histogram::<AtomicU64>::new(
+                DISTRIBUTION_F64_SCALE,
+                3,
+                None,
+                None,
+            )),
+        );
+        for value in &[0.0, 0.000_000_1, 0.000_25, 0.000_5] {
+            channel
+                .record(Measurement::DistributionF64 {
+                    value: *value,
+                    count: 1,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.percentile_f64(0.0), Some(0.0));
+        assert_eq!(channel.percentile_f64(0.5), Some(0.0));
+        assert_eq!(channel.percentile_f64(0.75), Some(0.000_25));
+        assert_eq!(channel.percentile_f64(1.0), Some(0.000_5));
+
+        for value in &[-1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                channel.record(Measurement::DistributionF64 {
+                    value: *value,
+                    count: 1,
+                    time: 2,
+                }),
+                Err(RecordError::InvalidValue)
+            );
+        }
+        assert_eq!(channel.counter(), 4);
+        assert_eq!(channel.last_write(), 1);
+    }
+
+    #[test]
+    fn latch_and_snapshot() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::TimeInterval, None);
+        assert_eq!(channel.latch_and_snapshot(), None);
+
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Arc::new(Channel::new(
+            "test".to_string(),
+            Source::TimeInterval,
+            Some(histogram),
+        ));
+        let recorder = {
+            let channel = channel.clone();
+            std::thread::spawn(move || {
+                for duration in 0..100_000 {
+                    channel
+                        .record(Measurement::Timing { duration, time: 1 })
+                        .unwrap();
+                }
+            })
+        };
+        let mut total = 0;
+        while !recorder.is_finished() {
+            total += channel.latch_and_snapshot().unwrap().total_count();
+        }
+        recorder.join().unwrap();
+        total += channel.latch_and_snapshot().unwrap().total_count();
+        assert_eq!(total, 100_000);
+        assert_eq!(channel.counter(), 100_000);
+        assert_eq!(channel.percentile(0.5), None);
+
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::with_latched(
+            "test".to_string(),
+            Source::Distribution,
+            Some(histogram),
+            false,
+        );
+        channel
+            .record(Measurement::Distribution {
+                value: 10,
+                count: 3,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.latch_and_snapshot().unwrap().buckets(), &[(10, 3)]);
+        assert_eq!(channel.percentile(0.5), Some(10));
+    }
+
+    #[test]
+    fn custom_percentile() {
+        let channel = distribution_channel();
+        let percentile = Percentile::from_percent(99.5).unwrap();
+        channel.add_output(Output::Percentile(percentile));
+        assert!(channel.readings().is_empty());
+        assert!(channel.hash_map().is_empty());
+        for value in 1..=1_000 {
+            channel
+                .record(Measurement::Distribution {
+                    value,
+                    count: 1,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        let readings = channel.readings();
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].output(), Output::Percentile(percentile));
+        assert_eq!(readings[0].value(), 995);
+    }
+
+    #[test]
+    fn readings_and_reset() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        channel.add_output(Output::Counter);
+        let counter = |readings: Vec<Reading>| {
+            assert_eq!(readings.len(), 1);
+            readings[0].value()
+        };
+        channel
+            .record(Measurement::Increment { count: 5, time: 1 })
+            .unwrap();
+        assert_eq!(counter(channel.readings_and_reset()), 5);
+        assert_eq!(counter(channel.readings_and_reset()), 0);
+        channel
+            .record(Measurement::Increment { count: 3, time: 2 })
+            .unwrap();
+        assert_eq!(counter(channel.readings_and_reset()), 3);
+        assert_eq!(channel.counter(), 8);
+        assert_eq!(counter(channel.readings()), 8);
+
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        channel.add_output(Output::Counter);
+        channel
+            .record(Measurement::Gauge { value: 42, time: 1 })
+            .unwrap();
+        assert_eq!(counter(channel.readings_and_reset()), 42);
+        assert_eq!(counter(channel.readings_and_reset()), 42);
+    }
+
+    #[test]
+    fn readings_and_reset_concurrent() {
+        let channel = Arc::new(Channel::<AtomicU64>::new(
+            "test".to_string(),
+            Source::Counter,
+            None,
+        ));
+        channel.add_output(Output::Counter);
+        let drain = |channel: &Channel<AtomicU64>| -> u64 {
+            channel
+                .readings_and_reset()
+                .iter()
+                .map(|reading| reading.value())
+                .sum()
+        };
+        let writer = {
+            let channel = channel.clone();
+            std::thread::spawn(move || {
+                for time in 0..100_000 {
+                    channel
+                        .record(Measurement::Increment { count: 1, time })
+                        .unwrap();
+                }
+            })
+        };
+        let drainers: Vec<_> = (0..2)
+            .map(|_| {
+                let channel = channel.clone();
+                std::thread::spawn(move || {
+                    let mut drained = 0;
+                    while channel.counter() < 100_000 {
+                        drained += drain(&channel);
+                    }
+                    drained
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        let mut drained = drain(&channel);
+        for drainer in drainers {
+            drained += drainer.join().unwrap();
+        }
+        drained += drain(&channel);
+        assert_eq!(drained, 100_000);
+    }
+
+    #[test]
+    fn last_write() {
+        let channel = distribution_channel();
+        channel.add_output(Output::LastWriteTime);
+        assert_eq!(channel.last_write(), 0);
+        assert!(channel.readings().is_empty());
+        channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 1_234,
+            })
+            .unwrap();
+        assert_eq!(channel.last_write(), 1_234);
+        assert_eq!(channel.hash_map().get(&Output::LastWriteTime), Some(&1_234));
+        let readings = channel.readings();
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].value(), 1_234);
+
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::TimeInterval, None);
+        channel
+            .record(Measurement::Timing {
+                duration: 10,
+                time: 5_678,
+            })
+            .unwrap();
+        assert_eq!(channel.last_write(), 5_678);
+    }
+
+    #[test]
+    fn counter_reset() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+        channel.add_output(Output::Counter);
+        let record = |value, seconds: u64| {
+            channel
+                .record(Measurement::Counter {
+                    value,
+                    time: seconds * 1_000_000_000,
+                })
+                .unwrap()
+        };
+        record(1_000, 1);
+        record(1_100, 2);
+        record(1_300, 3);
+        assert_eq!(channel.readings_and_reset()[0].value(), 1_300);
+        record(20, 4);
+        assert_eq!(channel.counter(), 20);
+        assert_eq!(channel.readings_and_reset()[0].value(), 20);
+        record(70, 5);
+        assert_eq!(channel.readings_and_reset()[0].value(), 50);
+        assert_eq!(channel.percentile(0.0), Some(50));
+        assert_eq!(channel.percentile(1.0), Some(200));
+        assert_eq!(channel.max.value(), 200);
+        assert_eq!(channel.min.value(), 50);
+        assert_eq!(channel.histogram.as_ref().unwrap().total_count(), 3);
+    }
+
+    #[test]
+    fn merge() {
+        let a = distribution_channel();
+        let b = distribution_channel();
+        for value in 1..=50 {
+            a.record(Measurement::Distribution {
+                value,
+                count: 1,
+                time: value,
+            })
+            .unwrap();
+        }
+        for value in 51..=100 {
+            b.record(Measurement::Distribution {
+                value,
+                count: 2,
+                time: value,
+            })
+            .unwrap();
+        }
+        a.merge(&b).unwrap();
+        assert_eq!(a.counter(), 150);
+        assert_eq!(a.last_write(), 100);
+        assert_eq!(a.percentile(0.0), Some(1));
+        assert_eq!(a.percentile(0.34), Some(51));
+        assert_eq!(a.percentile(1.0), Some(100));
+        assert_eq!(b.counter(), 100);
+
+        let gauge = |value, time| {
+            let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+            channel.record(Measurement::Gauge { value, time }).unwrap();
+            channel
+        };
+        let a = gauge(10, 1);
+        a.record(Measurement::Gauge { value: 20, time: 2 }).unwrap();
+        a.merge(&gauge(5, 3)).unwrap();
+        assert_eq!((a.min.value(), a.min.time()), (5, 3));
+        assert_eq!((a.max.value(), a.max.time()), (20, 2));
+        a.merge(&gauge(30, 4)).unwrap();
+        assert_eq!((a.max.value(), a.max.time()), (30, 4));
+        assert_eq!(
+            a.merge(&distribution_channel()),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Gauge,
+                got: Source::Distribution,
+            })
+        );
+    }
+
+    #[test]
+    fn percentile_scaled_large() {
+        let histogram = Histogram::<AtomicU64>::new(u64::MAX, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+        channel
+            .record(Measurement::Distribution {
+                value: u64::MAX,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        channel
+            .record(Measurement::Distribution {
+                value: u64::MAX - 1,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(1.0), Some(u64::MAX));
+        for rounding in &[
+            RoundingMode::Truncate,
+            RoundingMode::Nearest,
+            RoundingMode::Up,
+        ] {
+            channel.set_rounding(*rounding);
+            assert_eq!(channel.percentile_scaled(1.0, 1), Some(u64::MAX));
+            assert_eq!(
+                channel.percentile_scaled(1.0, 2),
+                Some(u64::MAX / 2 + (*rounding != RoundingMode::Truncate) as u64)
+            );
+        }
+        assert_eq!(RoundingMode::Up.round(1e30), u64::MAX);
+        assert_eq!(RoundingMode::Nearest.round(-1.0), 0);
+    }
+
+    #[test]
+    fn percentile_scaled() {
+        let histogram = Histogram::<AtomicU64>::new(2_000_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+        channel
+            .record(Measurement::Distribution {
+                value: 1_999_999,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.rounding(), RoundingMode::Truncate);
+        assert_eq!(channel.percentile_scaled(1.0, 1_000_000), Some(1));
+        channel.set_rounding(RoundingMode::Nearest);
+        assert_eq!(channel.percentile_scaled(1.0, 1_000_000), Some(2));
+        channel.set_rounding(RoundingMode::Up);
+        assert_eq!(channel.percentile_scaled(1.0, 1_000_000), Some(2));
+    }
+
+    fn distribution_channel() -> Channel<AtomicU64> {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        Channel::new("test".to_string(), Source::Distribution, Some(histogram))
+    }
+
+    #[test]
+    fn merge_digest() {
+        let single = distribution_channel();
+        let workers = [distribution_channel(), distribution_channel()];
+        let coordinator = distribution_channel();
+        for value in 1..=100_000 {
+            let measurement = |time| Measurement::Distribution {
+                value,
+                count: 1,
+                time,
+            };
+            single.record(measurement(value)).unwrap();
+            workers[(value % 2) as usize]
+                .record(measurement(value))
+                .unwrap();
+        }
+        for worker in &workers {
+            assert_eq!(coordinator.merge_digest(&worker.export_digest()), Ok(()));
+        }
+        let expected = single.percentile(0.99).unwrap();
+        let merged = coordinator.percentile(0.99).unwrap();
+        assert!((merged as f64 - expected as f64).abs() / expected as f64 <= 0.001);
+        assert_eq!(coordinator.percentile(0.0), single.percentile(0.0));
+        assert_eq!(coordinator.percentile(1.0), single.percentile(1.0));
+    }
+
+    #[test]
+    fn merge_digest_errors() {
+        let channel = distribution_channel();
+        assert_eq!(channel.merge_digest(&[0; 15]), Err(DigestError::Truncated));
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Distribution, None);
+        assert_eq!(channel.export_digest(), Vec::<u8>::new());
+        assert_eq!(channel.merge_digest(&[]), Err(DigestError::NoHistogram));
+    }
+
+    #[test]
+    fn pause_resume() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+        let record = |value, seconds: u64| {
+            channel
+                .record(Measurement::Counter {
+                    value,
+                    time: seconds * 1_000_000_000,
+                })
+                .unwrap()
+        };
+        record(0, 1);
+        record(10, 2);
+        assert_eq!(channel.percentile(1.0), Some(10));
+        channel.pause();
+        assert!(channel.is_paused());
+        record(100_000_000, 3);
+        assert_eq!(channel.counter(), 100_000_000);
+        assert_eq!(channel.percentile(1.0), Some(10));
+        channel.resume();
+        assert!(!channel.is_paused());
+        record(100_000_010, 4);
+        assert_eq!(channel.counter(), 100_000_010);
+        assert_eq!(channel.percentile(1.0), Some(10));
+        assert_eq!(channel.max.value(), 10);
+        record(100_000_030, 5);
+        assert_eq!(channel.percentile(1.0), Some(20));
+        assert_eq!(channel.max.value(), 20);
+    }
+
+    #[test]
+    fn weighted_percentile() {
+        let channel = distribution_channel();
+        assert_eq!(channel.weighted_percentile(0.99), None);
+        channel
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 990,
+                time: 1,
+            })
+            .unwrap();
+        channel
+            .record(Measurement::Distribution {
+                value: 900,
+                count: 10,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(0.99), Some(100));
+        assert_eq!(channel.weighted_percentile(0.99), Some(900));
+        assert_eq!(channel.weighted_percentile(0.91), Some(100));
+        assert_eq!(channel.weighted_percentile(0.92), Some(900));
+        assert!(channel.weighted_percentile(0.99) > channel.percentile(0.99));
+    }
+
+    #[test]
+    fn merge_digest_too_high() {
+        let worker = distribution_channel();
+        let coordinator = distribution_channel();
+        worker
+            .record(Measurement::Distribution {
+                value: 2_000_000,
+                count: 3,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(coordinator.merge_digest(&worker.export_digest()), Ok(()));
+        assert_eq!(coordinator.histogram.as_ref().unwrap().too_high(), 3);
+    }
+
+    #[test]
+    fn set_cardinality() {
+        let channel = Channel::<AtomicU64>::new("keys".to_string(), Source::Set, None);
+        channel.add_output(Output::Cardinality);
+        channel.add_output(Output::Counter);
+        assert_eq!(channel.cardinality(), Some(0));
+        // each of 10_000 distinct keys is seen three times
+        for _ in 0..3 {
+            for value in 0..10_000 {
+                channel.record(Measurement::Set { value, time: 1 }).unwrap();
+            }
+        }
+        assert_eq!(channel.counter(), 30_000);
+        // within three standard errors of 0.81%
+        let estimate = channel.hash_map()[&Output::Cardinality];
+        assert!(
+            (estimate as f64 - 10_000.0).abs() <= 10_000.0 * 0.0243,
+            "{}",
+            estimate
+        );
+        let gauge = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        assert_eq!(gauge.cardinality(), None);
+        assert_eq!(
+            gauge.record(Measurement::Set { value: 1, time: 1 }),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Gauge,
+                got: Source::Set,
+            })
+        );
+
+        let other = Channel::<AtomicU64>::new("other".to_string(), Source::Set, None);
+        for value in 5_000..15_000 {
+            other.record(Measurement::Set { value, time: 2 }).unwrap();
+        }
+        channel.merge(&other).unwrap();
+        let estimate = channel.cardinality().unwrap();
+        assert!(
+            (estimate as f64 - 15_000.0).abs() <= 15_000.0 * 0.0243,
+            "{}",
+            estimate
+        );
+        channel.latch();
+        assert_eq!(channel.cardinality(), Some(0));
+        assert_eq!(channel.counter(), 40_000);
+    }
+
+    #[test]
+    fn hash_map_with_status() {
+        let channel = distribution_channel();
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel.add_output(Output::MaxPointTime);
+        let statuses = channel.hash_map_with_status();
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[&Output::Counter], Some(0));
+        assert_eq!(statuses[&Output::Percentile(Percentile::p50)], None);
+        assert_eq!(statuses[&Output::MaxPointTime], None);
+        assert!(!statuses.contains_key(&Output::Mean));
+        assert_eq!(channel.hash_map().len(), 1);
+
+        channel
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 2,
+                time: 1,
+            })
+            .unwrap();
+        let statuses = channel.hash_map_with_status();
+        assert_eq!(statuses[&Output::Counter], Some(2));
+        assert_eq!(statuses[&Output::Percentile(Percentile::p50)], Some(100));
+        // the max point is of the largest value
+        assert_eq!(statuses[&Output::MaxPointTime], Some(1));
+        assert!(channel
+            .hash_map()
+            .into_iter()
+            .all(|(output, value)| statuses[&output] == Some(value)));
+    }
+
+    #[test]
+    fn signed_gauge() {
+        let channel = Channel::<AtomicU64>::new("lag".to_string(), Source::SignedGauge, None);
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::MaxPointTime);
+        channel.add_output(Output::MinPointTime);
+        assert_eq!(channel.signed_max(), None);
+        assert_eq!(channel.signed_min(), None);
+        for (value, time) in &[(-5, 1), (12, 2), (-40, 3), (3, 4)] {
+            channel
+                .record(Measurement::SignedGauge {
+                    value: *value,
+                    time: *time,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.signed_gauge(), 3);
+        assert_eq!(channel.signed_max(), Some((12, 2)));
+        assert_eq!(channel.signed_min(), Some((-40, 3)));
+        assert_eq!(point_times(&channel), (Some(2), Some(3)));
+
+        channel
+            .record(Measurement::SignedGauge { value: -7, time: 5 })
+            .unwrap();
+        let readings = channel.readings_and_reset();
+        let counter = readings
+            .iter()
+            .find(|r| r.output() == Output::Counter)
+            .unwrap();
+        assert_eq!(counter.signed_value(), Some(-7));
+        assert!(readings
+            .iter()
+            .filter(|r| r.output() != Output::Counter)
+            .all(|r| r.signed_value().is_none()));
+        assert!(counter.to_json().ends_with("\"value\":-7}"));
+
+        channel.latch();
+        assert_eq!(channel.signed_max(), None);
+        assert_eq!(channel.signed_gauge(), -7);
+        assert_eq!(
+            channel.record(Measurement::Gauge { value: 1, time: 6 }),
+            Err(RecordError::SourceMismatch {
+                expected: Source::SignedGauge,
+                got: Source::Gauge,
+            })
+        );
+    }
+
+    #[test]
+    fn reset_extremes() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        channel.add_output(Output::MaxPointTime);
+        channel.add_output(Output::MinPointTime);
+        for (duration, time) in &[(10, 1), (50, 2), (30, 3)] {
+            channel
+                .record(Measurement::Timing {
+                    duration: *duration,
+                    time: *time,
+                })
+                .unwrap();
+        }
+        assert_eq!(point_times(&channel), (Some(2), Some(1)));
+        channel.reset_extremes();
+        assert_eq!(point_times(&channel), (None, None));
+        assert_eq!(channel.counter(), 3);
+        assert_eq!(channel.sample_count(), 3);
+        assert_eq!(channel.percentile(1.0), Some(50));
+        channel
+            .record(Measurement::Timing {
+                duration: 20,
+                time: 4,
+            })
+            .unwrap();
+        assert_eq!(point_times(&channel), (Some(4), Some(4)));
+    }
+
+    #[test]
+    fn record_traced() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        channel.add_output(Output::MaxPointTime);
+        channel.add_output(Output::Percentile(Percentile::p50));
+        let max_time = |channel: &Channel<AtomicU64>| {
+            channel
+                .readings()
+                .into_iter()
+                .find(|r| r.output() == Output::MaxPointTime)
+                .map(|r| (r.value(), r.exemplar()))
+        };
+        channel
+            .record_traced(
+                Measurement::Timing {
+                    duration: 10,
+                    time: 1,
+                },
+                0xa,
+            )
+            .unwrap();
+        channel
+            .record_traced(
+                Measurement::TimeInterval {
+                    start: 2,
+                    stop: 502,
+                },
+                0xabc,
+            )
+            .unwrap();
+        // a faster sample does not replace the exemplar
+        channel
+            .record_traced(
+                Measurement::Timing {
+                    duration: 20,
+                    time: 3,
+                },
+                0xb,
+            )
+            .unwrap();
+        assert_eq!(channel.exemplar(), Some(0xabc));
+        assert_eq!(max_time(&channel), Some((2, Some(0xabc))));
+        assert!(channel
+            .readings()
+            .iter()
+            .filter(|r| r.output() != Output::MaxPointTime)
+            .all(|r| r.exemplar().is_none()));
+
+        // a slower sample without a trace id clears it
+        channel
+            .record(Measurement::Timing {
+                duration: 900,
+                time: 4,
+            })
+            .unwrap();
+        assert_eq!(max_time(&channel), Some((4, None)));
+
+        let other = Channel::new(
+            "other".to_string(),
+            Source::TimeInterval,
+            Some(Histogram::<AtomicU64>::new(1_000_000, 3, None, None)),
+        );
+        other
+            .record_traced(
+                Measurement::Timing {
+                    duration: 5_000,
+                    time: 5,
+                },
+                u128::MAX,
+            )
+            .unwrap();
+        channel.merge(&other).unwrap();
+        assert_eq!(channel.exemplar(), Some(u128::MAX));
+        let reading = channel
+            .readings()
+            .into_iter()
+            .find(|r| r.output() == Output::MaxPointTime)
+            .unwrap();
+        assert!(reading
+            .to_json()
+            .ends_with(&format!("\"trace_id\":\"{}\"}}", "f".repeat(32))));
+        channel.latch();
+        assert_eq!(channel.exemplar(), None);
+    }
+
+    #[test]
+    fn trim_below() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        // warmup samples, then steady state
+        for duration in (0..10).chain(500..510) {
+            channel
+                .record_labeled(
+                    "get",
+                    Measurement::Timing {
+                        duration,
+                        time: duration + 1,
+                    },
+                )
+                .unwrap();
+        }
+        assert_eq!(channel.percentile(0.0), Some(0));
+        channel.trim_below(100);
+        assert_eq!(channel.sample_count(), 10);
+        assert_eq!(channel.counter(), 20);
+        assert_eq!(channel.percentile(0.0), Some(500));
+        assert_eq!(channel.percentile(0.5), Some(504));
+        assert_eq!(channel.labeled_percentile("get", 0.0), Some(500));
+    }
+
+    #[test]
+    fn collect_readings() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let latency = Channel::new("latency".to_string(), Source::TimeInterval, Some(histogram));
+        latency.add_output(Output::Percentile(Percentile::p99));
+        latency.add_output(Output::Counter);
+        latency.add_output(Output::Percentile(Percentile::p50));
+        latency
+            .record(Measurement::Timing {
+                duration: 100,
+                time: 1,
+            })
+            .unwrap();
+        let connections = Channel::<AtomicU64>::new("connections".to_string(), Source::Gauge, None);
+        connections.add_output(Output::MaxPointTime);
+        connections.add_output(Output::Counter);
+        connections
+            .record(Measurement::Gauge { value: 3, time: 1 })
+            .unwrap();
+
+        let expected = vec![
+            ("connections".to_string(), Output::Counter),
+            ("connections".to_string(), Output::MaxPointTime),
+            ("latency".to_string(), Output::Counter),
+            ("latency".to_string(), Output::Percentile(Percentile::p50)),
+            ("latency".to_string(), Output::Percentile(Percentile::p99)),
+        ];
+        let keys = |readings: Vec<Reading>| -> Vec<(String, Output)> {
+            readings.iter().map(|r| (r.label(), r.output())).collect()
+        };
+        for _ in 0..10 {
+            assert_eq!(
+                keys(super::collect_readings(&[&latency, &connections])),
+                expected
+            );
+        }
+        // a channel given twice is only reported once
+        assert_eq!(
+            keys(super::collect_readings(&[
+                &connections,
+                &latency,
+                &connections
+            ])),
+            expected
+        );
+
+        // channels of the same name with different labels are each reported
+        let requests: Vec<Channel<AtomicU64>> = ["b", "a"]
+            .iter()
+            .map(|backend| {
+                let channel = Channel::new("req".to_string(), Source::Counter, None);
+                channel.add_output(Output::Counter);
+                channel.set_label("backend".to_string(), backend.to_string());
+                channel
+            })
+            .collect();
+        let backends = |readings: Vec<Reading>| -> Vec<String> {
+            readings
+                .iter()
+                .map(|r| r.labels()["backend"].clone())
+                .collect()
+        };
+        assert_eq!(
+            backends(super::collect_readings(&[&requests[0], &requests[1]])),
+            vec!["a".to_string(), "b".to_string()]
+        );
+        assert_eq!(
+            backends(super::collect_readings(&[
+                &requests[1],
+                &requests[0],
+                &requests[1]
+            ])),
+            vec!["a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[test]
+    fn saturated() {
+        let histogram = Histogram::<AtomicU8>::new(1_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        for _ in 0..255 {
+            channel
+                .record(Measurement::Timing {
+                    duration: 10,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert!(!channel.saturated());
+        channel
+            .record(Measurement::Timing {
+                duration: 10,
+                time: 1,
+            })
+            .unwrap();
+        assert!(channel.saturated());
+        assert_eq!(channel.counter(), 256);
+        assert_eq!(channel.sample_count(), 255);
+        assert_eq!(channel.percentile(0.5), Some(10));
+    }
+
+    #[test]
+    fn counter_since_latch() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::CounterInterval);
+        let increment = |count| {
+            channel
+                .record(Measurement::Increment { count, time: 1 })
+                .unwrap()
+        };
+        increment(5);
+        increment(10);
+        assert_eq!(channel.counter_since_latch(), 15);
+        channel.latch();
+        assert_eq!(channel.counter_since_latch(), 0);
+        increment(7);
+        let map = channel.hash_map();
+        assert_eq!(map[&Output::Counter], 22);
+        assert_eq!(map[&Output::CounterInterval], 7);
+        channel.latch_and_snapshot();
+        increment(1);
+        assert_eq!(channel.counter_since_latch(), 1);
+        channel.zero();
+        assert_eq!(channel.counter_since_latch(), 0);
+
+        let gauge = Channel::<AtomicU64>::new("gauge".to_string(), Source::Gauge, None);
+        gauge.add_output(Output::CounterInterval);
+        gauge
+            .record(Measurement::Gauge { value: 3, time: 1 })
+            .unwrap();
+        assert!(gauge.hash_map().is_empty());
+    }
+
+    #[test]
+    fn record_now() {
+        // a clock which advances by a second each time it is read
+        struct MockClock(AtomicU64);
+
+        impl Clock for MockClock {
+            fn now(&self) -> u64 {
+                self.0.add(1_000_000_000) + 1_000_000_000
+            }
+        }
+
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+        channel.add_output(Output::Rate);
+        let measurement = |value| Measurement::Counter { value, time: 0 };
+        assert_eq!(
+            channel.record_now(measurement(0)),
+            Err(RecordError::NoClock)
+        );
+        channel.set_clock(Some(Arc::new(MockClock(AtomicU64::new(0)))));
+        for value in &[100, 150, 350] {
+            channel.record_now(measurement(*value)).unwrap();
+        }
+        assert_eq!(channel.last_write(), 3_000_000_000);
+        assert_eq!(channel.rate(), Some(200));
+        assert_eq!(channel.percentile(0.0), Some(50));
+        // the clone shares the clock
+        let clone = channel.clone();
+        clone.record_now(measurement(400)).unwrap();
+        assert_eq!(clone.last_write(), 4_000_000_000);
+        assert_eq!(clone.rate(), Some(50));
+    }
+
+    #[test]
+    fn extreme_values() {
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        channel.add_output(Output::MaxValue);
+        channel.add_output(Output::MinValue);
+        // no point has been recorded yet
+        assert!(channel.hash_map().is_empty());
+        for (time, value) in [40, 75, 12, 60, 30].iter().enumerate() {
+            channel
+                .record(Measurement::Gauge {
+                    value: *value,
+                    time: time as u64 + 1,
+                })
+                .unwrap();
+        }
+        let outputs = channel.hash_map();
+        assert_eq!(outputs[&Output::MaxValue], 75);
+        assert_eq!(outputs[&Output::MinValue], 12);
+        // the latest reading is not an extreme
+        assert_eq!(channel.counter(), 30);
+
+        let signed = Channel::<AtomicU64>::new("test".to_string(), Source::SignedGauge, None);
+        signed.add_output(Output::MaxValue);
+        signed.add_output(Output::MinValue);
+        for (time, value) in [-5, 20, -30, 8].iter().enumerate() {
+            signed
+                .record(Measurement::SignedGauge {
+                    value: *value,
+                    time: time as u64 + 1,
+                })
+                .unwrap();
+        }
+        let readings = signed.readings();
+        let value = |output| {
+            readings
+                .iter()
+                .find(|reading| reading.output() == output)
+                .and_then(|reading| reading.signed_value())
+        };
+        assert_eq!(value(Output::MaxValue), Some(20));
+        assert_eq!(value(Output::MinValue), Some(-30));
+    }
+
+    #[test]
+    fn counter_delta() {
+        let new_channel = || {
+            let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+            let channel = Channel::new("test".to_string(), Source::Counter, Some(histogram));
+            channel.add_output(Output::Rate);
+            channel
+        };
+        let monotonic = new_channel();
+        let deltas = new_channel();
+        let totals = [100, 150, 350, 360];
+        let mut previous = 0;
+        for (i, total) in totals.iter().enumerate() {
+            let time = (i as u64 + 1) * 1_000_000_000;
+            monotonic
+                .record(Measurement::Counter {
+                    value: *total,
+                    time,
+                })
+                .unwrap();
+            deltas
+                .record(Measurement::CounterDelta {
+                    delta: total - previous,
+                    time,
+                })
+                .unwrap();
+            previous = *total;
+        }
+        assert_eq!(deltas.counter(), monotonic.counter());
+        assert_eq!(deltas.rate(), monotonic.rate());
+        assert_eq!(deltas.sample_count(), 3);
+        for percentile in &[0.0, 0.5, 1.0] {
+            assert_eq!(
+                deltas.percentile(*percentile),
+                monotonic.percentile(*percentile)
+            );
+        }
+        assert_eq!(deltas.hash_map(), monotonic.hash_map());
+
+        // a smaller delta is not taken as a reset
+        deltas
+            .record(Measurement::CounterDelta {
+                delta: 1,
+                time: 5_000_000_000,
+            })
+            .unwrap();
+        assert_eq!(deltas.counter(), 361);
+        assert_eq!(deltas.rate(), Some(1));
+        // a delta at the same time is counted without a rate
+        deltas
+            .record(Measurement::CounterDelta {
+                delta: 4,
+                time: 5_000_000_000,
+            })
+            .unwrap();
+        assert_eq!(deltas.counter(), 365);
+        assert_eq!(deltas.sample_count(), 4);
+
+        let gauge = Channel::<AtomicU64>::new("test".to_string(), Source::Gauge, None);
+        assert_eq!(
+            gauge.record(Measurement::CounterDelta { delta: 1, time: 1 }),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Gauge,
+                got: Source::Counter,
+            })
+        );
+    }
+
+    #[test]
+    fn logarithmic_gauge() {
+        let histogram = Histogram::<AtomicU64>::logarithmic(1 << 40, None, None);
+        // a fraction of the memory of the default precision of 3
+        let precise = Histogram::<AtomicU64>::new(1 << 40, 3, None, None);
+        assert!(histogram.size() * 8 < precise.size());
+        let channel = Channel::new("memory".to_string(), Source::Gauge, Some(histogram));
+        // sizes from bytes to gigabytes
+        let sizes = [7, 512, 65_536, 10_485_760, 3_221_225_472];
+        for (time, size) in sizes.iter().enumerate() {
+            channel
+                .record(Measurement::Gauge {
+                    value: *size,
+                    time: time as u64 + 1,
+                })
+                .unwrap();
+        }
+        for (i, size) in sizes.iter().enumerate() {
+            let percentile = (i as f64 + 1.0) / sizes.len() as f64;
+            let value = channel.percentile(percentile).unwrap();
+            assert!(
+                value >= *size && (value - size) as f64 <= 0.1 * *size as f64,
+                "size: {} value: {}",
+                size,
+                value
+            );
+        }
+        assert_eq!(channel.percentile(0.0), Some(7));
+    }
+
+    #[test]
+    fn min_samples() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel.add_output(Output::Percentile(Percentile::p99));
+        channel
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 9,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile_min_samples(0.99, 9), Some(100));
+        assert_eq!(channel.percentile_min_samples(0.99, 10), None);
+
+        assert_eq!(channel.min_samples(), 0);
+        channel.set_min_samples(10);
+        // below the threshold only the counter is reported
+        let outputs = channel.hash_map();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[&Output::Counter], 9);
+        assert_eq!(
+            channel.hash_map_with_status()[&Output::Percentile(Percentile::p99)],
+            None
+        );
+        // the percentile itself is still available
+        assert_eq!(channel.percentile(0.99), Some(100));
+
+        channel
+            .record(Measurement::Distribution {
+                value: 200,
+                count: 1,
+                time: 2,
+            })
+            .unwrap();
+        let outputs = channel.hash_map();
+        assert_eq!(outputs[&Output::Percentile(Percentile::p50)], 100);
+        assert_eq!(outputs[&Output::Percentile(Percentile::p99)], 200);
+        assert_eq!(channel.clone().min_samples(), 10);
+    }
+
+    #[test]
+    fn with_default_percentiles() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::TimeInterval, Some(histogram));
+        channel.add_output(Output::Mean);
+        let channel = channel.with_default_percentiles();
+        let expected: HashSet<Output> = [
+            Output::Counter,
+            Output::MaxValue,
+            Output::Mean,
+            Output::Percentile(Percentile::p50),
+            Output::Percentile(Percentile::p90),
+            Output::Percentile(Percentile::p99),
+            Output::Percentile(Percentile::p999),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        assert_eq!(*channel.current_outputs(), expected);
+        for value in 1..=1_000 {
+            channel
+                .record(Measurement::Timing {
+                    duration: value,
+                    time: value,
+                })
+                .unwrap();
+        }
+        let outputs = channel.hash_map();
+        assert_eq!(outputs[&Output::MaxValue], 1_000);
+        assert_eq!(outputs[&Output::Percentile(Percentile::p90)], 900);
+    }
+
+    #[test]
+    fn has_data() {
+        let counter = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        assert!(!counter.has_data());
+        counter
+            .record(Measurement::Counter { value: 5, time: 1 })
+            .unwrap();
+        assert!(counter.has_data());
+
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+        assert!(!channel.has_data());
+        channel
+            .record(Measurement::Distribution {
+                value: 10,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert!(channel.has_data());
+        channel.zero();
+        assert!(!channel.has_data());
+    }
+
+    #[test]
+    fn percentile_cache() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+        assert!(!channel.percentile_cache());
+        channel.set_percentile_cache(true);
+        assert!(channel.percentile_cache());
+        channel.add_output(Output::Percentile(Percentile::p50));
+        channel.add_output(Output::Percentile(Percentile::p99));
+        let record = |value, count| {
+            channel
+                .record(Measurement::Distribution {
+                    value,
+                    count,
+                    time: 1,
+                })
+                .unwrap()
+        };
+        assert_eq!(channel.percentile(0.5), None);
+        record(100, 10);
+        // an empty result is not served once the histogram changes
+        assert_eq!(channel.percentile(0.5), Some(100));
+        assert_eq!(channel.percentile(0.5), Some(100));
+        record(200, 30);
+        assert_eq!(channel.percentile(0.5), Some(200));
+        assert_eq!(
+            channel.percentiles(&[0.1, 0.5]),
+            vec![(0.1, Some(100)), (0.5, Some(200))]
+        );
+        // a missing percentile is computed along with the cached ones
+        assert_eq!(
+            channel.percentiles(&[0.5, 0.99]),
+            vec![(0.5, Some(200)), (0.99, Some(200))]
+        );
+        // the outputs share the cache and are invalidated by a latch
+        assert_eq!(
+            channel.hash_map()[&Output::Percentile(Percentile::p50)],
+            200
+        );
+        channel.latch();
+        assert_eq!(channel.percentile(0.5), None);
+        assert!(channel.hash_map().is_empty());
+        record(50, 1);
+        assert_eq!(channel.percentile(0.99), Some(50));
+
+        let clone = channel.clone();
+        assert!(clone.percentile_cache());
+        channel.set_percentile_cache(false);
+        record(300, 3);
+        assert_eq!(channel.percentile(0.99), Some(300));
+    }
+
+    #[test]
+    fn zero_count_distribution() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new("test".to_string(), Source::Distribution, Some(histogram));
+        channel
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 2,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(channel.percentile(0.0), Some(100));
+        let zero = Measurement::Distribution {
+            value: 5,
+            count: 0,
+            time: 2,
+        };
+        channel.record(zero).unwrap();
+        channel.record_buffered(zero).unwrap();
+        channel.flush_thread_local();
+        assert_eq!(channel.counter(), 2);
+        assert_eq!(channel.sample_count(), 2);
+        assert_eq!(channel.last_write(), 1);
+        assert_eq!(channel.percentile(0.0), Some(100));
+
+        let scaled = Channel::new(
+            "test".to_string(),
+            Source::DistributionF64,
+            Some(Histogram::<AtomicU64>::new(1_000_000, 3, None, None)),
+        );
+        scaled
+            .record(Measurement::DistributionF64 {
+                value: 0.5,
+                count: 0,
+                time: 2,
+            })
+            .unwrap();
+        assert!(!scaled.has_data());
+        assert_eq!(scaled.last_write(), 0);
+        // the value is still validated
+        assert_eq!(
+            scaled.record(Measurement::DistributionF64 {
+                value: f64::NAN,
+                count: 0,
+                time: 2,
+            }),
+            Err(RecordError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn percentile_bucket() {
+        let channel = Channel::<AtomicU64>::new(
+            "test".to_string(),
+            Source::Distribution,
+            Some(Histogram::new(100_000_000, 3, None, None)),
+        );
+        assert_eq!(channel.percentile_bucket(0.99), None);
+        for value in 1..=100 {
+            channel
+                .record(Measurement::Distribution {
+                    value: value * 100_000,
+                    count: 1,
+                    time: value,
+                })
+                .unwrap();
+        }
+        let (low, high) = channel.percentile_bucket(0.99).unwrap();
+        assert_eq!((low, high), (9_900_000, 9_909_999));
+        assert_eq!(channel.percentile(0.99), Some(high));
+
+        let channel = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        assert_eq!(channel.percentile_bucket(0.99), None);
+    }
+
+    #[test]
+    fn distribution_extremes() {
+        let channel = Channel::<AtomicU64>::new(
+            "response/size".to_string(),
+            Source::Distribution,
+            Some(Histogram::new(1_000, 3, None, None)),
+        );
+        channel.add_output(Output::MaxValue);
+        channel.add_output(Output::MinValue);
+        channel.add_output(Output::MaxPointTime);
+        assert!(channel.hash_map().is_empty());
+        // the extremes are of the values, not the counts, and include values
+        // above the histogram max
+        for (time, (value, count)) in [(512, 1), (64, 100), (4_096, 2), (128, 0), (2_048, 7)]
+            .iter()
+            .enumerate()
+        {
+            channel
+                .record(Measurement::Distribution {
+                    value: *value,
+                    count: *count,
+                    time: time as u64 + 1,
+                })
+                .unwrap();
+        }
+        let outputs = channel.hash_map();
+        assert_eq!(outputs[&Output::MaxValue], 4_096);
+        assert_eq!(outputs[&Output::MinValue], 64);
+        assert_eq!(outputs[&Output::MaxPointTime], 3);
+        channel.latch();
+        assert!(channel.hash_map().is_empty());
+
+        // buffered measurements track the same extremes
+        for (time, value) in [30, 10, 20].iter().enumerate() {
+            channel
+                .record_buffered(Measurement::Distribution {
+                    value: *value,
+                    count: 1,
+                    time: time as u64 + 10,
+                })
+                .unwrap();
+        }
+        channel.flush_thread_local();
+        let outputs = channel.hash_map();
+        assert_eq!(outputs[&Output::MaxValue], 30);
+        assert_eq!(outputs[&Output::MinValue], 10);
+        assert_eq!(outputs[&Output::MaxPointTime], 10);
+    }
+
+    #[test]
+    fn approx_eq() {
+        let channel = || {
+            Channel::<AtomicU64>::new(
+                "test".to_string(),
+                Source::Distribution,
+                Some(Histogram::new(1_000_000, 3, None, None)),
+            )
+        };
+        let record = |channel: &Channel<AtomicU64>, value: u64, count: u64| {
+            channel
+                .record(Measurement::Distribution {
+                    value,
+                    count,
+                    time: 1,
+                })
+                .unwrap();
+        };
+        let a = channel();
+        let b = channel();
+        assert!(a.approx_eq(&b, 0.0));
+        for value in 1..=1_000 {
+            record(&a, value * 100, 1);
+            record(&b, value * 100 + 1, 1);
+        }
+        // the values differ by less than 1%, but not exactly
+        assert!(a.approx_eq(&b, 0.01));
+        assert!(!a.approx_eq(&b, 0.0));
+
+        // off by counter
+        let counters: Vec<Channel<AtomicU64>> = [1_000, 1_005]
+            .iter()
+            .map(|count| {
+                let channel = Channel::new("test".to_string(), Source::Counter, None);
+                channel
+                    .record(Measurement::Increment {
+                        count: *count,
+                        time: 1,
+                    })
+                    .unwrap();
+                channel
+            })
+            .collect();
+        assert_eq!(
+            counters[0].approx_diff(&counters[1], 0.001),
+            vec!["counter: 1000 != 1005"]
+        );
+        assert!(counters[0].approx_eq(&counters[1], 0.01));
+        assert!(a.clone().approx_eq(&a, 0.0));
+
+        // off by distribution, with the same counter
+        let d = channel();
+        for value in 1..=1_000 {
+            record(&d, value * 200, 1);
+        }
+        let diff = a.approx_diff(&d, 0.01);
+        assert!(diff.iter().all(|line| line.starts_with('p')));
+        assert!(diff.contains(&"p50: Some(50099) != Some(100999)".to_string()));
+
+        // an empty histogram and a different source
+        let e = Channel::<AtomicU64>::new("test".to_string(), Source::Counter, None);
+        assert_eq!(
+            channel().approx_diff(&e, 0.5),
+            vec!["source: distribution != counter"]
+        );
+        let diff = a.approx_diff(&channel(), 0.5);
+        assert_eq!(diff[0], "counter: 1000 != 0");
+        assert_eq!(diff[1], "p0: Some(100) != None");
+    }
+
+    #[test]
+    fn sampling() {
+        let channel = Channel::<AtomicU64>::new(
+            "requests".to_string(),
+            Source::TimeInterval,
+            Some(Histogram::new(1_000_000, 3, None, None)),
+        );
+        assert_eq!(channel.sampling(), 1);
+        channel.set_sampling(0);
+        assert_eq!(channel.sampling(), 1);
+        channel.set_sampling(10);
+        assert_eq!(channel.sampling(), 10);
+        for value in 0..10 * 1000 {
+            channel
+                .record(Measurement::Timing {
+                    duration: value % 100,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 10 * 1000);
+        // only one in ten measurements reached the histogram
+        assert_eq!(channel.sample_count(), 1000);
+        let p50 = channel.percentile(0.5).unwrap();
+        assert!((40..=60).contains(&p50));
+        // a partial run of measurements is not yet counted
+        for _ in 0..5 {
+            channel
+                .record(Measurement::Timing {
+                    duration: 1,
+                    time: 2,
+                })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 10 * 1000);
+        // a mismatched measurement is rejected even when it would be skipped
+        assert!(channel
+            .record(Measurement::Distribution {
+                value: 1,
+                count: 1,
+                time: 3,
+            })
+            .is_err());
+
+        let channel = Channel::<AtomicU64>::new("bytes".to_string(), Source::Counter, None);
+        channel.set_sampling(4);
+        for _ in 0..4 * 250 {
+            channel
+                .record(Measurement::Increment { count: 3, time: 1 })
+                .unwrap();
+        }
+        assert_eq!(channel.counter(), 3 * 4 * 250);
+
+        // the skipped counts are summed rather than assumed to match the
+        // applied one
+        let varying = Channel::<AtomicU64>::new("bytes".to_string(), Source::Counter, None);
+        varying.set_sampling(2);
+        for count in &[1, 100, 7, 3, 50, 50] {
+            varying
+                .record(Measurement::Increment {
+                    count: *count,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(varying.counter(), 211);
+
+        // invalid measurements are rejected wherever they fall in the cycle
+        let fractional = Channel::<AtomicU64>::new(
+            "ratio".to_string(),
+            Source::DistributionF64,
+            Some(Histogram::new(1_000_000, 3, None, None)),
+        );
+        fractional.set_sampling(2);
+        for _ in 0..2 {
+            assert_eq!(
+                fractional.record(Measurement::DistributionF64 {
+                    value: f64::NAN,
+                    count: 1,
+                    time: 1,
+                }),
+                Err(RecordError::InvalidValue)
+            );
+        }
+        assert_eq!(fractional.counter(), 0);
+        for _ in 0..2 {
+            fractional
+                .record(Measurement::DistributionF64 {
+                    value: 0.5,
+                    count: 1,
+                    time: 1,
+                })
+                .unwrap();
+        }
+        assert_eq!(fractional.counter(), 2);
+        // an invalid measurement after a skipped one keeps the skipped count
+        let half = Measurement::DistributionF64 {
+            value: 0.5,
+            count: 1,
+            time: 2,
+        };
+        fractional.record(half).unwrap();
+        assert!(fractional
+            .record(Measurement::DistributionF64 {
+                value: -1.0,
+                count: 1,
+                time: 2,
+            })
+            .is_err());
+        fractional.record(half).unwrap();
+        assert_eq!(fractional.counter(), 4);
+
+        // labeled histograms only see the applied measurements
+        let labeled = Channel::<AtomicU64>::new(
+            "latency".to_string(),
+            Source::TimeInterval,
+            Some(Histogram::new(1_000_000, 3, None, None)),
+        );
+        labeled.add_output(Output::Percentile(Percentile::p50));
+        labeled.set_sampling(10);
+        for _ in 0..10 {
+            labeled
+                .record_labeled(
+                    "hit",
+                    Measurement::Timing {
+                        duration: 5,
+                        time: 1,
+                    },
+                )
+                .unwrap();
+        }
+        assert_eq!(labeled.counter(), 10);
+        assert_eq!(labeled.sample_count(), 1);
+        assert_eq!(labeled.lock_labeled()["hit"].total_count(), 1);
+        // other measurements are always applied
+        channel
+            .record(Measurement::Counter { value: 7, time: 2 })
+            .unwrap();
+        assert_eq!(channel.counter(), 7);
+    }
+
+    #[test]
+    fn percentile_as() {
+        let channel = Channel::<AtomicU64>::new(
+            "latency".to_string(),
+            Source::Distribution,
+            Some(Histogram::new(100_000, 4, None, None)),
+        );
+        assert_eq!(channel.unit(), TimeUnit::Nanoseconds);
+        assert_eq!(channel.percentile_as(0.5, TimeUnit::Microseconds), None);
+        channel
+            .record(Measurement::Distribution {
+                value: 1_500,
+                count: 1,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(
+            channel.percentile_as(0.5, TimeUnit::Microseconds),
+            Some(1.5)
+        );
+        assert_eq!(
+            channel.percentile_as(0.5, TimeUnit::Milliseconds),
+            Some(0.0015)
+        );
+        assert_eq!(
+            channel.percentile_as(0.5, TimeUnit::Nanoseconds),
+            Some(1_500.0)
+        );
+        // the values of a channel recorded in us
+        channel.set_unit(TimeUnit::Microseconds);
+        assert_eq!(channel.clone().unit(), TimeUnit::Microseconds);
+        assert_eq!(channel.percentile_as(0.5, TimeUnit::Seconds), Some(0.0015));
+    }
+
+    #[test]
+    fn exact_rate() {
+        let channel = Channel::<AtomicU64>::new("bytes".to_string(), Source::Counter, None);
+        channel
+            .record(Measurement::Counter { value: 0, time: 1 })
+            .unwrap();
+        // a delta whose product with 1e9 is beyond the precision of an f64
+        let delta = 9_007_199_254_740_993;
+        channel
+            .record(Measurement::Counter {
+                value: delta,
+                time: 1 + 1_000_000_000,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), Some(delta));
+        channel
+            .record(Measurement::Counter {
+                value: 2 * delta,
+                time: 1 + 3_000_000_000,
+            })
+            .unwrap();
+        // 9_007_199_254_740_993 / 2 = 4_503_599_627_370_496.5, truncated
+        assert_eq!(channel.rate(), Some(4_503_599_627_370_496));
+        channel.set_rounding(RoundingMode::Up);
+        channel
+            .record(Measurement::Counter {
+                value: 2 * delta + 10,
+                time: 1 + 3_000_000_003,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), Some(3_333_333_334));
+
+        // a rate larger than a u64 saturates
+        let channel = Channel::<AtomicU64>::new("bytes".to_string(), Source::Counter, None);
+        channel
+            .record(Measurement::CounterDelta { delta: 0, time: 1 })
+            .unwrap();
+        channel
+            .record(Measurement::CounterDelta {
+                delta: u64::MAX,
+                time: 2,
+            })
+            .unwrap();
+        assert_eq!(channel.rate(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn load_values() {
+        let channel = Channel::<AtomicU64>::new(
+            "latency".to_string(),
+            Source::TimeInterval,
+            Some(Histogram::new(1_000, 3, None, None)),
+        );
+        channel.add_output(Output::Percentile(Percentile::p90));
+        let values: Vec<u64> = (1..=100).rev().collect();
+        channel.load_values(&values);
+        assert_eq!(channel.sample_count(), 100);
+        assert_eq!(channel.percentile(0.5), Some(50));
+        assert_eq!(channel.hash_map()[&Output::Percentile(Percentile::p90)], 90);
+        assert_eq!(channel.counter(), 0);
+
+        let channel = Channel::<AtomicU64>::new("requests".to_string(), Source::Counter, None);
+        channel.load_values(&values);
+        assert_eq!(channel.percentile(0.5), None);
     }
 }
@@ -0,0 +1,59 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+/// A source of the times at which measurements are recorded, in nanoseconds,
+/// see `Channel::record_now`. A `Clock` should not return zero, which is taken
+/// to mean that no time was recorded.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+}
+
+/// A `Clock` which never goes backwards, reading the same clock as
+/// `time::precise_time_ns`. The times are relative to an arbitrary point, so
+/// they are only comparable within one host.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> u64 {
+        time::precise_time_ns()
+    }
+}
+
+/// A `Clock` reading the wall clock as nanoseconds since the unix epoch. The
+/// time may jump if the system clock is adjusted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WallClock;
+
+impl Clock for WallClock {
+    fn now(&self) -> u64 {
+        let now = time::get_time();
+        now.sec as u64 * 1_000_000_000 + now.nsec as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clocks() {
+        let monotonic = MonotonicClock;
+        let first = monotonic.now();
+        assert!(first > 0);
+        assert!(monotonic.now() >= first);
+        // some time after 2019
+        assert!(WallClock.now() > 1_546_300_800 * 1_000_000_000);
+    }
+}
@@ -0,0 +1,194 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+/// A metric recorded from more than one `Source` under one name, eg:
+/// `requests` counted by one code path and recorded as a distribution of
+/// sizes by another. Each source has its own `Channel`, named by the name of
+/// the `CompositeChannel` and the `Source`, eg: `requests/counter`, so their
+/// readings never collide in a registry. Measurements are recorded into the
+/// `Channel` of their source, see `Measurement::source`.
+pub struct CompositeChannel<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+{
+    name: String,
+    channels: Vec<Channel<T>>,
+}
+
+impl<T: 'static> CompositeChannel<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    /// Create a new `CompositeChannel` without any sources
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            channels: Vec::new(),
+        }
+    }
+
+    /// Adds a `Channel` of the `Source`, with the histogram if given,
+    /// replacing any existing `Channel` of that source
+    pub fn with_source(mut self, source: Source, histogram: Option<Histogram<T>>) -> Self {
+        self.channels.retain(|channel| channel.source() != source);
+        self.channels.push(Channel::new(
+            format!("{}/{}", self.name, source),
+            source,
+            histogram,
+        ));
+        self
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Returns the `Channel` of the `Source`, eg: to register its outputs
+    pub fn channel(&self, source: Source) -> Option<&Channel<T>> {
+        self.channels
+            .iter()
+            .find(|channel| channel.source() == source)
+    }
+
+    /// Returns the `Channel`s of each source in the order they were added
+    pub fn channels(&self) -> &[Channel<T>] {
+        &self.channels
+    }
+
+    /// Records the `Measurement` into the `Channel` of its source. Returns
+    /// `RecordError::NoChannel` if there is no `Channel` of that source.
+    pub fn record(
+        &self,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<(), RecordError> {
+        let source = measurement.source();
+        self.channel(source)
+            .ok_or(RecordError::NoChannel(source))?
+            .record(measurement)
+    }
+
+    /// Returns the `Reading`s of every `Channel`, whose labels carry the
+    /// suffix of their source
+    pub fn readings(&self) -> Vec<Reading> {
+        self.channels
+            .iter()
+            .flat_map(|channel| channel.readings())
+            .collect()
+    }
+
+    pub fn latch(&self) {
+        for channel in &self.channels {
+            channel.latch();
+        }
+    }
+
+    pub fn zero(&self) {
+        for channel in &self.channels {
+            channel.zero();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_and_distribution() {
+        let requests = CompositeChannel::<AtomicU64>::new("requests".to_string())
+            .with_source(Source::Counter, None)
+            .with_source(
+                Source::Distribution,
+                Some(Histogram::new(1_000_000, 3, None, None)),
+            );
+        assert_eq!(requests.name(), "requests");
+        requests
+            .channel(Source::Counter)
+            .unwrap()
+            .add_output(Output::Counter);
+        let sizes = requests.channel(Source::Distribution).unwrap();
+        sizes.add_output(Output::Counter);
+        sizes.add_output(Output::Percentile(Percentile::p50));
+
+        for time in 1..=10 {
+            requests
+                .record(Measurement::Increment { count: 1, time })
+                .unwrap();
+            requests
+                .record(Measurement::Distribution {
+                    value: time * 100,
+                    count: 1,
+                    time,
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            requests.record(Measurement::Gauge { value: 1, time: 11 }),
+            Err(RecordError::NoChannel(Source::Gauge))
+        );
+
+        let mut readings: Vec<(String, String, u64)> = requests
+            .readings()
+            .iter()
+            .map(|reading| {
+                (
+                    reading.label(),
+                    reading.output().to_string(),
+                    reading.value(),
+                )
+            })
+            .collect();
+        readings.sort();
+        assert_eq!(
+            readings,
+            vec![
+                ("requests/counter".to_string(), "counter".to_string(), 10),
+                (
+                    "requests/distribution".to_string(),
+                    "counter".to_string(),
+                    10
+                ),
+                ("requests/distribution".to_string(), "p50".to_string(), 500),
+            ]
+        );
+
+        requests.latch();
+        requests.zero();
+        assert!(requests
+            .channels()
+            .iter()
+            .all(|channel| channel.counter() == 0));
+    }
+
+    #[test]
+    fn replace_source() {
+        let channel = CompositeChannel::<AtomicU64>::new("test".to_string())
+            .with_source(Source::Gauge, None)
+            .with_source(Source::Gauge, Some(Histogram::new(1_000, 3, None, None)));
+        assert_eq!(channel.channels().len(), 1);
+        channel
+            .record(Measurement::Gauge { value: 7, time: 1 })
+            .unwrap();
+        assert_eq!(
+            channel.channel(Source::Gauge).unwrap().percentile(1.0),
+            Some(7)
+        );
+        assert_eq!(channel.channels()[0].name(), "test/gauge");
+    }
+}
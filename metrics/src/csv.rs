@@ -0,0 +1,88 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::io::{self, Write};
+
+/// Writes the `Reading`s as CSV with a `time,name,output,value` header row,
+/// eg: for loading interval data into a spreadsheet. The time is left empty
+/// for readings without one and the output is its `Display` name, such as
+/// `counter` or `p99`. Fields containing a comma, quote, or line break are
+/// quoted, with quotes doubled.
+pub fn write_csv<W: Write>(writer: &mut W, readings: &[Reading]) -> io::Result<()> {
+    writeln!(writer, "time,name,output,value")?;
+    for reading in readings {
+        let time = reading
+            .time()
+            .map(|time| time.to_string())
+            .unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            time,
+            escape_csv(&reading.label()),
+            escape_csv(&reading.output().to_string()),
+            reading.formatted_value()
+        )?;
+    }
+    Ok(())
+}
+
+/// Renders the `Reading`s as CSV, see `write_csv`
+pub fn to_csv_string(readings: &[Reading]) -> String {
+    let mut csv = Vec::new();
+    // writing to a Vec does not fail
+    write_csv(&mut csv, readings).unwrap();
+    String::from_utf8(csv).unwrap()
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        assert_eq!(to_csv_string(&[]), "time,name,output,value\n");
+        let readings = vec![
+            Reading::new("requests".to_string(), Output::Counter, 100).with_time(1_000),
+            Reading::new(
+                "latency,get".to_string(),
+                Output::Percentile(Percentile::p99),
+                250,
+            )
+            .with_time(2_000),
+            Reading::new("say \"hi\"".to_string(), Output::Mean, 3),
+            Reading::new_signed("lag".to_string(), Output::Counter, -5),
+        ];
+        assert_eq!(
+            to_csv_string(&readings),
+            concat!(
+                "time,name,output,value\n",
+                "1000,requests,counter,100\n",
+                "2000,\"latency,get\",p99,250\n",
+                ",\"say \"\"hi\"\"\",mean,3\n",
+                ",lag,counter,-5\n",
+            )
+        );
+    }
+}
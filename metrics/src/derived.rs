@@ -0,0 +1,143 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::sync::Arc;
+
+/// The ratio of the counters of two `Channel`s, eg: the error rate as errors
+/// over total requests. Nothing is recorded into a `DerivedChannel`, the
+/// ratio is computed from the referenced channels each time it is read, so it
+/// is always consistent with their counters.
+pub struct DerivedChannel<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+{
+    name: String,
+    numerator: Arc<Channel<T>>,
+    denominator: Arc<Channel<T>>,
+}
+
+impl<T: 'static> DerivedChannel<T>
+where
+    T: Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    /// Create a new `DerivedChannel` of the ratio of the `numerator` counter
+    /// to the `denominator` counter. Both are expected to be `Counter`
+    /// channels, as the counter of other sources is not a cumulative total.
+    pub fn new(name: String, numerator: Arc<Channel<T>>, denominator: Arc<Channel<T>>) -> Self {
+        Self {
+            name,
+            numerator,
+            denominator,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn numerator(&self) -> &Arc<Channel<T>> {
+        &self.numerator
+    }
+
+    pub fn denominator(&self) -> &Arc<Channel<T>> {
+        &self.denominator
+    }
+
+    /// Returns the ratio of the lifetime counters, or `None` if the
+    /// denominator is zero
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.numerator.counter(), self.denominator.counter())
+    }
+
+    /// Returns the ratio of the increase of each counter since its last
+    /// `latch()`, eg: the error rate of the current interval, see
+    /// `Channel::counter_since_latch`. Returns `None` if the denominator has
+    /// not increased.
+    pub fn interval_ratio(&self) -> Option<f64> {
+        ratio(
+            self.numerator.counter_since_latch(),
+            self.denominator.counter_since_latch(),
+        )
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    if denominator == 0 {
+        None
+    } else {
+        Some(numerator as f64 / denominator as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_rate() {
+        let errors = Arc::new(Channel::<AtomicU64>::new(
+            "errors".to_string(),
+            Source::Counter,
+            None,
+        ));
+        let total = Arc::new(Channel::<AtomicU64>::new(
+            "requests".to_string(),
+            Source::Counter,
+            None,
+        ));
+        let error_rate =
+            DerivedChannel::new("error_rate".to_string(), errors.clone(), total.clone());
+        assert_eq!(error_rate.name(), "error_rate");
+        assert_eq!(error_rate.ratio(), None);
+        assert_eq!(error_rate.interval_ratio(), None);
+
+        // the errors and requests counted in each interval
+        let intervals = [(1, 10), (0, 30), (8, 20)];
+        let mut expected = (0, 0);
+        for (time, (interval_errors, interval_total)) in intervals.iter().enumerate() {
+            expected.0 += interval_errors;
+            expected.1 += interval_total;
+            let time = time as u64 + 1;
+            errors
+                .record(Measurement::Counter {
+                    value: expected.0,
+                    time,
+                })
+                .unwrap();
+            total
+                .record(Measurement::Counter {
+                    value: expected.1,
+                    time,
+                })
+                .unwrap();
+            assert_eq!(
+                error_rate.interval_ratio(),
+                Some(*interval_errors as f64 / *interval_total as f64)
+            );
+            assert_eq!(
+                error_rate.ratio(),
+                Some(expected.0 as f64 / expected.1 as f64)
+            );
+            errors.latch();
+            total.latch();
+            assert_eq!(error_rate.interval_ratio(), None);
+        }
+        assert_eq!(error_rate.ratio(), Some(0.15));
+    }
+}
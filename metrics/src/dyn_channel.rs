@@ -0,0 +1,315 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// The width of the counts held by each bucket of a histogram
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountWidth {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+/// A `Channel` whose histogram count width is chosen at runtime, so channels
+/// of different widths can be held in one collection, eg: a `Vec<DynChannel>`.
+///
+/// Each bucket of a histogram holds a count of the `CountWidth`, so the memory
+/// used by the buckets is the number of buckets times 1, 2, 4, or 8 bytes. A
+/// narrow width saves memory for channels which see few samples per interval,
+/// while a bucket which receives more samples than the width can hold
+/// saturates at its max and skews the percentiles. Choosing the width per
+/// channel at compile time with `Channel<T>` avoids the dispatch on each call,
+/// and `DynChannel` exposes only the most common operations, use `inner_u8()`
+/// and the like to reach the rest.
+pub enum DynChannel {
+    U8(Channel<AtomicU8>),
+    U16(Channel<AtomicU16>),
+    U32(Channel<AtomicU32>),
+    U64(Channel<AtomicU64>),
+}
+
+// calls the same method on the channel whatever its width
+macro_rules! dispatch {
+    ($self:ident, $channel:ident => $call:expr) => {
+        match $self {
+            DynChannel::U8($channel) => $call,
+            DynChannel::U16($channel) => $call,
+            DynChannel::U32($channel) => $call,
+            DynChannel::U64($channel) => $call,
+        }
+    };
+}
+
+impl DynChannel {
+    /// Create a new `DynChannel` without a histogram
+    pub fn new(name: String, source: Source, width: CountWidth) -> Self {
+        match width {
+            CountWidth::U8 => DynChannel::U8(Channel::new(name, source, None)),
+            CountWidth::U16 => DynChannel::U16(Channel::new(name, source, None)),
+            CountWidth::U32 => DynChannel::U32(Channel::new(name, source, None)),
+            CountWidth::U64 => DynChannel::U64(Channel::new(name, source, None)),
+        }
+    }
+
+    /// Create a new `DynChannel` with a histogram of the given `max` and
+    /// `precision`, see `Histogram::new`, with counts of the `width`
+    pub fn with_histogram(
+        name: String,
+        source: Source,
+        width: CountWidth,
+        max: u64,
+        precision: u32,
+    ) -> Self {
+        match width {
+            CountWidth::U8 => DynChannel::U8(Channel::new(
+                name,
+                source,
+                Some(Histogram::new(max, precision, None, None)),
+            )),
+            CountWidth::U16 => DynChannel::U16(Channel::new(
+                name,
+                source,
+                Some(Histogram::new(max, precision, None, None)),
+            )),
+            CountWidth::U32 => DynChannel::U32(Channel::new(
+                name,
+                source,
+                Some(Histogram::new(max, precision, None, None)),
+            )),
+            CountWidth::U64 => DynChannel::U64(Channel::new(
+                name,
+                source,
+                Some(Histogram::new(max, precision, None, None)),
+            )),
+        }
+    }
+
+    /// Returns the width of the histogram counts
+    pub fn width(&self) -> CountWidth {
+        match self {
+            DynChannel::U8(_) => CountWidth::U8,
+            DynChannel::U16(_) => CountWidth::U16,
+            DynChannel::U32(_) => CountWidth::U32,
+            DynChannel::U64(_) => CountWidth::U64,
+        }
+    }
+
+    /// Returns the `Channel` if its counts are `u8`
+    pub fn inner_u8(&self) -> Option<&Channel<AtomicU8>> {
+        match self {
+            DynChannel::U8(channel) => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Channel` if its counts are `u16`
+    pub fn inner_u16(&self) -> Option<&Channel<AtomicU16>> {
+        match self {
+            DynChannel::U16(channel) => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Channel` if its counts are `u32`
+    pub fn inner_u32(&self) -> Option<&Channel<AtomicU32>> {
+        match self {
+            DynChannel::U32(channel) => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Channel` if its counts are `u64`
+    pub fn inner_u64(&self) -> Option<&Channel<AtomicU64>> {
+        match self {
+            DynChannel::U64(channel) => Some(channel),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        dispatch!(self, channel => channel.name())
+    }
+
+    pub fn source(&self) -> Source {
+        dispatch!(self, channel => channel.source())
+    }
+
+    /// Records a `Measurement` into the `Channel`, as with `Channel::record`.
+    /// Returns `RecordError::CountOverflow` if a count does not fit in the
+    /// width of the `Channel`, in which case nothing is recorded.
+    pub fn record(&self, measurement: Measurement<u64>) -> Result<(), RecordError> {
+        dispatch!(self, channel => channel.record(narrow(measurement)?))
+    }
+
+    pub fn counter(&self) -> u64 {
+        dispatch!(self, channel => channel.counter())
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        dispatch!(self, channel => channel.percentile(percentile))
+    }
+
+    /// Returns the number of samples in the histogram, see
+    /// `Channel::sample_count`
+    pub fn sample_count(&self) -> u64 {
+        dispatch!(self, channel => channel.sample_count())
+    }
+
+    pub fn add_output(&self, output: Output) {
+        dispatch!(self, channel => channel.add_output(output))
+    }
+
+    pub fn delete_output(&self, output: Output) {
+        dispatch!(self, channel => channel.delete_output(output))
+    }
+
+    pub fn readings(&self) -> Vec<Reading> {
+        dispatch!(self, channel => channel.readings())
+    }
+
+    pub fn hash_map(&self) -> HashMap<Output, u64> {
+        dispatch!(self, channel => channel.hash_map())
+    }
+
+    pub fn latch(&self) {
+        dispatch!(self, channel => channel.latch())
+    }
+
+    pub fn zero(&self) {
+        dispatch!(self, channel => channel.zero())
+    }
+}
+
+// converts the count of a measurement to the width of a channel
+fn narrow<C>(measurement: Measurement<u64>) -> Result<Measurement<C>, RecordError>
+where
+    C: TryFrom<u64>,
+{
+    let count = |count: u64| C::try_from(count).map_err(|_| RecordError::CountOverflow);
+    let measurement = match measurement {
+        Measurement::Counter { value, time } => Measurement::Counter { value, time },
+        Measurement::CounterDelta { delta, time } => Measurement::CounterDelta { delta, time },
+        Measurement::Distribution {
+            value,
+            count: n,
+            time,
+        } => Measurement::Distribution {
+            value,
+            count: count(n)?,
+            time,
+        },
+        Measurement::DistributionF64 {
+            value,
+            count: n,
+            time,
+        } => Measurement::DistributionF64 {
+            value,
+            count: count(n)?,
+            time,
+        },
+        Measurement::Gauge { value, time } => Measurement::Gauge { value, time },
+        Measurement::Increment { count: n, time } => Measurement::Increment {
+            count: count(n)?,
+            time,
+        },
+        Measurement::Set { value, time } => Measurement::Set { value, time },
+        Measurement::SignedGauge { value, time } => Measurement::SignedGauge { value, time },
+        Measurement::TimeInterval { start, stop } => Measurement::TimeInterval { start, stop },
+        Measurement::Timing { duration, time } => Measurement::Timing { duration, time },
+    };
+    Ok(measurement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        let channels: Vec<DynChannel> = [
+            CountWidth::U8,
+            CountWidth::U16,
+            CountWidth::U32,
+            CountWidth::U64,
+        ]
+        .iter()
+        .map(|width| {
+            DynChannel::with_histogram(
+                format!("{:?}", width),
+                Source::Distribution,
+                *width,
+                1_000_000,
+                3,
+            )
+        })
+        .collect();
+        for channel in &channels {
+            channel.add_output(Output::Percentile(Percentile::p50));
+            channel
+                .record(Measurement::Distribution {
+                    value: 100,
+                    count: 200,
+                    time: 1,
+                })
+                .unwrap();
+            assert_eq!(channel.counter(), 200);
+            assert_eq!(channel.sample_count(), 200);
+            assert_eq!(channel.percentile(0.5), Some(100));
+            assert_eq!(
+                channel.hash_map()[&Output::Percentile(Percentile::p50)],
+                100
+            );
+            assert_eq!(channel.name(), format!("{:?}", channel.width()));
+        }
+        assert!(channels[0].inner_u8().is_some());
+        assert!(channels[0].inner_u64().is_none());
+        assert_eq!(
+            channels[3].inner_u64().map(|channel| channel.source()),
+            Some(Source::Distribution)
+        );
+
+        // a count which does not fit the width is rejected
+        let measurement = Measurement::Distribution {
+            value: 100,
+            count: 256,
+            time: 2,
+        };
+        assert_eq!(
+            channels[0].record(measurement),
+            Err(RecordError::CountOverflow)
+        );
+        assert_eq!(channels[0].counter(), 200);
+        assert_eq!(channels[1].record(measurement), Ok(()));
+        assert_eq!(channels[1].counter(), 456);
+    }
+
+    #[test]
+    fn without_histogram() {
+        let channel = DynChannel::new("connections".to_string(), Source::Gauge, CountWidth::U16);
+        assert_eq!(channel.width(), CountWidth::U16);
+        channel.add_output(Output::Counter);
+        channel
+            .record(Measurement::Gauge { value: 12, time: 1 })
+            .unwrap();
+        assert_eq!(channel.readings()[0].value(), 12);
+        assert_eq!(channel.percentile(0.5), None);
+        channel.zero();
+        assert_eq!(channel.counter(), 0);
+    }
+}
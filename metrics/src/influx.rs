@@ -0,0 +1,127 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::collections::BTreeMap;
+
+// the measurement, tags, and time of a line
+type LineKey = (String, Vec<(String, String)>, Option<u64>);
+
+/// Renders the `Reading`s in the InfluxDB line protocol, one line of
+/// `measurement,tags fields timestamp` for each channel and set of labels.
+///
+/// The channel name is the measurement and the labels of the readings are the
+/// tags, sorted by key. Each output becomes an integer field named by its
+/// `Display` name, eg: `counter=100i,p99=250i`. The timestamp is the time of
+/// the readings in nanoseconds, and is omitted for readings without one so
+/// the server assigns it. Lines are sorted by measurement, tags, and time.
+/// Spaces, commas, and for tags and fields, equals signs are escaped with a
+/// backslash.
+pub fn to_influx_line_protocol(readings: &[Reading]) -> String {
+    // the fields of each line
+    let mut lines: BTreeMap<LineKey, BTreeMap<String, String>> = BTreeMap::new();
+    for reading in readings {
+        let mut tags: Vec<(String, String)> = reading.labels().into_iter().collect();
+        tags.sort();
+        lines
+            .entry((reading.label(), tags, reading.time()))
+            .or_default()
+            .insert(reading.output().to_string(), reading.formatted_value());
+    }
+
+    let mut content = String::new();
+    for ((measurement, tags, time), fields) in lines {
+        content += &escape(&measurement, &[',', ' ']);
+        for (key, value) in tags {
+            content += &format!(
+                ",{}={}",
+                escape(&key, &[',', '=', ' ']),
+                escape(&value, &[',', '=', ' '])
+            );
+        }
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("{}={}i", escape(key, &[',', '=', ' ']), value))
+            .collect();
+        content += &format!(" {}", fields.join(","));
+        if let Some(time) = time {
+            content += &format!(" {}", time);
+        }
+        content.push('\n');
+    }
+    content
+}
+
+// escapes the special characters with a backslash
+fn escape(name: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_and_percentile() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new(
+            "request latency".to_string(),
+            Source::TimeInterval,
+            Some(histogram),
+        );
+        channel.add_output(Output::Percentile(Percentile::p99));
+        channel.add_output(Output::Counter);
+        for value in 1..=100 {
+            channel
+                .record(Measurement::TimeInterval {
+                    start: 1_000,
+                    stop: 1_000 + value,
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            to_influx_line_protocol(&channel.readings()),
+            "request\\ latency counter=100i,p99=99i 1000\n"
+        );
+    }
+
+    #[test]
+    fn tags() {
+        let mut labels = HashMap::new();
+        labels.insert("zone".to_string(), "us west,1".to_string());
+        labels.insert("backend".to_string(), "cache=01".to_string());
+        let readings = vec![
+            Reading::new_signed("lag".to_string(), Output::Counter, -5)
+                .with_labels(labels)
+                .with_time(2_000),
+            Reading::new("lag".to_string(), Output::Mean, 3),
+        ];
+        assert_eq!(
+            to_influx_line_protocol(&readings),
+            concat!(
+                "lag mean=3i\n",
+                "lag,backend=cache\\=01,zone=us\\ west\\,1 counter=-5i 2000\n",
+            )
+        );
+        assert_eq!(to_influx_line_protocol(&[]), "");
+    }
+}
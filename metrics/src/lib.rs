@@ -32,6 +32,11 @@
 //! configured to track measurements taken from counters, distributions,
 //! gauges, or time-intervals. The `Channel` allows for registering interest
 //! in one or more `Output`s which are used to produce `Reading`s.
+//! Channels can also be declared in a configuration file as a `ChannelSpec`
+//! and constructed with `build_channel`. A `DerivedChannel` reports the
+//! ratio of the counters of two `Channel`s, eg: errors over total requests,
+//! and a `CompositeChannel` records measurements of several sources under one
+//! name.
 //!
 //! ## Output
 //! An `Output` is registered with a `Channel` to signal that a type of
@@ -43,17 +48,51 @@
 //! A `Reading` represents the value of a metric at a point in time. The
 //! `Reading` stores information about the `Channel` label, the `Output` it
 //! corresponds to, and the value.
+//!
+//! ## ReadingSink
+//! A `ReadingSink` delivers `Reading`s to an external system. With the
+//! `statsd` feature enabled, `StatsdSink` sends them to a StatsD server.
+//! Alternatively, `render_prometheus` renders the readings of a set of
+//! `Channel`s in the Prometheus text format for scraping, `write_csv` writes
+//! readings as CSV for offline analysis, and `to_influx_line_protocol`
+//! renders them in the InfluxDB line protocol. For exporters which run on
+//! their own schedule, `stream_readings` delivers the readings of each
+//! interval over a `std::sync::mpsc` channel.
 
 mod channel;
+mod clock;
+mod composite;
+mod csv;
+mod derived;
+mod dyn_channel;
+mod influx;
 mod point;
+mod prometheus;
 mod recorder;
+mod sink;
+mod spec;
+mod stream;
 
-pub use crate::channel::{Channel, Measurement, Source};
-pub use crate::point::Point;
+pub use crate::channel::{
+    collect_readings, Channel, ChannelSnapshot, DigestError, Measurement, OutputSpecError,
+    RecordError, Source, DISTRIBUTION_F64_SCALE, GAUGE_F64_SCALE,
+};
+pub use crate::clock::{Clock, MonotonicClock, WallClock};
+pub use crate::composite::CompositeChannel;
+pub use crate::csv::{to_csv_string, write_csv};
+pub use crate::derived::DerivedChannel;
+pub use crate::dyn_channel::{CountWidth, DynChannel};
+pub use crate::influx::to_influx_line_protocol;
+pub use crate::point::{Point, SignedPoint};
+pub use crate::prometheus::render_prometheus;
 pub use crate::recorder::*;
+pub use crate::sink::*;
+pub use crate::spec::{build_channel, ChannelSpec, HistogramSpec, SpecError};
+pub use crate::stream::stream_readings;
 pub use datastructures::*;
 pub(crate) use logger::*;
 
+use std::collections::HashMap;
 use std::fmt;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -74,9 +113,127 @@ pub enum Percentile {
     p999,
     p9999,
     Maximum,
+    /// any other percentile, see `Percentile::from_ppm`
+    Custom(CustomPercentile),
+}
+
+/// A percentile which is not one of the named variants of `Percentile`, in
+/// parts per million. It can only be constructed through `Percentile`, so it
+/// is always in range and never equal to a named variant.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomPercentile {
+    ppm: u32,
 }
 
+impl CustomPercentile {
+    /// Returns the percentile in parts per million
+    pub fn ppm(self) -> u32 {
+        self.ppm
+    }
+}
+
+/// Error returned when a `Percentile` cannot be constructed
+#[derive(Clone, Debug, PartialEq)]
+pub enum PercentileError {
+    // the percentile was NaN or outside of 0.0 - 100.0
+    OutOfRange,
+}
+
+const PRESETS: [Percentile; 15] = [
+    Percentile::Minimum,
+    Percentile::p001,
+    Percentile::p01,
+    Percentile::p1,
+    Percentile::p5,
+    Percentile::p10,
+    Percentile::p25,
+    Percentile::p50,
+    Percentile::p75,
+    Percentile::p90,
+    Percentile::p95,
+    Percentile::p99,
+    Percentile::p999,
+    Percentile::p9999,
+    Percentile::Maximum,
+];
+
 impl Percentile {
+    pub const P50: Percentile = Percentile::p50;
+    pub const P90: Percentile = Percentile::p90;
+    pub const P99: Percentile = Percentile::p99;
+    pub const P999: Percentile = Percentile::p999;
+    pub const P9999: Percentile = Percentile::p9999;
+
+    /// Create a `Percentile` from a percentage in the range 0.0 - 100.0,
+    /// rounded to the nearest part per million. Percentages matching one of
+    /// the named variants return that variant. Returns an error if the
+    /// percentage is NaN or out of range.
+    pub fn from_percent(percent: f64) -> Result<Percentile, PercentileError> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(PercentileError::OutOfRange);
+        }
+        Percentile::from_ppm((percent * 10_000.0).round() as u32)
+    }
+
+    /// Create a `Percentile` from basis points out of 10_000, eg: 9_990 for
+    /// the p99.9, which avoids the rounding of `f64` percentages when parsing
+    /// configuration. Basis points matching one of the named variants return
+    /// that variant. Returns an error if the basis points exceed 10_000.
+    pub fn from_basis_points(bp: u32) -> Result<Percentile, PercentileError> {
+        if bp > 10_000 {
+            return Err(PercentileError::OutOfRange);
+        }
+        Percentile::from_ppm(bp * 100)
+    }
+
+    /// Returns the percentile in basis points out of 10_000, or `None` for a
+    /// `Custom` percentile which is finer than a basis point, see
+    /// `Histogram::percentile_bp`
+    pub fn basis_points(self) -> Option<u32> {
+        let ppm = self.ppm();
+        if ppm.is_multiple_of(100) {
+            Some(ppm / 100)
+        } else {
+            None
+        }
+    }
+
+    /// Create a `Percentile` from parts per million, eg: 999_500 for the
+    /// p99.95. Values matching one of the named variants return that variant.
+    /// Returns an error if the parts per million exceed 1_000_000.
+    pub fn from_ppm(ppm: u32) -> Result<Percentile, PercentileError> {
+        if ppm > 1_000_000 {
+            return Err(PercentileError::OutOfRange);
+        }
+        Ok(PRESETS
+            .iter()
+            .find(|preset| preset.ppm() == ppm)
+            .copied()
+            .unwrap_or(Percentile::Custom(CustomPercentile { ppm })))
+    }
+
+    // the percentile in parts per million
+    fn ppm(self) -> u32 {
+        match self {
+            Percentile::Minimum => 0,
+            Percentile::p001 => 100,
+            Percentile::p01 => 1_000,
+            Percentile::p1 => 10_000,
+            Percentile::p5 => 50_000,
+            Percentile::p10 => 100_000,
+            Percentile::p25 => 250_000,
+            Percentile::p50 => 500_000,
+            Percentile::p75 => 750_000,
+            Percentile::p90 => 900_000,
+            Percentile::p95 => 950_000,
+            Percentile::p99 => 990_000,
+            Percentile::p999 => 999_000,
+            Percentile::p9999 => 999_900,
+            Percentile::Maximum => 1_000_000,
+            Percentile::Custom(custom) => custom.ppm,
+        }
+    }
+
     pub fn as_f64(self) -> f64 {
         match self {
             Percentile::Minimum => 0.0,
@@ -94,6 +251,7 @@ impl Percentile {
             Percentile::p999 => 0.999,
             Percentile::p9999 => 0.9999,
             Percentile::Maximum => 1.0,
+            Percentile::Custom(custom) => f64::from(custom.ppm) / 1_000_000.0,
         }
     }
 }
@@ -116,22 +274,282 @@ impl fmt::Display for Percentile {
             Percentile::p999 => write!(f, "p999"),
             Percentile::p9999 => write!(f, "p9999"),
             Percentile::Maximum => write!(f, "maximum"),
+            // the percentage with a decimal point, eg: 99.95 is p99.95, so
+            // that it can't be mistaken for one of the names above
+            Percentile::Custom(CustomPercentile { ppm }) => {
+                let fraction = format!("{:04}", ppm % 10_000);
+                let fraction = fraction.trim_end_matches('0');
+                if fraction.is_empty() {
+                    write!(f, "p{}", ppm / 10_000)
+                } else {
+                    write!(f, "p{}.{}", ppm / 10_000, fraction)
+                }
+            }
+        }
+    }
+}
+
+/// Controls how fractional results are converted to integers. This is used
+/// when a value is scaled into a coarser unit (eg: ns to ms) and when a floating
+/// point aggregate, such as the rate derived from consecutive counter readings,
+/// is stored as a `u64`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RoundingMode {
+    /// round toward zero
+    #[default]
+    Truncate,
+    /// round to the nearest integer, with halfway cases rounded up
+    Nearest,
+    /// round toward positive infinity
+    Up,
+}
+
+impl RoundingMode {
+    /// Divides `value` by `divisor`, rounding the quotient according to the
+    /// `RoundingMode`. The result never exceeds `value`. Panics if `divisor` is
+    /// zero.
+    pub fn scale(self, value: u64, divisor: u64) -> u64 {
+        self.scale_wide(u128::from(value), u128::from(divisor)) as u64
+    }
+
+    // divides as with scale(), for quotients computed from wider values, eg: a
+    // rate in units per second from a count over nanoseconds
+    pub(crate) fn scale_wide(self, value: u128, divisor: u128) -> u128 {
+        let quotient = value / divisor;
+        let remainder = value % divisor;
+        let round_up = match self {
+            RoundingMode::Truncate => false,
+            RoundingMode::Nearest => remainder >= divisor - remainder,
+            RoundingMode::Up => remainder > 0,
+        };
+        if round_up {
+            quotient + 1
+        } else {
+            quotient
+        }
+    }
+
+    /// Converts a floating point value to a `u64`, rounding according to the
+    /// `RoundingMode`. Values outside the range of `u64` saturate at its bounds
+    /// and NaN converts to zero.
+    pub fn round(self, value: f64) -> u64 {
+        let value = match self {
+            RoundingMode::Truncate => value.trunc(),
+            RoundingMode::Nearest => value.round(),
+            RoundingMode::Up => value.ceil(),
+        };
+        value as u64
+    }
+
+    // the representation stored in an atomic by a `Channel`
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            RoundingMode::Truncate => 0,
+            RoundingMode::Nearest => 1,
+            RoundingMode::Up => 2,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => RoundingMode::Nearest,
+            2 => RoundingMode::Up,
+            _ => RoundingMode::Truncate,
         }
     }
 }
 
+/// The unit of the times recorded into a `Channel`, see `Channel::set_unit`
+/// and `Channel::percentile_as`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TimeUnit {
+    #[default]
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl TimeUnit {
+    /// Returns the number of nanoseconds in one of the unit
+    pub fn nanoseconds(self) -> u64 {
+        match self {
+            TimeUnit::Nanoseconds => 1,
+            TimeUnit::Microseconds => 1_000,
+            TimeUnit::Milliseconds => 1_000_000,
+            TimeUnit::Seconds => 1_000_000_000,
+        }
+    }
+
+    /// Converts a `value` of this unit into the `unit`
+    pub fn convert(self, value: f64, unit: TimeUnit) -> f64 {
+        value * self.nanoseconds() as f64 / unit.nanoseconds() as f64
+    }
+
+    // the representation stored in an atomic by a `Channel`
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            TimeUnit::Nanoseconds => 0,
+            TimeUnit::Microseconds => 1,
+            TimeUnit::Milliseconds => 2,
+            TimeUnit::Seconds => 3,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TimeUnit::Microseconds,
+            2 => TimeUnit::Milliseconds,
+            3 => TimeUnit::Seconds,
+            _ => TimeUnit::Nanoseconds,
+        }
+    }
+}
+
+/// Controls how a distribution value above the max of a `Channel`'s histogram
+/// is recorded
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutOfRangePolicy {
+    /// record the value into the histogram, which counts it as too high and
+    /// reports it at the max
+    #[default]
+    Clamp,
+    /// exclude the value from the histogram and add its count to the
+    /// `OutOfRange` output instead
+    Count,
+}
+
+impl OutOfRangePolicy {
+    // the representation stored in an atomic by a `Channel`
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            OutOfRangePolicy::Clamp => 0,
+            OutOfRangePolicy::Count => 1,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => OutOfRangePolicy::Count,
+            _ => OutOfRangePolicy::Clamp,
+        }
+    }
+}
+
+/// A statistic reported by a `Channel`. Equality and hashing compare the
+/// variant and, for percentiles, the `Percentile` variant, so an `Output` is a
+/// stable key across runs. A `Percentile::Custom` is never equal to a named
+/// percentile, so the same percentile is the same `Output` whichever way it
+/// was constructed.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Output {
+    Cardinality,
     Counter,
+    CounterInterval,
+    Ewma,
+    LastWriteTime,
     MaxPointTime,
+    MaxValue,
+    Mean,
     MinPointTime,
+    MinValue,
+    OutOfRange,
+    Overflows,
     Percentile(Percentile),
+    Rate,
+    StdDev,
+}
+
+/// Error returned when a string does not name an `Output`
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseOutputError {
+    // the string is not the `Display` form of any output
+    Unrecognized,
+}
+
+/// Renders the `Output` as a stable name, eg: `counter`, `max_time`, or the
+/// `Percentile` name, such as `p99` or `p99.95`. The name can be parsed back
+/// with `FromStr`.
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Output::Cardinality => write!(f, "cardinality"),
+            Output::Counter => write!(f, "counter"),
+            Output::CounterInterval => write!(f, "counter_interval"),
+            Output::Ewma => write!(f, "ewma"),
+            Output::LastWriteTime => write!(f, "last_write_time"),
+            Output::MaxPointTime => write!(f, "max_time"),
+            Output::MaxValue => write!(f, "max_value"),
+            Output::Mean => write!(f, "mean"),
+            Output::MinPointTime => write!(f, "min_time"),
+            Output::MinValue => write!(f, "min_value"),
+            Output::OutOfRange => write!(f, "out_of_range"),
+            Output::Overflows => write!(f, "overflows"),
+            Output::Percentile(percentile) => write!(f, "{}", percentile),
+            Output::Rate => write!(f, "rate"),
+            Output::StdDev => write!(f, "std_dev"),
+        }
+    }
+}
+
+/// Parses the name of an `Output` as rendered by `Display`. Percentiles may
+/// also be given as any percentage in the range 0 - 100, eg: `p99.95`, and are
+/// converted with `Percentile::from_percent`, so a percentage which matches a
+/// named percentile parses as the named variant.
+impl std::str::FromStr for Output {
+    type Err = ParseOutputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let output = match s {
+            "cardinality" => Output::Cardinality,
+            "counter" => Output::Counter,
+            "counter_interval" => Output::CounterInterval,
+            "ewma" => Output::Ewma,
+            "last_write_time" => Output::LastWriteTime,
+            "max_time" => Output::MaxPointTime,
+            "max_value" => Output::MaxValue,
+            "mean" => Output::Mean,
+            "min_time" => Output::MinPointTime,
+            "min_value" => Output::MinValue,
+            "out_of_range" => Output::OutOfRange,
+            "overflows" => Output::Overflows,
+            "rate" => Output::Rate,
+            "std_dev" => Output::StdDev,
+            _ => Output::Percentile(parse_percentile(s).ok_or(ParseOutputError::Unrecognized)?),
+        };
+        Ok(output)
+    }
+}
+
+// parses either the name of a percentile or a percentage prefixed with 'p'
+fn parse_percentile(s: &str) -> Option<Percentile> {
+    if let Some(preset) = PRESETS.iter().find(|preset| preset.to_string() == s) {
+        return Some(*preset);
+    }
+    let percent = s.strip_prefix('p')?;
+    let digits = percent.chars().filter(|c| c.is_ascii_digit()).count();
+    let points = percent.chars().filter(|c| *c == '.').count();
+    if digits == 0 || digits + points != percent.len() || points > 1 {
+        return None;
+    }
+    percent
+        .parse()
+        .ok()
+        .and_then(|percent| Percentile::from_percent(percent).ok())
 }
 
 pub struct Reading {
     label: String,
     output: Output,
     value: u64,
+    // dimensions of the channel the reading was taken from
+    labels: HashMap<String, String>,
+    // the time of the latest measurement the reading reflects
+    time: Option<u64>,
+    // whether the value holds the bits of an i64
+    signed: bool,
+    // the id of a trace which produced the value
+    exemplar: Option<u128>,
 }
 
 impl Reading {
@@ -140,9 +558,42 @@ impl Reading {
             label,
             output,
             value,
+            labels: HashMap::new(),
+            time: None,
+            signed: false,
+            exemplar: None,
         }
     }
 
+    /// Create a new `Reading` of a signed value, eg: the `Counter` output of a
+    /// `SignedGauge` channel. `value()` returns the bits of the value as a
+    /// `u64`, use `signed_value()` to read it back.
+    pub fn new_signed(label: String, output: Output, value: i64) -> Self {
+        let mut reading = Self::new(label, output, value as u64);
+        reading.signed = true;
+        reading
+    }
+
+    /// Sets the time of the `Reading`, in the same units as the times of the
+    /// `Measurement`s it was taken from
+    pub fn with_time(mut self, time: u64) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Attaches the id of a trace which produced the value, eg: the slowest
+    /// request of the interval, see `Channel::record_traced()`
+    pub fn with_exemplar(mut self, trace_id: u128) -> Self {
+        self.exemplar = Some(trace_id);
+        self
+    }
+
+    /// Attaches the labels, eg: `backend="cache01"`, to the `Reading`
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
     pub fn output(&self) -> Output {
         self.output.clone()
     }
@@ -154,11 +605,103 @@ impl Reading {
     pub fn value(&self) -> u64 {
         self.value
     }
+
+    /// Returns the value of a `Reading` created with `new_signed()`, or `None`
+    /// if the value is unsigned
+    pub fn signed_value(&self) -> Option<i64> {
+        if self.signed {
+            Some(self.value as i64)
+        } else {
+            None
+        }
+    }
+
+    // the value as it should be rendered by exporters
+    pub(crate) fn formatted_value(&self) -> String {
+        match self.signed_value() {
+            Some(value) => value.to_string(),
+            None => self.value.to_string(),
+        }
+    }
+
+    /// Returns the time of the `Reading`, if it has one
+    pub fn time(&self) -> Option<u64> {
+        self.time
+    }
+
+    /// Returns the trace id attached to the `Reading`, if it has one
+    pub fn exemplar(&self) -> Option<u128> {
+        self.exemplar
+    }
+
+    /// Returns the labels of the `Channel` the `Reading` was taken from
+    pub fn labels(&self) -> HashMap<String, String> {
+        self.labels.clone()
+    }
+
+    /// Serializes the `Reading` as a JSON object with `name`, `output`, and
+    /// `value` fields. Percentile readings include a numeric `percentile` field
+    /// in the range 0.0 - 1.0, and readings with an exemplar include it as a
+    /// 32 digit hex `trace_id` field.
+    pub fn to_json(&self) -> String {
+        let output = match self.output {
+            Output::Cardinality => "cardinality",
+            Output::Counter => "counter",
+            Output::CounterInterval => "counter_interval",
+            Output::Ewma => "ewma",
+            Output::LastWriteTime => "last_write_time",
+            Output::MaxPointTime => "max_point_time",
+            Output::MaxValue => "max_value",
+            Output::Mean => "mean",
+            Output::MinPointTime => "min_point_time",
+            Output::MinValue => "min_value",
+            Output::OutOfRange => "out_of_range",
+            Output::Overflows => "overflows",
+            Output::Percentile(_) => "percentile",
+            Output::Rate => "rate",
+            Output::StdDev => "std_dev",
+        };
+        let mut fields = vec![
+            format!("\"name\":\"{}\"", escape_json(&self.label)),
+            format!("\"output\":\"{}\"", output),
+        ];
+        if let Output::Percentile(percentile) = self.output {
+            fields.push(format!("\"percentile\":{}", percentile.as_f64()));
+        }
+        fields.push(format!("\"value\":{}", self.formatted_value()));
+        if let Some(trace_id) = self.exemplar {
+            fields.push(format!("\"trace_id\":\"{:032x}\"", trace_id));
+        }
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// Serializes `Reading`s as a JSON array of objects, see `Reading::to_json`
+pub fn to_json(readings: &[Reading]) -> String {
+    let objects: Vec<String> = readings.iter().map(Reading::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
 
     fn approx_eq(a: u64, b: u64, precision: u64) -> bool {
         let power = 10_u64.pow(precision as u32) as f64;
@@ -173,6 +716,249 @@ mod tests {
         }
     }
 
+    #[test]
+    fn percentile_basis_points() {
+        assert_eq!(Percentile::from_basis_points(9_990), Ok(Percentile::P999));
+        assert_eq!(Percentile::from_basis_points(0), Ok(Percentile::Minimum));
+        assert_eq!(
+            Percentile::from_basis_points(10_000),
+            Ok(Percentile::Maximum)
+        );
+        assert_eq!(
+            Percentile::from_basis_points(9_995),
+            Percentile::from_ppm(999_500)
+        );
+        assert_eq!(
+            Percentile::from_basis_points(10_001),
+            Err(PercentileError::OutOfRange)
+        );
+        for bp in &[0, 1, 50, 2_500, 9_950, 9_990, 9_999, 10_000] {
+            let percentile = Percentile::from_basis_points(*bp).unwrap();
+            assert_eq!(percentile.basis_points(), Some(*bp));
+            assert_eq!(
+                Ok(percentile),
+                Percentile::from_percent(f64::from(*bp) / 100.0)
+            );
+        }
+        assert_eq!(Percentile::from_ppm(999_950).unwrap().basis_points(), None);
+
+        // the same percentile is the same output whichever way it was given
+        let mut outputs = std::collections::HashSet::new();
+        outputs.insert(Output::Percentile(
+            Percentile::from_basis_points(9_995).unwrap(),
+        ));
+        outputs.insert(Output::Percentile(Percentile::from_percent(99.95).unwrap()));
+        assert_eq!(outputs.len(), 1);
+
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        for value in 1..=1_000 {
+            histogram.increment(value * 3, 1);
+        }
+        for bp in &[1, 2_500, 9_950, 9_990] {
+            let percentile = Percentile::from_basis_points(*bp).unwrap();
+            assert_eq!(
+                histogram.percentile_bp(percentile.basis_points().unwrap()),
+                histogram.percentile(percentile.as_f64())
+            );
+        }
+    }
+
+    #[test]
+    fn percentile_from_ppm() {
+        assert_eq!(Percentile::from_ppm(500_000), Ok(Percentile::P50));
+        assert_eq!(Percentile::from_ppm(1_000_000), Ok(Percentile::Maximum));
+        assert_eq!(
+            Percentile::from_ppm(1_000_001),
+            Err(PercentileError::OutOfRange)
+        );
+        let custom = Percentile::from_ppm(999_500).unwrap();
+        match custom {
+            Percentile::Custom(custom) => assert_eq!(custom.ppm(), 999_500),
+            _ => panic!("expected a custom percentile"),
+        }
+        assert_eq!(custom, Percentile::from_percent(99.95).unwrap());
+    }
+
+    #[test]
+    fn percentile_from_percent() {
+        assert_eq!(Percentile::from_percent(0.0), Ok(Percentile::Minimum));
+        assert_eq!(Percentile::from_percent(100.0), Ok(Percentile::Maximum));
+        assert_eq!(Percentile::from_percent(99.9), Ok(Percentile::P999));
+        assert_eq!(Percentile::from_percent(0.01), Ok(Percentile::p001));
+        assert_eq!(
+            Percentile::from_percent(99.95),
+            Percentile::from_ppm(999_500)
+        );
+        assert_eq!(
+            Percentile::from_percent(-0.1),
+            Err(PercentileError::OutOfRange)
+        );
+        assert_eq!(
+            Percentile::from_percent(100.1),
+            Err(PercentileError::OutOfRange)
+        );
+        assert_eq!(
+            Percentile::from_percent(f64::NAN),
+            Err(PercentileError::OutOfRange)
+        );
+
+        let percentile = Percentile::from_percent(99.95).unwrap();
+        assert_eq!(percentile.as_f64(), 0.9995);
+        assert_eq!(format!("{}", percentile), "p99.95");
+        assert_eq!(
+            format!("{}", Percentile::from_ppm(125_000).unwrap()),
+            "p12.5"
+        );
+        assert_eq!(format!("{}", Percentile::from_ppm(5_000).unwrap()), "p0.5");
+        assert_eq!(format!("{}", Percentile::from_ppm(150_000).unwrap()), "p15");
+    }
+
+    #[test]
+    fn output_round_trip() {
+        let mut outputs = vec![
+            Output::Cardinality,
+            Output::Counter,
+            Output::CounterInterval,
+            Output::Ewma,
+            Output::LastWriteTime,
+            Output::MaxPointTime,
+            Output::MaxValue,
+            Output::Mean,
+            Output::MinPointTime,
+            Output::MinValue,
+            Output::OutOfRange,
+            Output::Overflows,
+            Output::Rate,
+            Output::StdDev,
+        ];
+        outputs.extend(PRESETS.iter().map(|preset| Output::Percentile(*preset)));
+        for ppm in &[1, 5_000, 15_000, 125_000, 150_000, 999_500, 999_999] {
+            outputs.push(Output::Percentile(Percentile::from_ppm(*ppm).unwrap()));
+        }
+        for output in outputs {
+            assert_eq!(output.to_string().parse(), Ok(output));
+        }
+
+        assert_eq!(Output::MaxPointTime.to_string(), "max_time");
+        assert_eq!(Output::Percentile(Percentile::p999).to_string(), "p999");
+        assert_eq!("p99.9".parse(), Ok(Output::Percentile(Percentile::p999)));
+        assert_eq!(
+            "p99.95".parse(),
+            Ok(Output::Percentile(Percentile::from_ppm(999_500).unwrap()))
+        );
+        assert_eq!(
+            "p15".parse(),
+            Ok(Output::Percentile(Percentile::from_ppm(150_000).unwrap()))
+        );
+        for invalid in &[
+            "", "p", "p.", "p100.1", "p-1", "p1e2", "p1.2.3", "Counter", "max",
+        ] {
+            assert_eq!(
+                invalid.parse::<Output>(),
+                Err(ParseOutputError::Unrecognized)
+            );
+        }
+    }
+
+    #[test]
+    fn filtered_channels() {
+        let metrics = Metrics::<AtomicU64>::new();
+        let recorder = metrics.recorder();
+        recorder.add_channel("request/ok".to_string(), Source::Counter, None);
+        recorder.add_channel("request/error".to_string(), Source::Counter, None);
+        recorder.add_channel("connections".to_string(), Source::Gauge, None);
+        recorder.add_channel("request/latency".to_string(), Source::TimeInterval, None);
+
+        let names = |channels: Vec<Arc<Channel<AtomicU64>>>| {
+            let mut names: Vec<String> = channels.iter().map(|c| c.name()).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(metrics.channels_by_source(Source::Counter)),
+            vec!["request/error", "request/ok"]
+        );
+        assert_eq!(
+            names(metrics.channels_by_source(Source::Gauge)),
+            vec!["connections"]
+        );
+        assert!(metrics.channels_by_source(Source::Distribution).is_empty());
+        assert_eq!(
+            names(metrics.channels_with_prefix("request/")),
+            vec!["request/error", "request/latency", "request/ok"]
+        );
+        assert_eq!(names(metrics.channels_with_prefix("")).len(), 4);
+        assert!(metrics.channels_with_prefix("response/").is_empty());
+
+        recorder.delete_channel("connections".to_string());
+        assert!(metrics.channels_by_source(Source::Gauge).is_empty());
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(to_json(&[]), "[]");
+        let readings = vec![
+            Reading::new("requests".to_string(), Output::Counter, 42),
+            Reading::new(
+                "latency".to_string(),
+                Output::Percentile(Percentile::p999),
+                1_500,
+            ),
+            Reading::new(
+                "latency".to_string(),
+                Output::Percentile(Percentile::Maximum),
+                2_000,
+            ),
+            Reading::new("say \"hi\"\n".to_string(), Output::StdDev, 7),
+        ];
+        assert_eq!(
+            to_json(&readings),
+            concat!(
+                r#"[{"name":"requests","output":"counter","value":42},"#,
+                r#"{"name":"latency","output":"percentile","percentile":0.999,"value":1500},"#,
+                r#"{"name":"latency","output":"percentile","percentile":1,"value":2000},"#,
+                r#"{"name":"say \"hi\"\n","output":"std_dev","value":7}]"#,
+            )
+        );
+    }
+
+    #[test]
+    fn rounding_mode() {
+        assert_eq!(RoundingMode::Truncate.scale(1_999_999, 1_000_000), 1);
+        assert_eq!(RoundingMode::Nearest.scale(1_999_999, 1_000_000), 2);
+        assert_eq!(RoundingMode::Up.scale(1_999_999, 1_000_000), 2);
+        assert_eq!(RoundingMode::Nearest.scale(1_499_999, 1_000_000), 1);
+        assert_eq!(RoundingMode::Nearest.scale(1_500_000, 1_000_000), 2);
+        assert_eq!(RoundingMode::Up.scale(2_000_000, 1_000_000), 2);
+        assert_eq!(RoundingMode::Truncate.round(1.5), 1);
+        assert_eq!(RoundingMode::Nearest.round(1.5), 2);
+        assert_eq!(RoundingMode::Up.round(1.1), 2);
+        for mode in &[
+            RoundingMode::Truncate,
+            RoundingMode::Nearest,
+            RoundingMode::Up,
+        ] {
+            assert_eq!(RoundingMode::from_u8(mode.to_u8()), *mode);
+        }
+    }
+
+    #[test]
+    fn time_unit() {
+        assert_eq!(TimeUnit::default(), TimeUnit::Nanoseconds);
+        assert_eq!(
+            TimeUnit::Nanoseconds.convert(1_500.0, TimeUnit::Microseconds),
+            1.5
+        );
+        assert_eq!(
+            TimeUnit::Seconds.convert(2.0, TimeUnit::Milliseconds),
+            2_000.0
+        );
+        assert_eq!(
+            TimeUnit::Microseconds.convert(250.0, TimeUnit::Microseconds),
+            250.0
+        );
+    }
+
     #[test]
     fn counter_channel() {
         let metrics = Metrics::<AtomicU64>::new();
@@ -182,29 +968,35 @@ mod tests {
         recorder.add_channel(name.clone(), Source::Counter, Some(histogram_config));
         assert_eq!(recorder.counter("test".to_string()), 0);
         assert_eq!(recorder.percentile("test".to_string(), 0.0), None);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 1_000_000_000,
-                value: 1,
-            },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 1_000_000_000,
+                    value: 1,
+                },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 1);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 2_000_000_000,
-                value: 1,
-            },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 2_000_000_000,
+                    value: 1,
+                },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 1);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 3_000_000_000,
-                value: 2,
-            },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 3_000_000_000,
+                    value: 2,
+                },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 2);
         assert!(approx_eq(
             recorder.percentile("test".to_string(), 0.0).unwrap(),
@@ -231,25 +1023,31 @@ mod tests {
         let recorder = metrics.recorder();
         recorder.add_channel(name.clone(), Source::Counter, Some(histogram));
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 0_u64.wrapping_sub(2_000_000_000),
-                value: 0,
-            },
-        );
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 0_u64.wrapping_sub(1_000_000_000),
-                value: 1,
-            },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 0_u64.wrapping_sub(2_000_000_000),
+                    value: 0,
+                },
+            )
+            .unwrap();
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 0_u64.wrapping_sub(1_000_000_000),
+                    value: 1,
+                },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 1);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter { time: 0, value: 2 },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter { time: 0, value: 2 },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 2);
         assert!(approx_eq(
             recorder.percentile("test".to_string(), 0.0).unwrap(),
@@ -258,20 +1056,24 @@ mod tests {
         ));
         recorder.zero();
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 0,
-                value: 0_u64.wrapping_sub(1),
-            },
-        );
-        recorder.record(
-            "test".to_string(),
-            Measurement::Counter {
-                time: 1_000_000_000,
-                value: 0,
-            },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 0,
+                    value: 0_u64.wrapping_sub(1),
+                },
+            )
+            .unwrap();
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Counter {
+                    time: 1_000_000_000,
+                    value: 0,
+                },
+            )
+            .unwrap();
         assert!(approx_eq(
             recorder.percentile("test".to_string(), 0.0).unwrap(),
             1,
@@ -320,7 +1122,9 @@ mod tests {
         ];
         for (time, &value) in data.iter().enumerate() {
             let time = time as u64 * 1_000_000_000;
-            recorder.record("test".to_string(), Measurement::Counter { time, value });
+            recorder
+                .record("test".to_string(), Measurement::Counter { time, value })
+                .unwrap();
             assert_eq!(recorder.counter("test".to_string()), value);
         }
         assert!(approx_eq(
@@ -343,24 +1147,28 @@ mod tests {
         let histogram = Histogram::new(100, 3, None, None);
         recorder.add_channel(name.clone(), Source::Distribution, Some(histogram));
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Distribution {
-                value: 1,
-                count: 1,
-                time: 0,
-            },
-        );
-        assert_eq!(recorder.counter("test".to_string()), 1);
-        for i in 2..101 {
-            recorder.record(
+        recorder
+            .record(
                 "test".to_string(),
                 Measurement::Distribution {
-                    value: i,
+                    value: 1,
                     count: 1,
                     time: 0,
                 },
-            );
+            )
+            .unwrap();
+        assert_eq!(recorder.counter("test".to_string()), 1);
+        for i in 2..101 {
+            recorder
+                .record(
+                    "test".to_string(),
+                    Measurement::Distribution {
+                        value: i,
+                        count: 1,
+                        time: 0,
+                    },
+                )
+                .unwrap();
         }
         assert_eq!(recorder.counter("test".to_string()), 100);
         assert_eq!(recorder.percentile("test".to_string(), 0.0), Some(1));
@@ -380,25 +1188,58 @@ mod tests {
         let histogram = Histogram::new(100, 3, None, None);
         recorder.add_channel(name.clone(), Source::Gauge, Some(histogram));
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record("test".to_string(), Measurement::Gauge { value: 0, time: 1 });
+        recorder
+            .record("test".to_string(), Measurement::Gauge { value: 0, time: 1 })
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Gauge {
-                value: 100,
-                time: 1,
-            },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Gauge {
+                    value: 100,
+                    time: 1,
+                },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 100);
-        recorder.record("test".to_string(), Measurement::Gauge { value: 0, time: 1 });
+        recorder
+            .record("test".to_string(), Measurement::Gauge { value: 0, time: 1 })
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record(
-            "test".to_string(),
-            Measurement::Gauge { value: 42, time: 1 },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::Gauge { value: 42, time: 1 },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 42);
     }
 
+    #[test]
+    fn record_error() {
+        let metrics = Metrics::<AtomicU64>::new();
+        let recorder = metrics.recorder();
+        recorder.add_channel("test".to_string(), Source::Gauge, None);
+        assert_eq!(
+            recorder.record(
+                "test".to_string(),
+                Measurement::Counter { value: 1, time: 1 }
+            ),
+            Err(RecordError::SourceMismatch {
+                expected: Source::Gauge,
+                got: Source::Counter,
+            })
+        );
+        assert_eq!(recorder.counter("test".to_string()), 0);
+        assert_eq!(
+            recorder.record(
+                "missing".to_string(),
+                Measurement::Counter { value: 1, time: 1 }
+            ),
+            Ok(())
+        );
+    }
+
     #[test]
     fn time_interval_channel() {
         let metrics = Metrics::<AtomicU64>::new();
@@ -407,19 +1248,23 @@ mod tests {
         let histogram = Histogram::new(100, 3, None, None);
         recorder.add_channel(name.clone(), Source::TimeInterval, Some(histogram));
         assert_eq!(recorder.counter("test".to_string()), 0);
-        recorder.record(
-            "test".to_string(),
-            Measurement::TimeInterval { start: 0, stop: 1 },
-        );
+        recorder
+            .record(
+                "test".to_string(),
+                Measurement::TimeInterval { start: 0, stop: 1 },
+            )
+            .unwrap();
         assert_eq!(recorder.counter("test".to_string()), 1);
         for i in 1..100 {
-            recorder.record(
-                "test".to_string(),
-                Measurement::TimeInterval {
-                    start: i,
-                    stop: i + 1,
-                },
-            );
+            recorder
+                .record(
+                    "test".to_string(),
+                    Measurement::TimeInterval {
+                        start: i,
+                        stop: i + 1,
+                    },
+                )
+                .unwrap();
         }
         assert_eq!(recorder.counter("test".to_string()), 100);
         assert_eq!(recorder.percentile("test".to_string(), 0.0), Some(1));
@@ -34,13 +34,200 @@ impl Point {
         self.time.get()
     }
 
+    /// Overwrites the value and time of the `Point`. Use `update_max()` or
+    /// `update_min()` to only keep the most extreme value.
     pub fn set(&self, value: u64, time: u64) {
         self.value.set(value);
         self.time.set(time);
     }
 
+    /// Adds `delta` to the value, saturating at `u64::MAX`. The time is
+    /// unchanged.
+    pub fn saturating_add_value(&self, delta: u64) {
+        self.value.saturating_add(delta);
+    }
+
+    /// Sets the `Point` if it has not been set, or if `value` is greater than
+    /// the current value. Returns true if the `Point` was set. Once set, the
+    /// value is raised atomically, so concurrent updates never lose the
+    /// greatest value, though the time may be that of a lesser one.
+    pub fn update_max(&self, value: u64, time: u64) -> bool {
+        if self.time() == 0 {
+            self.set(value, time);
+            return true;
+        }
+        if value > self.value.fetch_max(value, Ordering::Relaxed) {
+            self.time.set(time);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sets the `Point` if it has not been set, or if `value` is less than the
+    /// current value. Returns true if the `Point` was set. Once set, the value
+    /// is lowered atomically, as with `update_max()`.
+    pub fn update_min(&self, value: u64, time: u64) -> bool {
+        if self.time() == 0 {
+            self.set(value, time);
+            return true;
+        }
+        if value < self.value.fetch_min(value, Ordering::Relaxed) {
+            self.time.set(time);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn reset(&self) {
+        self.value.set(0);
+        self.time.set(0);
+    }
+}
+
+/// A `Point` holding a signed value, eg: the extremes of a `SignedGauge`
+pub struct SignedPoint {
+    value: AtomicI64,
+    time: AtomicU64,
+}
+
+impl SignedPoint {
+    pub fn new(value: i64, time: u64) -> Self {
+        let value = AtomicI64::new(value);
+        let time = AtomicU64::new(time);
+        Self { value, time }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.value.get()
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time.get()
+    }
+
+    /// Overwrites the value and time of the `SignedPoint`
+    pub fn set(&self, value: i64, time: u64) {
+        self.value.set(value);
+        self.time.set(time);
+    }
+
+    /// Sets the `SignedPoint` if it has not been set, or if `value` is greater
+    /// than the current value. Returns true if the `SignedPoint` was set, see
+    /// `Point::update_max()`
+    pub fn update_max(&self, value: i64, time: u64) -> bool {
+        if self.time() == 0 {
+            self.set(value, time);
+            return true;
+        }
+        if value > self.value.fetch_max(value, Ordering::Relaxed) {
+            self.time.set(time);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sets the `SignedPoint` if it has not been set, or if `value` is less
+    /// than the current value. Returns true if the `SignedPoint` was set, see
+    /// `Point::update_min()`
+    pub fn update_min(&self, value: i64, time: u64) -> bool {
+        if self.time() == 0 {
+            self.set(value, time);
+            return true;
+        }
+        if value < self.value.fetch_min(value, Ordering::Relaxed) {
+            self.time.set(time);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn reset(&self) {
         self.value.set(0);
         self.time.set(0);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_max() {
+        let point = Point::new(0, 0);
+        assert!(point.update_max(5, 10));
+        assert_eq!((point.value(), point.time()), (5, 10));
+        assert!(!point.update_max(3, 20));
+        assert_eq!((point.value(), point.time()), (5, 10));
+        point.update_max(5, 30);
+        assert_eq!((point.value(), point.time()), (5, 10));
+        point.update_max(8, 40);
+        assert_eq!((point.value(), point.time()), (8, 40));
+    }
+
+    #[test]
+    fn update_min() {
+        let point = Point::new(0, 0);
+        // an unset point takes the first value, even though it is larger
+        assert!(point.update_min(5, 10));
+        assert_eq!((point.value(), point.time()), (5, 10));
+        assert!(!point.update_min(8, 20));
+        assert_eq!((point.value(), point.time()), (5, 10));
+        assert!(point.update_min(0, 30));
+        assert_eq!((point.value(), point.time()), (0, 30));
+        point.reset();
+        point.update_min(7, 40);
+        assert_eq!((point.value(), point.time()), (7, 40));
+    }
+
+    #[test]
+    fn signed_update() {
+        let max = SignedPoint::new(0, 0);
+        let min = SignedPoint::new(0, 0);
+        for (value, time) in &[(-5, 10), (3, 20), (-8, 30), (-1, 40)] {
+            max.update_max(*value, *time);
+            min.update_min(*value, *time);
+        }
+        assert_eq!((max.value(), max.time()), (3, 20));
+        assert_eq!((min.value(), min.time()), (-8, 30));
+        assert!(!max.update_max(3, 60));
+        assert!(!min.update_min(-2, 60));
+        assert!(min.update_min(-9, 60));
+        // an unset point takes the first value, even though it is smaller
+        max.reset();
+        assert!(max.update_max(-20, 50));
+        assert_eq!((max.value(), max.time()), (-20, 50));
+    }
+
+    #[test]
+    fn update_max_concurrent() {
+        let point = std::sync::Arc::new(Point::new(0, 0));
+        point.update_max(1, 1);
+        let threads: Vec<_> = (0..8_u64)
+            .map(|thread| {
+                let point = point.clone();
+                std::thread::spawn(move || {
+                    for value in 0..10_000 {
+                        point.update_max(value * 8 + thread, 2);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!((point.value(), point.time()), (79_999, 2));
+    }
+
+    #[test]
+    fn saturating_add_value() {
+        let point = Point::new(u64::MAX - 1, 10);
+        point.saturating_add_value(1);
+        assert_eq!(point.value(), u64::MAX);
+        point.saturating_add_value(5);
+        assert_eq!((point.value(), point.time()), (u64::MAX, 10));
+    }
+}
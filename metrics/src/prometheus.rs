@@ -0,0 +1,329 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Renders the `Reading`s of the `Channel`s in the Prometheus text exposition
+/// format. Metrics are rendered in name order.
+///
+/// The `Counter` output is exposed as a `counter` named `name_total`, or as a
+/// `gauge` named `name` for `Gauge` and `SignedGauge` channels. Percentiles
+/// are grouped into a `summary` named `name` with one `name{quantile="0.99"}`
+/// sample each. The remaining outputs are exposed as gauges with a suffix, eg:
+/// `name_mean`. The name is that of the `Reading`, so the percentiles of a
+/// histogram recorded with `Channel::record_labeled()` form a summary of their
+/// own, eg: `name_label`. The labels of each `Reading` are exposed as
+/// Prometheus labels, so channels of the same name with different labels are
+/// rendered as series of one metric. Characters which are not valid in a
+/// Prometheus metric or label name are replaced with `_`.
+pub fn render_prometheus<T>(channels: &[Arc<Channel<T>>]) -> String
+where
+    T: Counter + Unsigned + 'static,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    let mut samples: Vec<Sample> = Vec::new();
+    for channel in channels {
+        let gauge = channel.source() == Source::Gauge || channel.source() == Source::SignedGauge;
+        for reading in channel.readings() {
+            let name = sanitize(&reading.label());
+            let mut labels: HashMap<String, String> = reading
+                .labels()
+                .into_iter()
+                .map(|(key, value)| (sanitize_label(&key), value))
+                .collect();
+            let output = reading.output();
+            let rank = match output {
+                Output::Percentile(_) => 0.0,
+                ref output => order(output),
+            };
+            let position = order(&output);
+            let (metric, kind) = match output {
+                Output::Counter if gauge => (name.clone(), "gauge"),
+                Output::Counter => (format!("{}_total", name), "counter"),
+                Output::Percentile(percentile) => {
+                    labels.insert("quantile".to_string(), percentile.as_f64().to_string());
+                    (name.clone(), "summary")
+                }
+                ref output => (format!("{}_{}", name, suffix(output)), "gauge"),
+            };
+            let mut labels: Vec<(String, String)> = labels.into_iter().collect();
+            labels.sort();
+            samples.push(Sample {
+                name,
+                rank,
+                labels,
+                order: position,
+                metric,
+                kind,
+                value: reading.formatted_value(),
+            });
+        }
+    }
+    samples.sort_by(|a, b| {
+        (&a.name, a.rank, &a.labels, a.order)
+            .partial_cmp(&(&b.name, b.rank, &b.labels, b.order))
+            .unwrap()
+    });
+
+    // the samples of each metric, in the order the metrics first appear
+    let mut families: Vec<(String, &str, String)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for sample in &samples {
+        let i = *index.entry(sample.metric.clone()).or_insert_with(|| {
+            families.push((sample.metric.clone(), sample.kind, String::new()));
+            families.len() - 1
+        });
+        let family = &mut families[i].2;
+        *family += &sample.metric;
+        if !sample.labels.is_empty() {
+            let labels: Vec<String> = sample
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect();
+            *family += &format!("{{{}}}", labels.join(","));
+        }
+        *family += &format!(" {}\n", sample.value);
+    }
+
+    let mut content = String::new();
+    for (metric, kind, lines) in families {
+        content += &format!("# TYPE {} {}\n", metric, kind);
+        content += &lines;
+    }
+    content
+}
+
+// a sample of a metric. samples are sorted by the name of the reading, the
+// rank of the metric among those of the name, the labels, and the order of
+// the output within the metric
+struct Sample {
+    name: String,
+    rank: f64,
+    labels: Vec<(String, String)>,
+    order: f64,
+    metric: String,
+    kind: &'static str,
+    value: String,
+}
+
+// the suffix of the gauge exposing an output
+fn suffix(output: &Output) -> &'static str {
+    match output {
+        Output::Cardinality => "cardinality",
+        Output::Counter => "counter",
+        Output::CounterInterval => "counter_interval",
+        Output::Ewma => "ewma",
+        Output::LastWriteTime => "last_write_time",
+        Output::MaxPointTime => "max_point_time",
+        Output::MaxValue => "max",
+        Output::Mean => "mean",
+        Output::MinPointTime => "min_point_time",
+        Output::MinValue => "min",
+        Output::OutOfRange => "out_of_range",
+        Output::Overflows => "overflows",
+        Output::Percentile(_) => "percentile",
+        Output::Rate => "rate",
+        Output::StdDev => "std_dev",
+    }
+}
+
+// the order outputs are rendered in, percentiles ascending after the counter
+fn order(output: &Output) -> f64 {
+    match output {
+        Output::Counter => -1.0,
+        Output::CounterInterval => -0.5,
+        Output::Cardinality => 1.25,
+        Output::Ewma => 1.5,
+        Output::Percentile(percentile) => percentile.as_f64(),
+        Output::LastWriteTime => 2.0,
+        Output::MaxPointTime => 3.0,
+        Output::MaxValue => 3.5,
+        Output::Mean => 4.0,
+        Output::MinPointTime => 5.0,
+        Output::MinValue => 5.25,
+        Output::OutOfRange => 5.5,
+        Output::Overflows => 6.0,
+        Output::Rate => 7.0,
+        Output::StdDev => 8.0,
+    }
+}
+
+// converts a channel name to a valid prometheus metric name
+fn sanitize(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.is_empty() || sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+// converts a label name to a valid prometheus label name, which unlike a
+// metric name may not contain a colon
+fn sanitize_label(name: &str) -> String {
+    sanitize(name).replace(':', "_")
+}
+
+// escapes a label value, which is quoted
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_and_percentiles() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let channel = Channel::new(
+            "request.latency-ns".to_string(),
+            Source::TimeInterval,
+            Some(histogram),
+        );
+        channel.add_output(Output::Percentile(Percentile::p99));
+        channel.add_output(Output::Counter);
+        channel.add_output(Output::Percentile(Percentile::p50));
+        for value in 1..=100 {
+            channel
+                .record(Measurement::TimeInterval {
+                    start: 1_000,
+                    stop: 1_000 + value,
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            render_prometheus(&[Arc::new(channel)]),
+            concat!(
+                "# TYPE request_latency_ns_total counter\n",
+                "request_latency_ns_total 100\n",
+                "# TYPE request_latency_ns summary\n",
+                "request_latency_ns{quantile=\"0.5\"} 50\n",
+                "request_latency_ns{quantile=\"0.99\"} 99\n",
+            )
+        );
+    }
+
+    #[test]
+    fn gauges() {
+        let gauge = Channel::<AtomicU64>::new("connections".to_string(), Source::Gauge, None);
+        gauge.add_output(Output::Counter);
+        gauge
+            .record(Measurement::Gauge {
+                time: 1_000,
+                value: 12,
+            })
+            .unwrap();
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let distribution = Channel::new("0size".to_string(), Source::Distribution, Some(histogram));
+        distribution.add_output(Output::Mean);
+        distribution
+            .record(Measurement::Distribution {
+                value: 10,
+                count: 2,
+                time: 1_000,
+            })
+            .unwrap();
+        let lag = Channel::<AtomicU64>::new("lag".to_string(), Source::SignedGauge, None);
+        lag.add_output(Output::Counter);
+        lag.record(Measurement::SignedGauge {
+            value: -250,
+            time: 1_000,
+        })
+        .unwrap();
+        assert_eq!(
+            render_prometheus(&[Arc::new(gauge), Arc::new(distribution), Arc::new(lag)]),
+            concat!(
+                "# TYPE _0size_mean gauge\n",
+                "_0size_mean 10\n",
+                "# TYPE connections gauge\n",
+                "connections 12\n",
+                "# TYPE lag gauge\n",
+                "lag -250\n",
+            )
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let histogram = Histogram::<AtomicU64>::new(1_000_000, 3, None, None);
+        let latency = Channel::new("t".to_string(), Source::TimeInterval, Some(histogram));
+        latency.add_output(Output::Percentile(Percentile::p50));
+        latency.set_label("zone".to_string(), "us \"west\"".to_string());
+        for (label, duration) in &[("hit", 10), ("miss", 200)] {
+            latency
+                .record_labeled(
+                    label,
+                    Measurement::Timing {
+                        duration: *duration,
+                        time: 1,
+                    },
+                )
+                .unwrap();
+        }
+        let requests: Vec<Arc<Channel<AtomicU64>>> = ["b", "a"]
+            .iter()
+            .map(|backend| {
+                let channel = Channel::new("req".to_string(), Source::Counter, None);
+                channel.add_output(Output::Counter);
+                channel.add_output(Output::Percentile(Percentile::p50));
+                channel.set_label("backend".to_string(), backend.to_string());
+                channel
+                    .record(Measurement::Increment { count: 3, time: 1 })
+                    .unwrap();
+                Arc::new(channel)
+            })
+            .collect();
+        let mut channels = requests;
+        channels.push(Arc::new(latency));
+        assert_eq!(
+            render_prometheus(&channels),
+            concat!(
+                "# TYPE req_total counter\n",
+                "req_total{backend=\"a\"} 3\n",
+                "req_total{backend=\"b\"} 3\n",
+                "# TYPE t summary\n",
+                "t{quantile=\"0.5\",zone=\"us \\\"west\\\"\"} 10\n",
+                "# TYPE t_hit summary\n",
+                "t_hit{quantile=\"0.5\",zone=\"us \\\"west\\\"\"} 10\n",
+                "# TYPE t_miss summary\n",
+                "t_miss{quantile=\"0.5\",zone=\"us \\\"west\\\"\"} 200\n",
+            )
+        );
+    }
+
+    #[test]
+    fn sanitize_names() {
+        assert_eq!(sanitize("a.b-c/d"), "a_b_c_d");
+        assert_eq!(sanitize("valid_name:sub"), "valid_name:sub");
+        assert_eq!(sanitize("9lives"), "_9lives");
+        assert_eq!(sanitize(""), "_");
+    }
+}
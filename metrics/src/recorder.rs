@@ -64,6 +64,29 @@ where
             labels: self.labels.clone(),
         }
     }
+
+    /// Returns the `Channel`s which track measurements of the given `Source`
+    pub fn channels_by_source(&self, source: Source) -> Vec<Arc<Channel<T>>> {
+        self.channels_where(|channel| channel.source() == source)
+    }
+
+    /// Returns the `Channel`s whose name starts with the given prefix
+    pub fn channels_with_prefix(&self, prefix: &str) -> Vec<Arc<Channel<T>>> {
+        self.channels_where(|channel| channel.name().starts_with(prefix))
+    }
+
+    fn channels_where<F>(&self, predicate: F) -> Vec<Arc<Channel<T>>>
+    where
+        F: Fn(&Channel<T>) -> bool,
+    {
+        let data_read = self.data_read.handle();
+        let labels = self.labels.lock().unwrap();
+        labels
+            .iter()
+            .filter_map(|label| data_read.get_and(label, |channel| channel[0].clone()))
+            .filter(|channel| predicate(channel))
+            .collect()
+    }
 }
 
 impl<T> Recorder<T>
@@ -72,13 +95,31 @@ where
     <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
     u64: From<<T as AtomicPrimitive>::Primitive>,
 {
+    /// Records a `Measurement` into the named `Channel`. Measurements for
+    /// channels which have not been added are ignored.
     pub fn record(
         &self,
         channel: String,
         measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
-    ) {
+    ) -> Result<(), RecordError> {
+        self.data_read
+            .get_and(&channel, |channel| (*channel)[0].record(measurement))
+            .unwrap_or(Ok(()))
+    }
+
+    /// Records a `Measurement` into the named `Channel` and the histogram it
+    /// keeps for the `label`, see `Channel::record_labeled`
+    pub fn record_labeled(
+        &self,
+        channel: String,
+        label: &str,
+        measurement: Measurement<<T as AtomicPrimitive>::Primitive>,
+    ) -> Result<(), RecordError> {
         self.data_read
-            .get_and(&channel, |channel| (*channel)[0].record(measurement));
+            .get_and(&channel, |channel| {
+                (*channel)[0].record_labeled(label, measurement)
+            })
+            .unwrap_or(Ok(()))
     }
 
     pub fn counter(&self, channel: String) -> u64 {
@@ -133,6 +174,22 @@ where
         result
     }
 
+    /// Returns the `Reading`s of all channels, with `Counter` outputs reporting
+    /// the increase since the previous call, see `Channel::readings_and_reset`
+    pub fn readings_and_reset(&self) -> Vec<Reading> {
+        let mut result = Vec::new();
+        let labels = self.labels.lock().unwrap();
+        for label in &*labels {
+            let readings = self
+                .data_read
+                .get_and(label, |channel| (*channel)[0].readings_and_reset());
+            if let Some(readings) = readings {
+                result.extend(readings);
+            }
+        }
+        result
+    }
+
     pub fn hash_map(&self) -> HashMap<String, HashMap<Output, u64>> {
         let mut result = HashMap::new();
         let labels = self.labels.lock().unwrap();
@@ -0,0 +1,27 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+#[cfg(feature = "statsd")]
+mod statsd;
+
+#[cfg(feature = "statsd")]
+pub use self::statsd::StatsdSink;
+
+use crate::Reading;
+
+/// A destination for `Reading`s, such as an external metrics pipeline
+pub trait ReadingSink {
+    /// Delivers a snapshot of `Reading`s to the sink
+    fn emit(&mut self, readings: &[Reading]);
+}
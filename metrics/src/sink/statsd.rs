@@ -0,0 +1,377 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+
+// a payload size which avoids fragmentation on most networks
+const DEFAULT_PACKET_SIZE: usize = 512;
+
+/// A `ReadingSink` which sends `Reading`s to a StatsD server over UDP.
+///
+/// Counters are sent as `name:value|c` with the value being the increase
+/// since the previous `emit()` of a counter with the same name and labels, or
+/// the whole value if the counter was reset, percentiles are sent as timers with
+/// `name.percentile:value|ms`, and the point times, rate, moving average,
+/// overflows, extreme values, and summary statistics are sent as gauges, eg:
+/// `name.maximum_time:value|g`, `name.maximum:value|g`, and `name.mean:value|g`.
+/// Values are sent in the units they were recorded in. Lines are batched into
+/// packets no larger than the configured packet size.
+///
+/// With DogStatsD tags enabled, the labels of each `Reading` are appended to
+/// its line as tags, eg: `requests:10|c|#backend:cache01`.
+pub struct StatsdSink {
+    socket: UdpSocket,
+    packet_size: usize,
+    // the previous value of each counter, by name and sorted labels
+    counters: HashMap<(String, Vec<(String, String)>), u64>,
+    tags: bool,
+}
+
+impl StatsdSink {
+    /// Create a new `StatsdSink` which sends to the StatsD server at `addr`.
+    /// Each address which `addr` resolves to is tried in turn, from a local
+    /// socket of the same family, IPv4 or IPv6, and the error of the last is
+    /// returned if none can be connected to.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, io::Error> {
+        let mut error = None;
+        for addr in addr.to_socket_addrs()? {
+            let local = if addr.is_ipv6() {
+                "[::]:0"
+            } else {
+                "0.0.0.0:0"
+            };
+            let socket = UdpSocket::bind(local).and_then(|socket| {
+                socket.connect(addr)?;
+                Ok(socket)
+            });
+            match socket {
+                Ok(socket) => {
+                    return Ok(Self {
+                        socket,
+                        packet_size: DEFAULT_PACKET_SIZE,
+                        counters: HashMap::new(),
+                        tags: false,
+                    })
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    /// Sets whether the labels of each `Reading` are sent as DogStatsD tags
+    pub fn set_dogstatsd_tags(&mut self, enabled: bool) {
+        self.tags = enabled;
+    }
+
+    /// Sends the `Reading`s of each of the `Channel`s. Unlike `emit()`, the
+    /// `Source` of each `Channel` is known, so the `Counter` output of a
+    /// `Gauge` channel is sent as the latest reading, `name:value|g`, instead
+    /// of as an increase. A negative reading of a `SignedGauge` channel is
+    /// preceded by `name:0|g`, as StatsD treats a signed gauge value as a
+    /// change to the gauge.
+    pub fn emit_channels<T>(&mut self, channels: &[Arc<Channel<T>>])
+    where
+        T: Counter + Unsigned + 'static,
+        <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+        u64: From<<T as AtomicPrimitive>::Primitive>,
+    {
+        let mut lines = Vec::new();
+        for channel in channels {
+            let gauge =
+                channel.source() == Source::Gauge || channel.source() == Source::SignedGauge;
+            for reading in channel.readings() {
+                lines.push(self.line(&reading, gauge));
+            }
+        }
+        self.send_lines(lines);
+    }
+
+    /// Set the maximum size of each packet in bytes. A single line which is
+    /// larger than this is sent in a packet of its own.
+    pub fn set_packet_size(&mut self, bytes: usize) {
+        self.packet_size = bytes;
+    }
+
+    fn line(&mut self, reading: &Reading, gauge: bool) -> String {
+        let label = reading.label();
+        let value = reading.value();
+        let mut labels: Vec<(String, String)> = reading.labels().into_iter().collect();
+        labels.sort();
+        let line = match reading.output() {
+            Output::Counter if gauge => signed_gauge(&label, reading),
+            Output::Counter => {
+                let previous = self
+                    .counters
+                    .insert((label.clone(), labels.clone()), value)
+                    .unwrap_or(0);
+                // a counter which went backwards was reset, so all of its
+                // value is new
+                let increase = value.checked_sub(previous).unwrap_or(value);
+                format!("{}:{}|c", label, increase)
+            }
+            Output::Percentile(percentile) => format!("{}.{}:{}|ms", label, percentile, value),
+            Output::Cardinality => format!("{}.cardinality:{}|g", label, value),
+            Output::CounterInterval => format!("{}.counter_interval:{}|g", label, value),
+            Output::Ewma => format!("{}.ewma:{}|g", label, value),
+            Output::LastWriteTime => format!("{}.last_write_time:{}|g", label, value),
+            Output::MaxPointTime => format!("{}.maximum_time:{}|g", label, value),
+            Output::MaxValue => signed_gauge(&format!("{}.maximum", label), reading),
+            Output::Mean => format!("{}.mean:{}|g", label, value),
+            Output::MinPointTime => format!("{}.minimum_time:{}|g", label, value),
+            Output::MinValue => signed_gauge(&format!("{}.minimum", label), reading),
+            Output::OutOfRange => format!("{}.out_of_range:{}|g", label, value),
+            Output::Overflows => format!("{}.overflows:{}|g", label, value),
+            Output::Rate => format!("{}.rate:{}|g", label, value),
+            Output::StdDev => format!("{}.std_dev:{}|g", label, value),
+        };
+        if !self.tags || labels.is_empty() {
+            return line;
+        }
+        let tags: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}:{}", key, value))
+            .collect();
+        // a negative gauge is sent as two lines which are each tagged
+        line.split('\n')
+            .map(|line| format!("{}|#{}", line, tags.join(",")))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // batches the lines into packets of at most the packet size
+    fn send_lines(&self, lines: Vec<String>) {
+        let mut packet = String::new();
+        for line in lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > self.packet_size {
+                self.send(&packet);
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        if !packet.is_empty() {
+            self.send(&packet);
+        }
+    }
+
+    fn send(&self, packet: &str) {
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            error!("failed to send statsd packet: {}", e);
+        }
+    }
+}
+
+impl ReadingSink for StatsdSink {
+    fn emit(&mut self, readings: &[Reading]) {
+        let lines = readings
+            .iter()
+            .map(|reading| self.line(reading, false))
+            .collect();
+        self.send_lines(lines);
+    }
+}
+
+// a negative gauge value would be taken as a decrement, so the gauge is first
+// set to zero
+fn signed_gauge(name: &str, reading: &Reading) -> String {
+    match reading.signed_value() {
+        Some(signed) if signed < 0 => format!("{}:0|g\n{}:{}|g", name, name, signed),
+        _ => format!("{}:{}|g", name, reading.value()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn receive(listener: &UdpSocket) -> Vec<String> {
+        let mut packets = Vec::new();
+        let mut buf = [0; 1500];
+        while let Ok(len) = listener.recv(&mut buf) {
+            packets.push(String::from_utf8(buf[..len].to_vec()).unwrap());
+        }
+        packets
+    }
+
+    #[test]
+    fn emit() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut sink = StatsdSink::new(listener.local_addr().unwrap()).unwrap();
+        sink.set_packet_size(64);
+
+        let readings = vec![
+            Reading::new("requests".to_string(), Output::Counter, 10),
+            Reading::new(
+                "latency".to_string(),
+                Output::Percentile(Percentile::p50),
+                100,
+            ),
+            Reading::new(
+                "latency".to_string(),
+                Output::Percentile(Percentile::p999),
+                1500,
+            ),
+            Reading::new("latency".to_string(), Output::MaxPointTime, 42),
+            Reading::new("latency".to_string(), Output::MinPointTime, 7),
+        ];
+        sink.emit(&readings);
+        let packets = receive(&listener);
+        assert_eq!(
+            packets,
+            vec![
+                "requests:10|c\nlatency.p50:100|ms\nlatency.p999:1500|ms".to_string(),
+                "latency.maximum_time:42|g\nlatency.minimum_time:7|g".to_string(),
+            ]
+        );
+        assert!(packets.iter().all(|p| p.len() <= 64));
+
+        // counters are sent as the increase since the previous emit
+        sink.emit(&[Reading::new("requests".to_string(), Output::Counter, 25)]);
+        assert_eq!(receive(&listener), vec!["requests:15|c".to_string()]);
+
+        // a reset counter sends its new value
+        sink.emit(&[Reading::new("requests".to_string(), Output::Counter, 4)]);
+        assert_eq!(receive(&listener), vec!["requests:4|c".to_string()]);
+    }
+
+    #[test]
+    fn labeled_counters() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut sink = StatsdSink::new(listener.local_addr().unwrap()).unwrap();
+        sink.set_dogstatsd_tags(true);
+
+        let reading = |backend: &str, value| {
+            let mut labels = HashMap::new();
+            labels.insert("backend".to_string(), backend.to_string());
+            Reading::new("requests".to_string(), Output::Counter, value).with_labels(labels)
+        };
+
+        // each labeled counter tracks its own previous value
+        sink.emit(&[reading("a", 10), reading("b", 100)]);
+        sink.emit(&[reading("a", 15), reading("b", 130)]);
+        let lines: Vec<String> = receive(&listener)
+            .iter()
+            .flat_map(|packet| packet.lines().map(str::to_string).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "requests:10|c|#backend:a".to_string(),
+                "requests:100|c|#backend:b".to_string(),
+                "requests:5|c|#backend:a".to_string(),
+                "requests:30|c|#backend:b".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_channels() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut sink = StatsdSink::new(listener.local_addr().unwrap()).unwrap();
+        sink.set_dogstatsd_tags(true);
+
+        let gauge = Channel::<AtomicU64>::new("connections".to_string(), Source::Gauge, None);
+        gauge.add_output(Output::Counter);
+        gauge
+            .record(Measurement::Gauge { value: 12, time: 1 })
+            .unwrap();
+        let histogram = Histogram::new(1_000_000, 3, None, None);
+        let latency =
+            Channel::<AtomicU64>::new("latency".to_string(), Source::TimeInterval, Some(histogram));
+        latency.add_output(Output::Counter);
+        latency.add_output(Output::Percentile(Percentile::p99));
+        latency.set_label("protocol".to_string(), "redis".to_string());
+        latency.set_label("backend".to_string(), "cache01".to_string());
+        latency
+            .record(Measurement::Timing {
+                duration: 250,
+                time: 1,
+            })
+            .unwrap();
+
+        let lag = Channel::<AtomicU64>::new("lag".to_string(), Source::SignedGauge, None);
+        lag.add_output(Output::Counter);
+        lag.set_label("region".to_string(), "west".to_string());
+        lag.record(Measurement::SignedGauge { value: -5, time: 1 })
+            .unwrap();
+
+        sink.emit_channels(&[Arc::new(gauge), Arc::new(latency), Arc::new(lag)]);
+        let mut lines: Vec<String> = receive(&listener)
+            .iter()
+            .flat_map(|packet| packet.lines().map(str::to_string).collect::<Vec<_>>())
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "connections:12|g".to_string(),
+                "lag:-5|g|#region:west".to_string(),
+                "lag:0|g|#region:west".to_string(),
+                "latency.p99:250|ms|#backend:cache01,protocol:redis".to_string(),
+                "latency:1|c|#backend:cache01,protocol:redis".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv6() {
+        // hosts without IPv6 can't run this test
+        let listener = match UdpSocket::bind("[::1]:0") {
+            Ok(listener) => listener,
+            Err(_) => return,
+        };
+        listener
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut sink = StatsdSink::new(listener.local_addr().unwrap()).unwrap();
+        sink.emit(&[Reading::new("requests".to_string(), Output::Counter, 10)]);
+        assert_eq!(receive(&listener), vec!["requests:10|c".to_string()]);
+        assert!(StatsdSink::new(&[][..] as &[std::net::SocketAddr]).is_err());
+    }
+
+    #[test]
+    fn send_error() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut sink = StatsdSink::new(listener.local_addr().unwrap()).unwrap();
+        drop(listener);
+        // sending to a closed port must not panic
+        for _ in 0..3 {
+            sink.emit(&[Reading::new("requests".to_string(), Output::Counter, 1)]);
+        }
+    }
+}
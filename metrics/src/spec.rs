@@ -0,0 +1,187 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use serde_derive::*;
+
+/// The declaration of a `Channel` in a configuration file, see
+/// `build_channel`. In TOML, a channel with a histogram looks like:
+///
+/// ```toml
+/// name = "request/latency"
+/// source = "time_interval"
+/// outputs = ["counter", "p50", "p99"]
+///
+/// [histogram]
+/// max = 1_000_000_000
+/// precision = 3
+/// ```
+///
+/// The source is the snake case name of a `Source`, eg: `signed_gauge`, and
+/// the outputs are parsed with `Output::from_str`. The precision of the
+/// histogram defaults to 3.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelSpec {
+    name: String,
+    source: String,
+    #[serde(default)]
+    outputs: Vec<String>,
+    histogram: Option<HistogramSpec>,
+}
+
+/// The range and precision of the histogram of a `ChannelSpec`, see
+/// `Histogram::new`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistogramSpec {
+    max: u64,
+    #[serde(default = "default_precision")]
+    precision: u32,
+}
+
+fn default_precision() -> u32 {
+    3
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpecError {
+    // the source does not name a Source
+    UnknownSource(String),
+    // the outputs which could not be parsed, in the order given
+    UnknownOutputs(Vec<String>),
+}
+
+/// Constructs the `Channel` declared by the `ChannelSpec`, along with its
+/// histogram and outputs. Returns an error naming the source or outputs if
+/// they are not recognized.
+pub fn build_channel<T>(spec: &ChannelSpec) -> Result<Channel<T>, SpecError>
+where
+    T: 'static + Counter + Unsigned,
+    <T as AtomicPrimitive>::Primitive: Default + PartialEq + Copy + Saturating + From<u8>,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    let source = parse_source(&spec.source)?;
+    let histogram = spec
+        .histogram
+        .as_ref()
+        .map(|histogram| Histogram::new(histogram.max, histogram.precision, None, None));
+    let channel = Channel::new(spec.name.clone(), source, histogram);
+    channel
+        .configure_outputs(&spec.outputs.join(","))
+        .map_err(|OutputSpecError::Unrecognized(outputs)| SpecError::UnknownOutputs(outputs))?;
+    Ok(channel)
+}
+
+fn parse_source(source: &str) -> Result<Source, SpecError> {
+    let source = match source {
+        "counter" => Source::Counter,
+        "distribution" => Source::Distribution,
+        "distribution_f64" => Source::DistributionF64,
+        "gauge" => Source::Gauge,
+        "set" => Source::Set,
+        "signed_gauge" => Source::SignedGauge,
+        "time_interval" => Source::TimeInterval,
+        _ => return Err(SpecError::UnknownSource(source.to_string())),
+    };
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        channel: Vec<ChannelSpec>,
+    }
+
+    #[test]
+    fn build_channels() {
+        let config: Config = toml::from_str(
+            r#"
+            [[channel]]
+            name = "request/latency"
+            source = "time_interval"
+            outputs = ["counter", "p50", "p99"]
+
+            [channel.histogram]
+            max = 1_000_000
+
+            [[channel]]
+            name = "connections"
+            source = "gauge"
+            "#,
+        )
+        .unwrap();
+        let channels: Vec<Channel<AtomicU64>> = config
+            .channel
+            .iter()
+            .map(|spec| build_channel(spec).unwrap())
+            .collect();
+
+        let latency = &channels[0];
+        assert_eq!(latency.name(), "request/latency");
+        assert_eq!(latency.source(), Source::TimeInterval);
+        let mut outputs: Vec<String> = latency
+            .outputs()
+            .iter()
+            .map(|output| output.to_string())
+            .collect();
+        outputs.sort();
+        assert_eq!(outputs, vec!["counter", "p50", "p99"]);
+        latency
+            .record(Measurement::Timing {
+                duration: 500,
+                time: 1,
+            })
+            .unwrap();
+        assert_eq!(latency.percentile(0.5), Some(500));
+
+        assert_eq!(channels[1].source(), Source::Gauge);
+        assert!(channels[1].outputs().is_empty());
+        assert_eq!(channels[1].percentile(0.5), None);
+    }
+
+    #[test]
+    fn errors() {
+        let spec: ChannelSpec = toml::from_str(
+            r#"
+            name = "test"
+            source = "histogram"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            build_channel::<AtomicU64>(&spec).err(),
+            Some(SpecError::UnknownSource("histogram".to_string()))
+        );
+        let spec: ChannelSpec = toml::from_str(
+            r#"
+            name = "test"
+            source = "counter"
+            outputs = ["counter", "p101", "median"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            build_channel::<AtomicU64>(&spec).err(),
+            Some(SpecError::UnknownOutputs(vec![
+                "p101".to_string(),
+                "median".to_string()
+            ]))
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//  Copyright 2019 Twitter, Inc
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::*;
+
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Starts a thread which, each time the `interval` elapses, takes the
+/// `Reading`s of the `Channel`s and then latches them, sending the batch to
+/// the returned `Receiver`. This allows an exporter, eg: an async task, to
+/// receive the readings of each interval with `try_recv()` without blocking.
+///
+/// The batches are sent over an unbounded channel, so the thread never waits
+/// on the exporter and recording into the channels is unaffected by a slow
+/// consumer. The thread exits after the `Receiver` is dropped.
+pub fn stream_readings<T>(
+    channels: Vec<Arc<Channel<T>>>,
+    interval: Duration,
+) -> io::Result<Receiver<Vec<Reading>>>
+where
+    T: 'static + Counter + Unsigned + Send + Sync,
+    <T as AtomicPrimitive>::Primitive:
+        Default + PartialEq + Copy + Saturating + From<u8> + Send + Sync,
+    u64: From<<T as AtomicPrimitive>::Primitive>,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("metrics-stream".to_string())
+        .spawn(move || loop {
+            thread::sleep(interval);
+            let mut readings = Vec::new();
+            for channel in &channels {
+                readings.extend(channel.readings());
+                channel.latch();
+            }
+            if sender.send(readings).is_err() {
+                break;
+            }
+        })?;
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the parts of a reading which identify it and its value
+    fn summary(readings: &[Reading]) -> Vec<(String, String, u64)> {
+        readings
+            .iter()
+            .map(|reading| {
+                (
+                    reading.label(),
+                    reading.output().to_string(),
+                    reading.value(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stream_readings() {
+        let requests = Arc::new(Channel::<AtomicU64>::new(
+            "requests".to_string(),
+            Source::Counter,
+            None,
+        ));
+        requests.add_output(Output::Counter);
+        let latency = Arc::new(Channel::<AtomicU64>::new(
+            "latency".to_string(),
+            Source::Distribution,
+            Some(Histogram::new(1_000_000, 3, None, None)),
+        ));
+        latency.add_output(Output::Percentile(Percentile::p50));
+        requests
+            .record(Measurement::Counter { value: 7, time: 1 })
+            .unwrap();
+        latency
+            .record(Measurement::Distribution {
+                value: 100,
+                count: 3,
+                time: 1,
+            })
+            .unwrap();
+        let mut expected = requests.readings();
+        expected.extend(latency.readings());
+
+        let receiver = super::stream_readings(
+            vec![requests.clone(), latency.clone()],
+            Duration::from_millis(10),
+        )
+        .unwrap();
+        let batch = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(summary(&batch), summary(&expected));
+        // the histogram was latched once the readings were taken
+        let batch = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            summary(&batch),
+            vec![("requests".to_string(), "counter".to_string(), 7)]
+        );
+        assert_eq!(latency.percentile(0.5), None);
+    }
+}
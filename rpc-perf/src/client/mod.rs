@@ -257,6 +257,7 @@ pub trait Client: Send {
             trace!("socket opened: client {} {:?}", self.id(), token);
             self.session_mut(token)
                 .set_timestamp(Some(time::precise_time_ns()));
+            self.session_mut(token).set_connected(None);
             self.set_session_state(token, State::Connecting);
             // TODO: use a configurable timeout value w/ policy here
             self.set_timeout(token, self.common().connect_timeout());
@@ -312,7 +313,9 @@ pub trait Client: Send {
                 State::Established => {
                     trace!("connection established {:?}", token);
                     if let Some(t0) = self.session(token).timestamp() {
-                        self.stat_interval(Stat::ConnectionsOpened, t0, time::precise_time_ns());
+                        let t1 = time::precise_time_ns();
+                        self.stat_interval(Stat::ConnectionsConnect, t0, t1);
+                        self.stat_interval(Stat::ConnectionsOpened, t0, t1);
                     }
                     self.do_established(token);
                 }
@@ -320,6 +323,11 @@ pub trait Client: Send {
                     self.do_close(token);
                 }
                 State::Negotiating => {
+                    let t1 = time::precise_time_ns();
+                    if let Some(t0) = self.session(token).timestamp() {
+                        self.stat_interval(Stat::ConnectionsConnect, t0, t1);
+                    }
+                    self.session_mut(token).set_connected(Some(t1));
                     self.do_negotiating(token);
                 }
                 _ => {
@@ -350,8 +358,12 @@ pub trait Client: Send {
             State::Negotiating => match target {
                 State::Established => {
                     debug!("session established");
+                    let t1 = time::precise_time_ns();
+                    if let Some(connected) = self.session(token).connected() {
+                        self.stat_interval(Stat::ConnectionsHandshake, connected, t1);
+                    }
                     if let Some(t0) = self.session(token).timestamp() {
-                        self.stat_interval(Stat::ConnectionsOpened, t0, time::precise_time_ns());
+                        self.stat_interval(Stat::ConnectionsOpened, t0, t1);
                     }
                     self.do_established(token);
                 }
@@ -469,10 +481,16 @@ pub trait Client: Send {
             self.stat_increment(Stat::ConnectionsServerClosed);
             self.set_state(token, State::Closed);
         } else {
-            let _result = self.session_mut(token).read_to();
+            let result = self.session_mut(token).read_to();
             let buf = self.session(token).read_buf();
             trace!("buffer: {:?}", buf);
             let len = buf.len();
+            // the buffer holds only this read, so it began the response
+            if len > 0 && result.ok() == Some(len) {
+                if let Some(t0) = self.session(token).timestamp() {
+                    self.stat_interval(Stat::ResponsesFirstByte, t0, time::precise_time_ns());
+                }
+            }
             match len {
                 0 => {
                     trace!("EOF on read");
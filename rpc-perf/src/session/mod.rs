@@ -43,6 +43,7 @@ pub enum State {
 pub struct Common {
     state: State,
     timestamp: Option<u64>,
+    connected: Option<u64>,
 }
 
 impl Common {
@@ -51,6 +52,7 @@ impl Common {
         Self {
             state: State::Closed,
             timestamp: None,
+            connected: None,
         }
     }
 
@@ -64,6 +66,17 @@ impl Common {
         self.timestamp = timestamp;
     }
 
+    /// Returns the time the connection was established, before any
+    /// negotiation
+    pub fn connected(&self) -> Option<u64> {
+        self.connected
+    }
+
+    /// Sets the time the connection was established
+    pub fn set_connected(&mut self, connected: Option<u64>) {
+        self.connected = connected;
+    }
+
     /// Gets the last set `State`
     pub fn state(&self) -> State {
         self.state
@@ -141,6 +154,17 @@ pub trait Session: Read + Write {
         self.common_mut().set_timestamp(timestamp);
     }
 
+    /// Returns the time the connection was established, before any
+    /// negotiation
+    fn connected(&self) -> Option<u64> {
+        self.common().connected()
+    }
+
+    /// Sets the time the connection was established
+    fn set_connected(&mut self, connected: Option<u64>) {
+        self.common_mut().set_connected(connected);
+    }
+
     // event loop registration
 
     /// Register the `Session` with an event loop
@@ -48,6 +48,17 @@ pub fn register_stats(recorder: &SimpleRecorder) {
     recorder.add_counter_channel(Stat::ResponsesError);
     recorder.add_counter_channel(Stat::ResponsesHit);
     recorder.add_counter_channel(Stat::ResponsesMiss);
+    register_timing_stats(recorder);
+}
+
+/// Registers the channels which break down the latency of each connection
+/// and request into phases, each with its own histogram: the time to
+/// establish the connection, the time to negotiate TLS once connected, and
+/// the time from sending a request to the first byte of its response
+pub fn register_timing_stats(recorder: &SimpleRecorder) {
+    recorder.add_histogram_channel(Stat::ConnectionsConnect, 60_000_000_000, 3);
+    recorder.add_histogram_channel(Stat::ConnectionsHandshake, 60_000_000_000, 3);
+    recorder.add_histogram_channel(Stat::ResponsesFirstByte, 60_000_000_000, 3);
 }
 
 pub struct StandardOut {
@@ -193,6 +204,11 @@ impl StandardOut {
         info!("Hit-rate: {:.2}%", hitrate);
 
         self.display_percentiles(Stat::ConnectionsOpened, "Connect Latency", 1000, "us");
+        self.display_percentiles(Stat::ConnectionsConnect, "TCP Connect", 1000, "us");
+        if self.recorder.counter(Stat::ConnectionsHandshake) > 0 {
+            self.display_percentiles(Stat::ConnectionsHandshake, "TLS Handshake", 1000, "us");
+        }
+        self.display_percentiles(Stat::ResponsesFirstByte, "First Byte", 1000, "us");
         self.display_percentiles(Stat::ResponsesTotal, "Request Latency", 1000, "us");
         self.previous = current;
     }
@@ -212,11 +228,14 @@ pub enum Stat {
     ConnectionsClientClosed,
     ConnectionsServerClosed,
     ConnectionsTimeout,
+    ConnectionsConnect,
+    ConnectionsHandshake,
     ResponsesTotal,
     ResponsesOk,
     ResponsesError,
     ResponsesHit,
     ResponsesMiss,
+    ResponsesFirstByte,
     CommandsGet,
     CommandsSet,
     KeySize,
@@ -242,11 +261,14 @@ impl ToString for Stat {
             Stat::ConnectionsClientClosed => "connections/closed/client",
             Stat::ConnectionsServerClosed => "connections/closed/server",
             Stat::ConnectionsTimeout => "connections/timeout",
+            Stat::ConnectionsConnect => "connections/latency/connect",
+            Stat::ConnectionsHandshake => "connections/latency/handshake",
             Stat::ResponsesTotal => "responses/total",
             Stat::ResponsesOk => "responses/ok",
             Stat::ResponsesError => "responses/error",
             Stat::ResponsesHit => "responses/hit",
             Stat::ResponsesMiss => "responses/miss",
+            Stat::ResponsesFirstByte => "responses/latency/first_byte",
         };
         label.to_string()
     }
@@ -394,17 +416,18 @@ impl SimpleRecorder {
     }
 
     pub fn increment<T: ToString>(&self, label: T) {
-        self.inner.record(
+        let _ = self.inner.record(
             label.to_string(),
             Measurement::Increment {
                 time: time::precise_time_ns(),
                 count: 1,
             },
-        )
+        );
     }
 
     pub fn time_interval<T: ToString>(&self, label: T, start: u64, stop: u64) {
-        self.inner
+        let _ = self
+            .inner
             .record(label.to_string(), Measurement::TimeInterval { start, stop });
     }
 
@@ -415,7 +438,7 @@ impl SimpleRecorder {
     }
 
     pub fn distribution<T: ToString>(&self, label: T, value: u64) {
-        self.inner.record(
+        let _ = self.inner.record(
             label.to_string(),
             Measurement::Distribution {
                 time: time::precise_time_ns(),
@@ -473,3 +496,47 @@ impl SimpleRecorder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_stats() {
+        let metrics = Metrics::new();
+        let recorder = SimpleRecorder {
+            inner: metrics.recorder(),
+            heatmap: None,
+        };
+        register_timing_stats(&recorder);
+        assert_eq!(recorder.percentile(Stat::ConnectionsConnect, 0.5), None);
+
+        // each phase of 100 connections and requests, in nanoseconds
+        for i in 1..=100 {
+            recorder.time_interval(Stat::ConnectionsConnect, 1_000, 1_000 + i * 1_000);
+            recorder.time_interval(Stat::ConnectionsHandshake, 1_000, 1_000 + i * 10_000);
+            recorder.time_interval(Stat::ResponsesFirstByte, 1_000, 1_000 + i * 100);
+        }
+        recorder.time_interval(Stat::ConnectionsHandshake, 1_000, 2_000);
+
+        assert_eq!(recorder.counter(Stat::ConnectionsConnect), 100);
+        assert_eq!(recorder.counter(Stat::ConnectionsHandshake), 101);
+        assert_eq!(recorder.counter(Stat::ResponsesFirstByte), 100);
+        assert_eq!(
+            recorder.percentile(Stat::ConnectionsConnect, 0.5),
+            Some(50_099)
+        );
+        assert_eq!(
+            recorder.percentile(Stat::ConnectionsHandshake, 0.5),
+            Some(500_999)
+        );
+        assert_eq!(
+            recorder.percentile(Stat::ResponsesFirstByte, 0.5),
+            Some(5_009)
+        );
+        assert_eq!(
+            recorder.percentile(Stat::ResponsesFirstByte, 1.0),
+            Some(10_099)
+        );
+    }
+}